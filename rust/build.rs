@@ -11,6 +11,11 @@ fn main() {
         // OS-exclusive features
         file_flags: { any(target_os = "openbsd", target_os = "netbsd", target_os = "freebsd",
                     target_os = "dragonfly", target_os = "macos", target_os = "ios") },
-        birthtime: { any(target_os = "freebsd", target_os = "ios", target_os = "macos", target_os = "netbsd", target_os = "openbsd") }
+        birthtime: { any(target_os = "freebsd", target_os = "ios", target_os = "macos", target_os = "netbsd", target_os = "openbsd") },
+        // Flags restricting the modifications of a file, set with chflags or the FS_IOC_SETFLAGS ioctl
+        inode_flags: { any(file_flags, target_os = "linux") },
+        seek_hole: { any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly",
+                    target_os = "illumos", target_os = "solaris") },
+        statx: { all(target_os = "linux", target_env = "gnu") }
     }
 }
@@ -57,6 +57,10 @@ pub struct SettingsConfig {
     /// Test cases that are expected to fail
     #[serde(default)]
     pub expected_failures: HashSet<String>,
+    /// Group ID given to new entries outside of set-group-ID directories.
+    /// It is detected from the mount options when absent.
+    #[serde(default)]
+    pub group_inheritance: Option<GroupInheritance>,
 }
 
 impl Default for SettingsConfig {
@@ -65,6 +69,7 @@ impl Default for SettingsConfig {
             naptime: default_naptime(),
             allow_remount: false,
             expected_failures: Default::default(),
+            group_inheritance: None,
         }
     }
 }
@@ -73,6 +78,88 @@ const fn default_naptime() -> f64 {
     1.0
 }
 
+/// Group ID given by the file system to a new entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupInheritance {
+    /// The group ID of the parent directory (BSD semantics, `grpid` mount option on Linux).
+    Bsd,
+    /// The effective group ID of the process, unless the parent directory has the S_ISGID bit set
+    /// (System V semantics, `nogrpid` mount option on Linux).
+    SystemV,
+}
+
+/// Configuration for the concurrent stress tests, which only run when this section is present.
+/// Please see the book for more details.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StressConfig {
+    /// Seed of the random operations, a random one is picked when absent.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Duration of each stress test (in seconds).
+    #[serde(default = "default_stress_duration")]
+    pub duration: f64,
+    /// Number of worker threads.
+    #[serde(default = "default_stress_threads")]
+    pub threads: usize,
+}
+
+impl Default for StressConfig {
+    fn default() -> Self {
+        StressConfig {
+            seed: None,
+            duration: default_stress_duration(),
+            threads: default_stress_threads(),
+        }
+    }
+}
+
+const fn default_stress_duration() -> f64 {
+    10.0
+}
+
+const fn default_stress_threads() -> usize {
+    4
+}
+
+/// Configuration for the mount option tests, which only run when this section is present.
+/// Please see the book for more details.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MountConfig {
+    /// Type of the file system of the scratch mounts.
+    #[serde(default = "default_mount_fs_type")]
+    pub fs_type: String,
+    /// Device of the scratch mounts, which is not required by tmpfs.
+    #[serde(default)]
+    pub device: Option<PathBuf>,
+    /// File-system specific options of the scratch mounts.
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+impl Default for MountConfig {
+    fn default() -> Self {
+        MountConfig {
+            fs_type: default_mount_fs_type(),
+            device: None,
+            data: None,
+        }
+    }
+}
+
+fn default_mount_fs_type() -> String {
+    String::from("tmpfs")
+}
+
+/// Configuration for the overlayfs copy-up tests, which only run when this section is present.
+/// Please see the book for more details.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct OverlayConfig {
+    /// Additional options of the overlay mounts, such as `redirect_dir=on` or `index=on`.
+    #[serde(default)]
+    pub options: Option<String>,
+}
+
 /// Configuration for the test suite.
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Config {
@@ -85,6 +172,15 @@ pub struct Config {
     /// Dummy authentication configuration.
     #[serde(default)]
     pub dummy_auth: DummyAuthConfig,
+    /// Concurrent stress tests configuration.
+    #[serde(default)]
+    pub stress: Option<StressConfig>,
+    /// Mount option tests configuration.
+    #[serde(default)]
+    pub mount: Option<MountConfig>,
+    /// Overlayfs copy-up tests configuration.
+    #[serde(default)]
+    pub overlay: Option<OverlayConfig>,
 }
 
 impl Config {
@@ -14,9 +14,13 @@ use nix::{
     },
 };
 
-use rand::distributions::{Alphanumeric, DistString};
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    rngs::StdRng,
+    SeedableRng,
+};
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
     fs::create_dir_all,
     ops::{Deref, DerefMut},
     os::fd::{AsRawFd, OwnedFd},
@@ -28,10 +32,31 @@ use std::{
 use strum_macros::EnumIter;
 
 use crate::{
-    config::{Config, DummyAuthEntry, FeaturesConfig},
+    config::{Config, DummyAuthEntry, FeaturesConfig, MountConfig, OverlayConfig, StressConfig},
+    trace::{self, Call},
     utils::{chmod, lchmod, open, symlink},
 };
 
+/// Which test directories are kept after the tests, for inspection.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum KeepPolicy {
+    #[default]
+    Never,
+    Failed,
+    Always,
+}
+
+impl KeepPolicy {
+    /// Return whether the directory of a test is kept, according to its outcome.
+    pub fn keeps(&self, failed: bool) -> bool {
+        match self {
+            KeepPolicy::Never => false,
+            KeepPolicy::Failed => failed,
+            KeepPolicy::Always => true,
+        }
+    }
+}
+
 /// File type, mainly used with [TestContext::create] and parameterized tests.
 #[derive(Debug, Clone, Eq, PartialEq, EnumIter)]
 pub enum FileType {
@@ -52,6 +77,21 @@ impl FileType {
 
 const NUM_RAND_CHARS: usize = 32;
 
+thread_local! {
+    /// Generator of the random names, which can be seeded to generate the same names again.
+    static NAME_RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Make the names generated afterwards by the test contexts of this thread only depend on `seed`.
+pub fn seed_names(seed: u64) {
+    NAME_RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Return a random name of `len` alphanumeric characters.
+fn random_name(len: usize) -> String {
+    NAME_RNG.with(|rng| Alphanumeric.sample_string(&mut *rng.borrow_mut(), len))
+}
+
 /// Auth entries which are composed of a [`User`] and its associated [`Group`].
 /// Allows to retrieve the auth entries.
 #[derive(Debug)]
@@ -116,8 +156,16 @@ pub struct TestContext<'a> {
     temp_dir: &'a Path,
     /// Features configuration, used to determine which features are enabled.
     features_config: &'a FeaturesConfig,
+    /// Stress tests configuration, if enabled.
+    stress_config: Option<&'a StressConfig>,
+    /// Mount option tests configuration, if enabled.
+    mount_config: Option<&'a MountConfig>,
+    /// Overlayfs copy-up tests configuration, if enabled.
+    overlay_config: Option<&'a OverlayConfig>,
     /// Auth entries which are composed of a [`User`] and its associated [`Group`].
     auth_entries: DummyAuthEntries<'a>,
+    /// Directories which are left as is after the test, instead of being prepared for removal.
+    keep: KeepPolicy,
     /// Jail, used to isolate the test environment on FreeBSD.
     #[cfg(target_os = "freebsd")]
     jail: Option<jail::RunningJail>,
@@ -194,6 +242,7 @@ impl<'a> SerializedTestContext<'a> {
             .unwrap_or_else(|| std::slice::from_ref(&user.gid))
             .to_vec();
         setgroups(&groups).unwrap();
+        trace::set_groups(&groups);
 
         setegid(groups[0]).unwrap();
         seteuid(user.uid).unwrap();
@@ -203,6 +252,7 @@ impl<'a> SerializedTestContext<'a> {
         seteuid(original_euid).unwrap();
         setegid(original_egid).unwrap();
         setgroups(&original_groups).unwrap();
+        trace::set_groups(&original_groups);
 
         if let Err(e) = res {
             resume_unwind(e)
@@ -215,10 +265,29 @@ impl<'a> SerializedTestContext<'a> {
         F: FnOnce(),
     {
         let previous_mask = umask(Mode::from_bits_truncate(mask));
+        trace::set_umask(Mode::from_bits_truncate(mask));
 
         let res = catch_unwind(AssertUnwindSafe(f));
 
         umask(previous_mask);
+        trace::set_umask(previous_mask);
+
+        if let Err(e) = res {
+            resume_unwind(e)
+        }
+    }
+
+    /// Execute the function with `path` as the current working directory.
+    pub fn with_cwd<P: AsRef<Path>, F>(&self, path: P, f: F)
+    where
+        F: FnOnce(),
+    {
+        let previous_cwd = std::env::current_dir().unwrap();
+        std::env::set_current_dir(path).unwrap();
+
+        let res = catch_unwind(AssertUnwindSafe(f));
+
+        std::env::set_current_dir(previous_cwd).unwrap();
 
         if let Err(e) = res {
             resume_unwind(e)
@@ -240,7 +309,11 @@ impl<'a> TestContext<'a> {
             naptime,
             temp_dir,
             features_config: &config.features,
+            stress_config: config.stress.as_ref(),
+            mount_config: config.mount.as_ref(),
+            overlay_config: config.overlay.as_ref(),
             auth_entries: DummyAuthEntries::new(entries),
+            keep: KeepPolicy::Never,
             #[cfg(target_os = "freebsd")]
             jail: None,
         }
@@ -255,10 +328,24 @@ impl<'a> TestContext<'a> {
         self.features_config
     }
 
+    /// Return the stress tests configuration, if enabled.
+    pub fn stress_config(&self) -> Option<&StressConfig> {
+        self.stress_config
+    }
+
+    /// Return the mount option tests configuration, if enabled.
+    pub fn mount_config(&self) -> Option<&MountConfig> {
+        self.mount_config
+    }
+
+    /// Return the overlayfs copy-up tests configuration, if enabled.
+    pub fn overlay_config(&self) -> Option<&OverlayConfig> {
+        self.overlay_config
+    }
+
     /// Generate a random path.
     pub fn gen_path(&self) -> PathBuf {
-        self.base_path()
-            .join(Alphanumeric.sample_string(&mut rand::thread_rng(), NUM_RAND_CHARS))
+        self.base_path().join(random_name(NUM_RAND_CHARS))
     }
 
     /// Create a regular file and open it.
@@ -289,9 +376,7 @@ impl<'a> TestContext<'a> {
         let max_name_len =
             pathconf(self.base_path(), nix::unistd::PathconfVar::NAME_MAX)?.unwrap() as usize;
 
-        let file = self
-            .new_file(f_type)
-            .name(Alphanumeric.sample_string(&mut rand::thread_rng(), max_name_len));
+        let file = self.new_file(f_type).name(random_name(max_name_len));
 
         file.create()
     }
@@ -311,7 +396,7 @@ impl<'a> TestContext<'a> {
         let remaining_chars = max_path_len - initial_path_len;
 
         let parts: Vec<_> = (0..remaining_chars / component_len)
-            .map(|_| Alphanumeric.sample_string(&mut rand::thread_rng(), component_len - 1))
+            .map(|_| random_name(component_len - 1))
             .collect();
 
         let remaining_chars = remaining_chars % component_len - 1;
@@ -320,7 +405,7 @@ impl<'a> TestContext<'a> {
 
             create_dir_all(&path).unwrap();
 
-            path.push(Alphanumeric.sample_string(&mut rand::thread_rng(), remaining_chars));
+            path.push(random_name(remaining_chars));
         } else {
             path.extend(&parts[..parts.len() - 1]);
 
@@ -356,6 +441,12 @@ impl<'a> TestContext<'a> {
         thread::sleep(self.naptime)
     }
 
+    /// Leave the test directory as is after the test according to `keep`,
+    /// instead of removing the permissions and flags which prevent its removal.
+    pub fn set_keep_policy(&mut self, keep: KeepPolicy) {
+        self.keep = keep
+    }
+
     /// Set this Context's jail, so it will be destroyed during teardown.
     #[cfg(target_os = "freebsd")]
     pub fn set_jail(&mut self, jail: jail::RunningJail) {
@@ -367,42 +458,72 @@ impl<'a> TestContext<'a> {
 // search or write permission is denied, or a flag denying delete for a file.
 impl<'a> Drop for TestContext<'a> {
     fn drop(&mut self) {
-        let iter = walkdir::WalkDir::new(self.base_path()).into_iter();
-        for entry in iter {
-            let entry = match entry {
-                Ok(e) => e,
-                _ => continue,
-            };
+        // The cleanup is not part of the test, but the state of a failed test is kept for its log
+        trace::close(thread::panicking());
 
-            if cfg!(lchflags) || entry.file_type().is_dir() {
-                let file_stat = match lstat(entry.path()) {
-                    Ok(s) => s,
-                    _ => continue,
-                };
+        // Shut down any jails
+        #[cfg(target_os = "freebsd")]
+        if let Some(jail) = self.jail.take() {
+            let _ = jail.kill();
+        }
 
-                let mode = Mode::S_IRWXU;
-                if (file_stat.st_mode & mode.bits()) != mode.bits() {
-                    let _ = lchmod(entry.path(), mode);
-                }
+        if self.keep.keeps(thread::panicking()) {
+            return;
+        }
+
+        unlock_tree(self.base_path());
+    }
+}
 
-                // We remove all flags
-                // TODO: Some platforms do not support lchflags, write chflagsat alternative for those (openbsd, macos, ios?)
-                #[cfg(lchflags)]
-                {
-                    use crate::utils::lchflags;
-                    use nix::{libc::fflags_t, sys::stat::FileFlag};
+/// Remove the permissions and flags which prevent the removal of the entries under `base`.
+pub fn unlock_tree(base: &Path) {
+    let iter = walkdir::WalkDir::new(base).into_iter();
+    for entry in iter {
+        let entry = match entry {
+            Ok(e) => e,
+            _ => continue,
+        };
+
+        // Restrictive flags are removed first, as they prevent changing the mode
+        #[cfg(target_os = "linux")]
+        if entry.file_type().is_file() || entry.file_type().is_dir() {
+            use crate::utils::{
+                get_inode_flags,
+                iflags::{FS_APPEND_FL, FS_IMMUTABLE_FL},
+                set_inode_flags,
+            };
 
-                    if file_stat.st_flags != FileFlag::empty().bits() as fflags_t {
-                        let _ = lchflags(entry.path(), FileFlag::empty());
-                    }
+            // Inode flags are only supported on regular files and directories
+            let restrictive = FS_IMMUTABLE_FL | FS_APPEND_FL;
+            if let Ok(flags) = get_inode_flags(entry.path()) {
+                if flags & restrictive != 0 {
+                    let _ = set_inode_flags(entry.path(), flags & !restrictive);
                 }
+            }
+        }
+
+        if cfg!(lchflags) || entry.file_type().is_dir() {
+            let file_stat = match lstat(entry.path()) {
+                Ok(s) => s,
+                _ => continue,
+            };
+
+            // We remove all flags
+            // TODO: Some platforms do not support lchflags, write chflagsat alternative for those (openbsd, macos, ios?)
+            #[cfg(lchflags)]
+            {
+                use crate::utils::lchflags;
+                use nix::{libc::fflags_t, sys::stat::FileFlag};
 
-                // Shut down any jails
-                #[cfg(target_os = "freebsd")]
-                if let Some(jail) = self.jail.take() {
-                    let _ = jail.kill();
+                if file_stat.st_flags != FileFlag::empty().bits() as fflags_t {
+                    let _ = lchflags(entry.path(), FileFlag::empty());
                 }
             }
+
+            let mode = Mode::S_IRWXU;
+            if (file_stat.st_mode & mode.bits()) != mode.bits() {
+                let _ = lchmod(entry.path(), mode);
+            }
         }
     }
 }
@@ -430,8 +551,7 @@ impl FileBuilder {
     /// [`Take`](std::mem::take) and return the path final form.
     fn final_path(&mut self) -> PathBuf {
         if self.random_name {
-            self.path
-                .push(Alphanumeric.sample_string(&mut rand::thread_rng(), NUM_RAND_CHARS))
+            self.path.push(random_name(NUM_RAND_CHARS))
         }
 
         std::mem::take(&mut self.path)
@@ -447,10 +567,44 @@ impl FileBuilder {
 
         match self.file_type {
             FileType::Regular => open(&path, OFlag::O_CREAT, mode).map(drop),
-            FileType::Dir => mkdir(&path, mode),
-            FileType::Fifo => mkfifo(&path, mode),
-            FileType::Block => mknod(&path, SFlag::S_IFBLK, mode, 0),
-            FileType::Char => mknod(&path, SFlag::S_IFCHR, mode, 0),
+            FileType::Dir => {
+                let res = mkdir(&path, mode);
+                trace::record(
+                    || Call::Mkdir {
+                        path: path.clone(),
+                        mode,
+                    },
+                    &res,
+                );
+                res
+            }
+            FileType::Fifo => {
+                let res = mkfifo(&path, mode);
+                trace::record(
+                    || Call::Mkfifo {
+                        path: path.clone(),
+                        mode,
+                    },
+                    &res,
+                );
+                res
+            }
+            FileType::Block | FileType::Char => {
+                let kind = if self.file_type == FileType::Block {
+                    SFlag::S_IFBLK
+                } else {
+                    SFlag::S_IFCHR
+                };
+                let res = mknod(&path, kind, mode, 0);
+                let call = || Call::Mknod {
+                    path: path.clone(),
+                    kind,
+                    mode,
+                    dev: 0,
+                };
+                trace::record(call, &res);
+                res
+            }
             FileType::Socket => {
                 let fd = socket(
                     nix::sys::socket::AddressFamily::Unix,
@@ -459,7 +613,9 @@ impl FileBuilder {
                     None,
                 )?;
                 let sockaddr = UnixAddr::new(&path)?;
-                bind(fd.as_raw_fd(), &sockaddr)?;
+                let res = bind(fd.as_raw_fd(), &sockaddr);
+                trace::record(|| Call::Bind { path: path.clone() }, &res);
+                res?;
                 if let Some(mode) = self.mode {
                     chmod(&path, mode)?;
                 }
@@ -25,12 +25,32 @@ pub enum FileSystemFeature {
     Chflags,
     /// NFSv4 style Access Control Lists are available
     Nfsv4Acls,
+    /// The [`copy_file_range`](https://man7.org/linux/man-pages/man2/copy_file_range.2.html) syscall is available
+    CopyFileRange,
+    /// The [`fallocate`](https://man7.org/linux/man-pages/man2/fallocate.2.html) syscall supports the `FALLOC_FL_KEEP_SIZE` mode
+    FallocateKeepSize,
+    /// The [`fallocate`](https://man7.org/linux/man-pages/man2/fallocate.2.html) syscall supports the `FALLOC_FL_PUNCH_HOLE` mode
+    FallocatePunchHole,
+    /// The [`fallocate`](https://man7.org/linux/man-pages/man2/fallocate.2.html) syscall supports the `FALLOC_FL_ZERO_RANGE` mode
+    FallocateZeroRange,
+    /// The [`fallocate`](https://man7.org/linux/man-pages/man2/fallocate.2.html) syscall supports the `FALLOC_FL_COLLAPSE_RANGE` mode
+    FallocateCollapseRange,
+    /// The [`fallocate`](https://man7.org/linux/man-pages/man2/fallocate.2.html) syscall supports the `FALLOC_FL_INSERT_RANGE` mode
+    FallocateInsertRange,
     /// The [`posix_fallocate`](https://pubs.opengroup.org/onlinepubs/007904975/functions/posix_fallocate.html) syscall is available
     PosixFallocate,
+    /// Mounts can be idmapped with [`mount_setattr`](https://man7.org/linux/man-pages/man2/mount_setattr.2.html) and `MOUNT_ATTR_IDMAP`
+    IdmappedMounts,
+    /// Files can be cloned with the [`FICLONE` and `FICLONERANGE`](https://man7.org/linux/man-pages/man2/ioctl_ficlone.2.html) ioctls
+    Reflink,
     /// [`rename`](https://pubs.opengroup.org/onlinepubs/9699919799/functions/rename.html) changes `st_ctime` on success (POSIX does not require a file system to update a file's ctime when it gets renamed, but some file systems choose to do it anyway)
     RenameCtime,
+    /// Ranges of a file which were never written are stored as holes, which do not allocate blocks and are reported by [`lseek`](https://man.freebsd.org/cgi/man.cgi?lseek(2)) with `SEEK_HOLE`
+    SparseFiles,
     /// `struct stat` contains an [`st_birthtime`](https://man.freebsd.org/cgi/man.cgi?stat(2)) field
     StatStBirthtime,
+    /// [`statx`](https://man7.org/linux/man-pages/man2/statx.2.html) fills the `stx_btime` field with the file creation time
+    StatxBtime,
     /// The [`SF_SNAPSHOT`](https://man.freebsd.org/cgi/man.cgi?chflags(2)) flag can be set with `chflags`
     ChflagsSfSnapshot,
     /// The [`UTIME_NOW`](https://pubs.opengroup.org/onlinepubs/9699919799.orig/functions/futimens.html) constant is available
@@ -188,3 +188,63 @@ pub enum FileFlags {
     SF_SNAPINVAL,
 }
 }
+
+/// Flags which restrict the modifications of a file, set with `chflags` on BSD
+/// and with the `FS_IOC_SETFLAGS` ioctl on Linux (see `ioctl_iflags(2)`).
+#[cfg(inode_flags)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumIter)]
+pub enum InodeFlag {
+    /// The file cannot be modified, linked, renamed or removed
+    /// (`SF_IMMUTABLE` on BSD, `FS_IMMUTABLE_FL` on Linux).
+    Immutable,
+    /// Data can only be appended to the file, which cannot be renamed or removed either
+    /// (`SF_APPEND` on BSD, `FS_APPEND_FL` on Linux).
+    Append,
+}
+
+#[cfg(inode_flags)]
+impl InodeFlag {
+    /// Set the flag on the file, following symbolic links.
+    pub fn set<P: ?Sized + nix::NixPath>(self, path: &P) -> nix::Result<()> {
+        self.update(path, true)
+    }
+
+    /// Clear the flag on the file, following symbolic links.
+    pub fn clear<P: ?Sized + nix::NixPath>(self, path: &P) -> nix::Result<()> {
+        self.update(path, false)
+    }
+
+    #[cfg(file_flags)]
+    fn update<P: ?Sized + nix::NixPath>(self, path: &P, set: bool) -> nix::Result<()> {
+        use nix::{
+            sys::stat::{stat, FileFlag},
+            unistd::chflags,
+        };
+
+        let flag = match self {
+            InodeFlag::Immutable => FileFlag::SF_IMMUTABLE,
+            InodeFlag::Append => FileFlag::SF_APPEND,
+        };
+        let mut flags = FileFlag::from_bits_truncate(stat(path)?.st_flags as _);
+        flags.set(flag, set);
+
+        chflags(path, flags)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn update<P: ?Sized + nix::NixPath>(self, path: &P, set: bool) -> nix::Result<()> {
+        use crate::utils::{
+            get_inode_flags,
+            iflags::{FS_APPEND_FL, FS_IMMUTABLE_FL},
+            set_inode_flags,
+        };
+
+        let flag = match self {
+            InodeFlag::Immutable => FS_IMMUTABLE_FL,
+            InodeFlag::Append => FS_APPEND_FL,
+        };
+        let flags = get_inode_flags(path)?;
+
+        set_inode_flags(path, if set { flags | flag } else { flags & !flag })
+    }
+}
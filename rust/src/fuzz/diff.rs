@@ -0,0 +1,425 @@
+//! Differential mode, which executes the same random sequences or test cases on two file systems
+//! side by side and reports every difference in behaviour, even where both behaviours are allowed by POSIX.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::Args;
+use colored::Colorize;
+use nix::{
+    errno::Errno,
+    sys::stat::{lstat, FileStat, Mode, SFlag},
+};
+use tempfile::tempdir_in;
+
+use crate::{
+    config::Config,
+    context::{seed_names, KeepPolicy},
+    execute_test_case, outcome, skip_reasons,
+    test::TestCase,
+    trace,
+    utils::chmod,
+};
+
+use super::{
+    actors, as_actor, cleanup, describe, generate,
+    op::{Actor, Step},
+    FuzzOptions,
+};
+
+/// Options of the `diff` command.
+#[derive(Debug, Args)]
+pub struct DiffOptions {
+    #[command(flatten)]
+    pub fuzz: FuzzOptions,
+
+    #[arg(
+        long,
+        num_args = 0..,
+        value_name = "PATTERN",
+        help = "Run the test cases whose name contains one of the patterns (all if none) instead of random sequences"
+    )]
+    pub tests: Option<Vec<String>>,
+}
+
+/// Difference in behaviour between the two file systems, for a single step.
+struct Difference {
+    /// Kind of the difference, used to group similar differences in the report.
+    kind: String,
+    description: String,
+}
+
+/// Differences of the same kind, along with an example.
+struct Group {
+    count: usize,
+    /// Number of differences which were the first one of their sequence or test case,
+    /// while the rest may be consequences of an earlier difference.
+    first: usize,
+    example: String,
+}
+
+/// Report of the differences, grouped by kind.
+#[derive(Default)]
+struct Report {
+    groups: BTreeMap<String, Group>,
+}
+
+impl Report {
+    /// Add the differences found at the same point of a sequence or test case,
+    /// described by `location`, and whether they are the first ones of it.
+    fn add(&mut self, differences: &[Difference], first: bool, location: impl Fn() -> String) {
+        for difference in differences {
+            let group = self
+                .groups
+                .entry(difference.kind.clone())
+                .or_insert_with(|| Group {
+                    count: 0,
+                    first: 0,
+                    example: format!("{}: {}", location(), difference.description),
+                });
+            group.count += 1;
+            group.first += usize::from(first);
+        }
+    }
+
+    fn print(&self, title: String) {
+        println!("{}", title.bold());
+        if self.groups.is_empty() {
+            println!("No difference found");
+            return;
+        }
+        println!("{:60} {:>6} {:>6}", "", "total", "first");
+        for (kind, group) in &self.groups {
+            println!("{:60} {:>6} {:>6}", kind.blue(), group.count, group.first);
+            println!("\te.g. {}", group.example);
+        }
+    }
+}
+
+/// Return the secondary file system, which the `diff` command compares to.
+fn secondary_fs(config: &Config) -> anyhow::Result<&Path> {
+    config.features.secondary_fs.as_deref().ok_or_else(|| {
+        anyhow::anyhow!("The diff command requires a secondary file system (secondary_fs)")
+    })
+}
+
+/// Run the sequences on `path` and on the secondary file system, and print a report of the differences.
+///
+/// Each sequence runs to the end, even after a difference, so that every difference is reported.
+pub fn run(options: &FuzzOptions, config: &Config, path: &Path) -> anyhow::Result<()> {
+    let secondary_fs = secondary_fs(config)?;
+    let naptime = Duration::from_secs_f64(config.settings.naptime);
+    let actors = actors(config);
+    let first_seed = options.seed.unwrap_or_else(rand::random);
+
+    let base_dirs = [tempdir_in(path)?, tempdir_in(secondary_fs)?];
+    for dir in &base_dirs {
+        // The dummy users need to access the directories of the sequences
+        chmod(dir.path(), Mode::from_bits_truncate(0o755))?;
+    }
+
+    let mut report = Report::default();
+    for seed in (0..options.sequences).map(|i| first_seed.wrapping_add(i)) {
+        let steps = generate(seed, options.length, &actors);
+        let dirs = [
+            tempdir_in(base_dirs[0].path())?,
+            tempdir_in(base_dirs[1].path())?,
+        ];
+        for dir in &dirs {
+            chmod(dir.path(), Mode::from_bits_truncate(0o777))?;
+        }
+
+        let mut diverged = false;
+        for (i, step) in steps.iter().enumerate() {
+            let differences = execute_step(
+                [dirs[0].path(), dirs[1].path()],
+                &actors[step.actor],
+                step,
+                naptime,
+            );
+            report.add(&differences, !diverged, || {
+                format!("seed {seed}, step {i}: {}", step.op)
+            });
+            diverged |= !differences.is_empty();
+        }
+
+        dirs.into_iter().for_each(cleanup);
+    }
+
+    report.print(format!(
+        "Differences between {} and {} ({} sequences of {} operations, first seed {first_seed})",
+        path.display(),
+        secondary_fs.display(),
+        options.sequences,
+        options.length
+    ));
+
+    Ok(())
+}
+
+/// Run the test cases on `path` and on the secondary file system, recording their syscalls,
+/// and print a report of the differences in their outcome and in the results of their syscalls.
+pub fn run_test_cases(test_cases: &[TestCase], config: &Config, path: &Path) -> anyhow::Result<()> {
+    let secondary_fs = secondary_fs(config)?;
+    let base_dirs = [tempdir_in(path)?, tempdir_in(secondary_fs)?];
+
+    let mut report = Report::default();
+    let mut compared = 0;
+    for test_case in test_cases {
+        let temp_dirs = [
+            tempdir_in(base_dirs[0].path())?,
+            tempdir_in(base_dirs[1].path())?,
+        ];
+        let dirs = [temp_dirs[0].path(), temp_dirs[1].path()];
+        for dir in dirs {
+            // Same mode as the base directory of the tests
+            chmod(dir, Mode::from_bits_truncate(0o755))?;
+        }
+
+        let skipped = dirs.map(|dir| {
+            let reasons = skip_reasons(test_case, config, dir);
+            (!reasons.is_empty()).then(|| reasons.join(", "))
+        });
+        if let [Some(_), Some(_)] = &skipped {
+            continue;
+        }
+        if let [Some(reason), None] | [None, Some(reason)] = &skipped {
+            let description = match skipped[0] {
+                Some(_) => format!("skipped on the first file system: {reason}"),
+                None => format!("skipped on the second file system: {reason}"),
+            };
+            let difference = Difference {
+                kind: String::from("test case: skipped"),
+                description,
+            };
+            report.add(&[difference], true, || test_case.name.to_string());
+            continue;
+        }
+
+        compared += 1;
+        // Both runs use the same names, so that their syscalls can be compared
+        let seed = rand::random();
+        let runs = dirs.map(|dir| {
+            seed_names(seed);
+            trace::start(dir, true);
+            let result = execute_test_case(test_case, config, dir, KeepPolicy::Never);
+            let calls: Vec<_> = trace::stop().unwrap().calls().collect();
+            outcome::take();
+            (result.is_ok(), calls)
+        });
+
+        let [(_, a), (_, b)] = &runs;
+        // After the test took another path, the following syscalls cannot be compared
+        let common = a.iter().zip(b).take_while(|(a, b)| a.1 == b.1).count();
+        let mut diverged = false;
+        for (i, (a, b)) in a.iter().zip(b).take(common).enumerate() {
+            // Opened file descriptors may differ
+            let results = [a.2.map(drop), b.2.map(drop)];
+            if results[0] != results[1] {
+                let difference = Difference {
+                    kind: format!("{}: result", a.0),
+                    description: format!(
+                        "returned {} and {}",
+                        describe(&results[0].map(|()| "success")),
+                        describe(&results[1].map(|()| "success"))
+                    ),
+                };
+                report.add(&[difference], !diverged, || {
+                    format!("{}, syscall {i}: {}", test_case.name, shorten(&a.1))
+                });
+                diverged = true;
+            }
+        }
+        if common < a.len().max(b.len()) {
+            let call = |calls: &[(&str, String, _)]| {
+                calls
+                    .get(common)
+                    .map_or_else(|| String::from("nothing"), |c| shorten(&c.1))
+            };
+            let difference = Difference {
+                kind: String::from("test case: syscalls"),
+                description: format!("performed {} and {}", call(a), call(b)),
+            };
+            report.add(&[difference], !diverged, || {
+                format!("{}, syscall {common}", test_case.name)
+            });
+            diverged = true;
+        }
+        if runs[0].0 != runs[1].0 {
+            let outcome = |passed| if passed { "passed" } else { "failed" };
+            let difference = Difference {
+                kind: String::from("test case: outcome"),
+                description: format!("{} and {}", outcome(runs[0].0), outcome(runs[1].0)),
+            };
+            report.add(&[difference], !diverged, || test_case.name.to_string());
+        }
+    }
+
+    report.print(format!(
+        "Differences between {} and {} ({compared} test cases run on both)",
+        path.display(),
+        secondary_fs.display(),
+    ));
+
+    Ok(())
+}
+
+/// Shorten an encoded syscall, whose paths might be as long as PATH_MAX, for the report.
+fn shorten(call: &str) -> String {
+    const MAX_LEN: usize = 120;
+
+    match call.char_indices().nth(MAX_LEN) {
+        Some((end, _)) => format!("{}...", &call[..end]),
+        None => call.to_string(),
+    }
+}
+
+/// Return the paths whose attributes are compared after the step:
+/// the paths of the operation and their parent directories.
+fn compared_paths(step: &Step) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = step
+        .op
+        .paths()
+        .into_iter()
+        .flat_map(|path| [path.to_path_buf(), path.parent().unwrap().to_path_buf()])
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    paths
+}
+
+/// Execute the step on both directories, and return the differences in behaviour.
+fn execute_step(
+    dirs: [&Path; 2],
+    actor: &Actor,
+    step: &Step,
+    naptime: Duration,
+) -> Vec<Difference> {
+    let paths = compared_paths(step);
+    let stat_all = |dir: &Path| -> Vec<_> { paths.iter().map(|p| lstat(&dir.join(p))).collect() };
+
+    let before = dirs.map(stat_all);
+    // Let the clock advance past the timestamps which are compared, so that updates can be observed
+    if let Some(latest) = before.iter().flatten().flatten().map(latest_change).max() {
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH + latest)
+            .unwrap_or_default();
+        thread::sleep(naptime.saturating_sub(elapsed));
+    }
+    let results = dirs.map(|dir| as_actor(actor, || step.op.execute(dir)));
+    let after = dirs.map(stat_all);
+
+    let name = step.op.name();
+    let mut differences = vec![];
+    if results[0] != results[1] {
+        differences.push(Difference {
+            kind: format!("{name}: result"),
+            description: format!(
+                "returned {} and {}",
+                describe(&results[0].map(|()| "success")),
+                describe(&results[1].map(|()| "success"))
+            ),
+        });
+    }
+
+    for (i, path) in paths.iter().enumerate() {
+        let path = if path.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            path
+        };
+        let mut push = |what: &str, description: String| {
+            differences.push(Difference {
+                kind: format!("{name}: {what}"),
+                description: format!("{}: {description}", path.display()),
+            })
+        };
+
+        let (a, b) = match (&after[0][i], &after[1][i]) {
+            (Ok(a), Ok(b)) => (a, b),
+            (Err(a), Err(b)) if a == b => continue,
+            (a, b) => {
+                push(
+                    "lstat result",
+                    format!("lstat returned {} and {}", stat_result(a), stat_result(b)),
+                );
+                continue;
+            }
+        };
+
+        let file_type = |st: &FileStat| st.st_mode & SFlag::S_IFMT.bits();
+        if file_type(a) != file_type(b) {
+            push(
+                "file type",
+                format!("{:#o} and {:#o}", file_type(a), file_type(b)),
+            );
+            continue;
+        }
+        let mode = |st: &FileStat| st.st_mode & 0o7777;
+        if mode(a) != mode(b) {
+            push("mode", format!("{:#o} and {:#o}", mode(a), mode(b)));
+        }
+        if (a.st_uid, a.st_gid) != (b.st_uid, b.st_gid) {
+            push(
+                "owner",
+                format!("{}:{} and {}:{}", a.st_uid, a.st_gid, b.st_uid, b.st_gid),
+            );
+        }
+        if a.st_nlink != b.st_nlink {
+            push("link count", format!("{} and {}", a.st_nlink, b.st_nlink));
+        }
+
+        let updated = [
+            timestamps_updated(&before[0][i], a),
+            timestamps_updated(&before[1][i], b),
+        ];
+        if let [Some(a), Some(b)] = updated {
+            for (field, a, b) in [("ctime", a.0, b.0), ("mtime", a.1, b.1)] {
+                if a != b {
+                    push(
+                        &format!("{field} update"),
+                        format!("{field} {} and {}", updated_str(a), updated_str(b)),
+                    );
+                }
+            }
+        }
+    }
+
+    differences
+}
+
+/// Return the time of the last change of ctime or mtime, since the Unix epoch.
+fn latest_change(st: &FileStat) -> Duration {
+    let time = |sec, nsec| Duration::new(sec as u64, nsec as u32);
+
+    time(st.st_ctime, st.st_ctime_nsec).max(time(st.st_mtime, st.st_mtime_nsec))
+}
+
+/// Return whether ctime and mtime were updated, if the file existed before and is still the same.
+fn timestamps_updated(before: &Result<FileStat, Errno>, after: &FileStat) -> Option<(bool, bool)> {
+    let before = before
+        .as_ref()
+        .ok()
+        .filter(|st| st.st_ino == after.st_ino)?;
+    let ctime = (before.st_ctime, before.st_ctime_nsec) != (after.st_ctime, after.st_ctime_nsec);
+    let mtime = (before.st_mtime, before.st_mtime_nsec) != (after.st_mtime, after.st_mtime_nsec);
+
+    Some((ctime, mtime))
+}
+
+fn updated_str(updated: bool) -> &'static str {
+    if updated {
+        "updated"
+    } else {
+        "not updated"
+    }
+}
+
+fn stat_result(res: &Result<FileStat, Errno>) -> String {
+    describe(&res.map(|st| format!("{:#o}", st.st_mode)))
+}
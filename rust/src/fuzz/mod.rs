@@ -0,0 +1,303 @@
+//! Randomised operation fuzzer.
+//!
+//! The fuzzer generates random sequences of namespace and metadata operations,
+//! performed by the current user and the dummy users.
+//! Each sequence is executed both on the file system under test and on an in-memory [`Model`]
+//! of POSIX semantics, and any divergence in the returned error or in the attributes reported
+//! by `lstat` is reported, along with a minimised sequence and the seed which reproduces it.
+
+use std::{
+    fmt::Display,
+    panic::{catch_unwind, resume_unwind, AssertUnwindSafe},
+    path::Path,
+};
+
+use clap::Args;
+use colored::Colorize;
+use nix::{
+    errno::Errno,
+    sys::stat::{lstat, Mode},
+    unistd::{getgroups, setegid, seteuid, setgroups, Gid, Uid},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tempfile::{tempdir_in, TempDir};
+
+use crate::{
+    config::{Config, GroupInheritance},
+    tests::group_inheritance::group_inheritance,
+    utils::{chmod, lchmod},
+};
+
+pub mod diff;
+mod model;
+mod op;
+
+use model::{Attrs, Model};
+use op::{Actor, Op, Step};
+
+/// Options of the `fuzz` and `diff` commands.
+#[derive(Debug, Args)]
+pub struct FuzzOptions {
+    #[arg(long, help = "Seed of the first sequence (random if not specified)")]
+    seed: Option<u64>,
+
+    #[arg(long, default_value_t = 100, help = "Number of sequences to run")]
+    sequences: u64,
+
+    #[arg(
+        long,
+        default_value_t = 50,
+        help = "Number of operations in each sequence"
+    )]
+    length: usize,
+}
+
+/// Divergence between the file system and the model.
+#[derive(Debug)]
+struct Divergence {
+    /// Index of the step after which the divergence was detected.
+    step: usize,
+    description: String,
+}
+
+/// Run the fuzzer in a temporary directory created in `path`.
+pub fn run(options: &FuzzOptions, config: &Config, path: &Path) -> anyhow::Result<()> {
+    let actors = actors(config);
+    let inheritance = group_inheritance(config, path)?;
+    let first_seed = options.seed.unwrap_or_else(rand::random);
+    let base_dir = tempdir_in(path)?;
+    // The dummy users need to access the directories of the sequences
+    chmod(base_dir.path(), Mode::from_bits_truncate(0o755))?;
+    let mut failures = 0;
+
+    for seed in (0..options.sequences).map(|i| first_seed.wrapping_add(i)) {
+        let steps = generate(seed, options.length, &actors);
+        let Some(divergence) = execute(base_dir.path(), &actors, inheritance, &steps)? else {
+            continue;
+        };
+
+        failures += 1;
+        println!(
+            "{} with seed {seed} at step {}: {}",
+            "Divergence".red().bold(),
+            divergence.step,
+            divergence.description
+        );
+        let (steps, divergence) =
+            minimize(base_dir.path(), &actors, inheritance, steps, divergence)?;
+        println!("Minimised sequence ({} steps):", steps.len());
+        for (i, step) in steps.iter().enumerate() {
+            let actor = &actors[step.actor];
+            println!("\t{i}: as {}:{}: {}", actor.uid, actor.gid, step.op);
+        }
+        println!("\t{}", divergence.description);
+        println!(
+            "Reproduce with: pjdfstest fuzz --seed {seed} --sequences 1 --length {}\n",
+            options.length
+        );
+    }
+
+    println!(
+        "\n{}: {failures} divergences in {} sequences of {} operations (first seed {first_seed})",
+        "Summary".bold(),
+        options.sequences,
+        options.length
+    );
+
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("The file system diverged from the model"))
+    }
+}
+
+/// Return the actors performing the operations: the current user,
+/// and the dummy users if the fuzzer runs as root.
+fn actors(config: &Config) -> Vec<Actor> {
+    let current = Actor {
+        uid: Uid::effective(),
+        gid: Gid::effective(),
+        groups: getgroups().unwrap(),
+    };
+    // The first dummy user is also a member of the group of the second one,
+    // so that permissions granted by supplementary groups are exercised
+    let [first, second] = &config.dummy_auth.entries;
+    let dummy_users = [
+        Actor {
+            uid: first.user.uid,
+            gid: first.group.gid,
+            groups: vec![first.group.gid, second.group.gid],
+        },
+        Actor {
+            uid: second.user.uid,
+            gid: second.group.gid,
+            groups: vec![second.group.gid],
+        },
+    ];
+
+    if current.is_root() {
+        std::iter::once(current).chain(dummy_users).collect()
+    } else {
+        vec![current]
+    }
+}
+
+/// Generate a random sequence of `length` steps from `seed`.
+fn generate(seed: u64, length: usize, actors: &[Actor]) -> Vec<Step> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let pool = op::pool();
+
+    (0..length)
+        .map(|_| Step {
+            actor: rng.gen_range(0..actors.len()),
+            op: Op::random(&mut rng, &pool, actors),
+        })
+        .collect()
+}
+
+/// Execute `steps` in a new directory inside `base`, and return the first divergence from the model.
+fn execute(
+    base: &Path,
+    actors: &[Actor],
+    inheritance: GroupInheritance,
+    steps: &[Step],
+) -> anyhow::Result<Option<Divergence>> {
+    let dir = tempdir_in(base)?;
+    chmod(dir.path(), Mode::from_bits_truncate(0o777))?;
+    let mut model = Model::new(&lstat(dir.path())?, inheritance);
+
+    let res = execute_in(dir.path(), &mut model, actors, steps);
+    cleanup(dir);
+
+    Ok(res)
+}
+
+fn execute_in(
+    dir: &Path,
+    model: &mut Model,
+    actors: &[Actor],
+    steps: &[Step],
+) -> Option<Divergence> {
+    let pool = op::pool();
+
+    for (i, step) in steps.iter().enumerate() {
+        let actor = &actors[step.actor];
+        let result = as_actor(actor, || step.op.execute(dir));
+        let expected = model.apply(actor, &step.op);
+        if result != expected {
+            return Some(Divergence {
+                step: i,
+                description: format!(
+                    "{} returned {}, expected {}",
+                    step.op,
+                    describe(&result.map(|()| "success")),
+                    describe(&expected.map(|()| "success"))
+                ),
+            });
+        }
+
+        // The whole tree is checked after the last step
+        let paths = if i + 1 == steps.len() {
+            pool.iter().map(|p| p.as_path()).collect()
+        } else {
+            step.op.paths()
+        };
+        for path in paths {
+            if let Some(description) = compare_attrs(dir, model, &actors[0], path) {
+                return Some(Divergence {
+                    step: i,
+                    description: format!("after {}, {description}", step.op),
+                });
+            }
+        }
+    }
+
+    None
+}
+
+/// Compare the attributes of `path` with the ones of the model,
+/// and return a description of the difference if any.
+/// They are retrieved with the credentials of the fuzzer itself.
+fn compare_attrs(dir: &Path, model: &Model, checker: &Actor, path: &Path) -> Option<String> {
+    let attrs = lstat(&dir.join(path)).map(|st| Attrs::from_stat(&st));
+    let expected = model.lstat(checker, path);
+    let matches = match (&attrs, &expected) {
+        (Ok(attrs), Ok(expected)) => attrs.matches(expected),
+        (attrs, expected) => attrs == expected,
+    };
+
+    (!matches).then(|| {
+        format!(
+            "lstat({}) returned {}, expected {}",
+            path.display(),
+            describe(&attrs),
+            describe(&expected)
+        )
+    })
+}
+
+/// Remove steps from the sequence as long as it still diverges from the model.
+fn minimize(
+    base: &Path,
+    actors: &[Actor],
+    inheritance: GroupInheritance,
+    mut steps: Vec<Step>,
+    mut divergence: Divergence,
+) -> anyhow::Result<(Vec<Step>, Divergence)> {
+    steps.truncate(divergence.step + 1);
+
+    let mut i = 0;
+    while i < steps.len() {
+        let mut candidate = steps.clone();
+        candidate.remove(i);
+        match execute(base, actors, inheritance, &candidate)? {
+            Some(d) => {
+                steps = candidate;
+                steps.truncate(d.step + 1);
+                divergence = d;
+            }
+            None => i += 1,
+        }
+    }
+
+    Ok((steps, divergence))
+}
+
+fn describe<T: Display>(result: &Result<T, Errno>) -> String {
+    match result {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("{e:?}"),
+    }
+}
+
+/// Execute `f` with the credentials of `actor`.
+fn as_actor<T, F: FnOnce() -> T>(actor: &Actor, f: F) -> T {
+    if actor.uid == Uid::effective() {
+        return f();
+    }
+
+    let original_euid = Uid::effective();
+    let original_egid = Gid::effective();
+    let original_groups = getgroups().unwrap();
+
+    setgroups(&actor.groups).unwrap();
+    setegid(actor.gid).unwrap();
+    seteuid(actor.uid).unwrap();
+
+    let res = catch_unwind(AssertUnwindSafe(f));
+
+    seteuid(original_euid).unwrap();
+    setegid(original_egid).unwrap();
+    setgroups(&original_groups).unwrap();
+
+    res.unwrap_or_else(|e| resume_unwind(e))
+}
+
+/// Make every directory of the tree accessible, so that it can be removed.
+fn cleanup(dir: TempDir) {
+    for entry in walkdir::WalkDir::new(dir.path()).into_iter().flatten() {
+        if entry.file_type().is_dir() {
+            let _ = lchmod(entry.path(), Mode::S_IRWXU);
+        }
+    }
+}
@@ -0,0 +1,849 @@
+//! In-memory reference model of POSIX file system semantics.
+//!
+//! The model tracks the namespace and the attributes reported by `lstat`,
+//! and returns the same errors as the file system should, in the order Linux checks them.
+//! Where POSIX leaves a choice, the model follows Linux.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    path::{Component, Path},
+};
+
+use nix::{
+    errno::Errno,
+    libc::{mode_t, nlink_t, off_t},
+    sys::stat::{FileStat, Mode, SFlag},
+    unistd::{Gid, Uid},
+};
+
+use super::op::{Actor, Op};
+use crate::config::GroupInheritance;
+
+/// Maximum number of symbolic links followed during a path resolution.
+#[cfg(target_os = "linux")]
+const MAXSYMLINKS: u32 = 40;
+#[cfg(not(target_os = "linux"))]
+const MAXSYMLINKS: u32 = 32;
+
+/// Error returned by `unlink` on a directory.
+#[cfg(target_os = "linux")]
+const UNLINK_DIR_ERROR: Errno = Errno::EISDIR;
+#[cfg(not(target_os = "linux"))]
+const UNLINK_DIR_ERROR: Errno = Errno::EPERM;
+
+const READ: mode_t = 0o4;
+const WRITE: mode_t = 0o2;
+const EXEC: mode_t = 0o1;
+
+type Ino = usize;
+
+#[derive(Debug)]
+enum Kind {
+    Regular {
+        size: off_t,
+    },
+    Dir {
+        entries: BTreeMap<OsString, Ino>,
+        parent: Ino,
+    },
+    Symlink {
+        target: OsString,
+    },
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: Kind,
+    mode: mode_t,
+    uid: Uid,
+    gid: Gid,
+    nlink: nlink_t,
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, Kind::Dir { .. })
+    }
+}
+
+/// Attributes reported by `lstat` which are compared between the model and the file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attrs {
+    pub file_type: mode_t,
+    pub mode: mode_t,
+    pub uid: Uid,
+    pub gid: Gid,
+    pub nlink: nlink_t,
+    /// Size of regular files and symbolic links.
+    pub size: Option<off_t>,
+}
+
+impl Attrs {
+    pub fn from_stat(st: &FileStat) -> Self {
+        let file_type = st.st_mode & SFlag::S_IFMT.bits();
+        let has_size = file_type == SFlag::S_IFREG.bits() || file_type == SFlag::S_IFLNK.bits();
+
+        Attrs {
+            file_type,
+            mode: st.st_mode & !SFlag::S_IFMT.bits(),
+            uid: Uid::from_raw(st.st_uid),
+            gid: Gid::from_raw(st.st_gid),
+            nlink: st.st_nlink,
+            size: has_size.then_some(st.st_size),
+        }
+    }
+
+    /// Whether the attributes reported by the file system match the ones of the model.
+    pub fn matches(&self, model: &Attrs) -> bool {
+        // Some file systems do not track the link count of directories and report 1
+        let dir_nlink_untracked = self.file_type == SFlag::S_IFDIR.bits() && self.nlink == 1;
+
+        self == model || (dir_nlink_untracked && Attrs { nlink: 1, ..*model } == *self)
+    }
+}
+
+impl Display for Attrs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "type {:#o}, mode {:#o}, uid {}, gid {}, nlink {}",
+            self.file_type, self.mode, self.uid, self.gid, self.nlink
+        )?;
+        if let Some(size) = self.size {
+            write!(f, ", size {size}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reference model of a directory tree.
+#[derive(Debug)]
+pub struct Model {
+    nodes: HashMap<Ino, Node>,
+    next_ino: Ino,
+    root: Ino,
+    /// Group ID given to new entries.
+    group_inheritance: GroupInheritance,
+    /// Whether hard links to files not owned by the caller are restricted,
+    /// as with the `fs.protected_hardlinks` sysctl on Linux.
+    protected_hardlinks: bool,
+}
+
+impl Model {
+    /// Create a model of an empty directory with the given attributes,
+    /// on a file system with the given group inheritance semantics.
+    pub fn new(root: &FileStat, group_inheritance: GroupInheritance) -> Self {
+        Model::with_root(
+            root.st_mode & 0o7777,
+            Uid::from_raw(root.st_uid),
+            Gid::from_raw(root.st_gid),
+            group_inheritance,
+            protected_hardlinks(),
+        )
+    }
+
+    fn with_root(
+        mode: mode_t,
+        uid: Uid,
+        gid: Gid,
+        group_inheritance: GroupInheritance,
+        protected_hardlinks: bool,
+    ) -> Self {
+        let root_node = Node {
+            kind: Kind::Dir {
+                entries: BTreeMap::new(),
+                parent: 0,
+            },
+            mode,
+            uid,
+            gid,
+            nlink: 2,
+        };
+
+        Model {
+            nodes: HashMap::from([(0, root_node)]),
+            next_ino: 1,
+            root: 0,
+            group_inheritance,
+            protected_hardlinks,
+        }
+    }
+
+    fn node(&self, ino: Ino) -> &Node {
+        &self.nodes[&ino]
+    }
+
+    fn node_mut(&mut self, ino: Ino) -> &mut Node {
+        self.nodes.get_mut(&ino).unwrap()
+    }
+
+    fn entries(&self, dir: Ino) -> &BTreeMap<OsString, Ino> {
+        match &self.node(dir).kind {
+            Kind::Dir { entries, .. } => entries,
+            _ => unreachable!("not a directory"),
+        }
+    }
+
+    fn entries_mut(&mut self, dir: Ino) -> &mut BTreeMap<OsString, Ino> {
+        match &mut self.node_mut(dir).kind {
+            Kind::Dir { entries, .. } => entries,
+            _ => unreachable!("not a directory"),
+        }
+    }
+
+    fn parent(&self, dir: Ino) -> Ino {
+        match self.node(dir).kind {
+            Kind::Dir { parent, .. } => parent,
+            _ => unreachable!("not a directory"),
+        }
+    }
+
+    /// Whether `actor` is granted the `want` permissions on `ino`.
+    fn permitted(&self, actor: &Actor, ino: Ino, want: mode_t) -> bool {
+        let node = self.node(ino);
+        if actor.is_root() {
+            // Execute permission is granted to root only if any execute bit is set
+            return want & EXEC == 0 || node.is_dir() || node.mode & 0o111 != 0;
+        }
+
+        let bits = if actor.uid == node.uid {
+            node.mode >> 6
+        } else if actor.in_group(node.gid) {
+            node.mode >> 3
+        } else {
+            node.mode
+        };
+
+        bits & want == want
+    }
+
+    /// Check that `dir` is a directory which can be searched.
+    fn check_search(&self, actor: &Actor, dir: Ino) -> Result<(), Errno> {
+        if !self.node(dir).is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        if !self.permitted(actor, dir, EXEC) {
+            return Err(Errno::EACCES);
+        }
+
+        Ok(())
+    }
+
+    /// Resolve `path` from the directory `start`.
+    /// The last component is followed if it is a symbolic link and `follow` is set.
+    fn walk(
+        &self,
+        actor: &Actor,
+        start: Ino,
+        path: &Path,
+        follow: bool,
+        links: &mut u32,
+    ) -> Result<Ino, Errno> {
+        let components: Vec<_> = path.components().collect();
+        let mut current = start;
+        for (i, component) in components.iter().enumerate() {
+            let name = match component {
+                Component::Normal(name) => name,
+                _ => unreachable!("unexpected component in {}", path.display()),
+            };
+            self.check_search(actor, current)?;
+            let child = *self.entries(current).get(*name).ok_or(Errno::ENOENT)?;
+
+            current = match &self.node(child).kind {
+                Kind::Symlink { target } if follow || i + 1 < components.len() => {
+                    *links += 1;
+                    if *links > MAXSYMLINKS {
+                        return Err(Errno::ELOOP);
+                    }
+                    self.walk(actor, current, Path::new(target), true, links)?
+                }
+                _ => child,
+            };
+        }
+
+        Ok(current)
+    }
+
+    /// Resolve `path`, following the last component if `follow` is set.
+    fn lookup(&self, actor: &Actor, path: &Path, follow: bool) -> Result<Ino, Errno> {
+        self.walk(actor, self.root, path, follow, &mut 0)
+    }
+
+    /// Resolve the parent directory of `path`, and return it along with the last component.
+    fn lookup_parent<'p>(&self, actor: &Actor, path: &'p Path) -> Result<(Ino, &'p OsStr), Errno> {
+        let parent = self.lookup(actor, path.parent().unwrap(), true)?;
+        self.check_search(actor, parent)?;
+
+        Ok((parent, path.file_name().unwrap()))
+    }
+
+    /// Check that `actor` can create or remove entries in `dir`.
+    fn check_modify(&self, actor: &Actor, dir: Ino) -> Result<(), Errno> {
+        if self.permitted(actor, dir, WRITE | EXEC) {
+            Ok(())
+        } else {
+            Err(Errno::EACCES)
+        }
+    }
+
+    /// Whether `ancestor` is `dir` or one of its ancestors.
+    fn is_ancestor(&self, ancestor: Ino, mut dir: Ino) -> bool {
+        loop {
+            if dir == ancestor {
+                return true;
+            }
+            if dir == self.root {
+                return false;
+            }
+            dir = self.parent(dir);
+        }
+    }
+
+    /// Group of a new file created by `actor` in `dir`.
+    fn new_gid(&self, actor: &Actor, dir: Ino) -> Gid {
+        let sgid_dir = self.node(dir).mode & Mode::S_ISGID.bits() != 0;
+        if self.group_inheritance == GroupInheritance::SystemV && !sgid_dir {
+            actor.gid
+        } else {
+            self.node(dir).gid
+        }
+    }
+
+    /// Create an entry in `dir`, which is assumed to be writable.
+    fn insert(&mut self, actor: &Actor, dir: Ino, name: &OsStr, kind: Kind, mode: mode_t) {
+        let is_dir = matches!(kind, Kind::Dir { .. });
+        let ino = self.next_ino;
+        self.next_ino += 1;
+        // Linux propagates the S_ISGID bit to new subdirectories
+        let sgid_dir = self.node(dir).mode & Mode::S_ISGID.bits() != 0;
+        let mode = if cfg!(target_os = "linux") && is_dir && sgid_dir {
+            mode | Mode::S_ISGID.bits()
+        } else {
+            mode
+        };
+
+        let node = Node {
+            kind,
+            mode,
+            uid: actor.uid,
+            gid: self.new_gid(actor, dir),
+            nlink: if is_dir { 2 } else { 1 },
+        };
+        self.nodes.insert(ino, node);
+        self.entries_mut(dir).insert(name.to_owned(), ino);
+        if is_dir {
+            self.node_mut(dir).nlink += 1;
+        }
+    }
+
+    /// Remove the entry `name` from `dir`, and the node if it was its last link.
+    fn remove(&mut self, dir: Ino, name: &OsStr) {
+        let ino = self.entries_mut(dir).remove(name).unwrap();
+        if self.node(ino).is_dir() {
+            self.node_mut(dir).nlink -= 1;
+            self.nodes.remove(&ino);
+        } else {
+            let node = self.node_mut(ino);
+            node.nlink -= 1;
+            if node.nlink == 0 {
+                self.nodes.remove(&ino);
+            }
+        }
+    }
+
+    /// Common path of the operations creating a new entry at `path`.
+    fn prepare_create<'p>(&self, actor: &Actor, path: &'p Path) -> Result<(Ino, &'p OsStr), Errno> {
+        let (dir, name) = self.lookup_parent(actor, path)?;
+        if self.entries(dir).contains_key(name) {
+            return Err(Errno::EEXIST);
+        }
+        self.check_modify(actor, dir)?;
+
+        Ok((dir, name))
+    }
+
+    /// Check that a hard link to `ino` can be created by `actor`.
+    fn check_link_source(&self, actor: &Actor, ino: Ino) -> Result<(), Errno> {
+        let node = self.node(ino);
+        if !self.protected_hardlinks || actor.is_root() || actor.uid == node.uid {
+            return Ok(());
+        }
+
+        let safe_source =
+            matches!(node.kind, Kind::Regular { .. }) && self.permitted(actor, ino, READ | WRITE);
+        if safe_source {
+            Ok(())
+        } else {
+            Err(Errno::EPERM)
+        }
+    }
+
+    /// Apply `op`, performed by `actor`, to the model and return its expected result.
+    pub fn apply(&mut self, actor: &Actor, op: &Op) -> Result<(), Errno> {
+        match op {
+            Op::Create { path, mode } => {
+                let (dir, name) = self.prepare_create(actor, path)?;
+                self.insert(actor, dir, name, Kind::Regular { size: 0 }, mode & 0o777);
+            }
+            Op::Mkdir { path, mode } => {
+                let (dir, name) = self.prepare_create(actor, path)?;
+                let kind = Kind::Dir {
+                    entries: BTreeMap::new(),
+                    parent: dir,
+                };
+                self.insert(actor, dir, name, kind, mode & 0o777);
+            }
+            Op::Symlink { target, path } => {
+                let (dir, name) = self.prepare_create(actor, path)?;
+                let kind = Kind::Symlink {
+                    target: target.clone().into_os_string(),
+                };
+                self.insert(actor, dir, name, kind, 0o777);
+            }
+            Op::Link { from, to } => {
+                let ino = self.lookup(actor, from, false)?;
+                let (dir, name) = self.lookup_parent(actor, to)?;
+                if self.entries(dir).contains_key(name) {
+                    return Err(Errno::EEXIST);
+                }
+                self.check_link_source(actor, ino)?;
+                self.check_modify(actor, dir)?;
+                if self.node(ino).is_dir() {
+                    return Err(Errno::EPERM);
+                }
+
+                self.entries_mut(dir).insert(name.to_owned(), ino);
+                self.node_mut(ino).nlink += 1;
+            }
+            Op::Rename { from, to } => self.rename(actor, from, to)?,
+            Op::Unlink { path } => {
+                let (dir, name) = self.lookup_parent(actor, path)?;
+                let ino = *self.entries(dir).get(name).ok_or(Errno::ENOENT)?;
+                self.check_modify(actor, dir)?;
+                if self.node(ino).is_dir() {
+                    return Err(UNLINK_DIR_ERROR);
+                }
+
+                self.remove(dir, name);
+            }
+            Op::Rmdir { path } => {
+                let (dir, name) = self.lookup_parent(actor, path)?;
+                let ino = *self.entries(dir).get(name).ok_or(Errno::ENOENT)?;
+                self.check_modify(actor, dir)?;
+                if !self.node(ino).is_dir() {
+                    return Err(Errno::ENOTDIR);
+                }
+                if !self.entries(ino).is_empty() {
+                    return Err(Errno::ENOTEMPTY);
+                }
+
+                self.remove(dir, name);
+            }
+            Op::Chmod { path, mode } => {
+                let ino = self.lookup(actor, path, true)?;
+                if !actor.is_root() && actor.uid != self.node(ino).uid {
+                    return Err(Errno::EPERM);
+                }
+
+                self.node_mut(ino).mode = mode & 0o777;
+            }
+            Op::Chown {
+                path,
+                uid,
+                gid,
+                follow,
+            } => {
+                let ino = self.lookup(actor, path, *follow)?;
+                let node = self.node(ino);
+                if !actor.is_root() {
+                    let is_owner = actor.uid == node.uid;
+                    let uid_allowed = uid.map_or(true, |uid| is_owner && uid == node.uid);
+                    let gid_allowed = gid.map_or(true, |gid| {
+                        is_owner && (gid == node.gid || actor.in_group(gid))
+                    });
+                    if !uid_allowed || !gid_allowed {
+                        return Err(Errno::EPERM);
+                    }
+                }
+
+                let node = self.node_mut(ino);
+                node.uid = uid.unwrap_or(node.uid);
+                node.gid = gid.unwrap_or(node.gid);
+            }
+            Op::Truncate { path, size } => {
+                let ino = self.lookup(actor, path, true)?;
+                match self.node(ino).kind {
+                    Kind::Dir { .. } => return Err(Errno::EISDIR),
+                    Kind::Symlink { .. } => unreachable!("symbolic links are followed"),
+                    Kind::Regular { .. } => (),
+                }
+                if !self.permitted(actor, ino, WRITE) {
+                    return Err(Errno::EACCES);
+                }
+
+                self.node_mut(ino).kind = Kind::Regular { size: *size };
+            }
+        }
+
+        Ok(())
+    }
+
+    fn rename(&mut self, actor: &Actor, from: &Path, to: &Path) -> Result<(), Errno> {
+        let (from_dir, from_name) = self.lookup_parent(actor, from)?;
+        let (to_dir, to_name) = self.lookup_parent(actor, to)?;
+        let source = *self.entries(from_dir).get(from_name).ok_or(Errno::ENOENT)?;
+        let target = self.entries(to_dir).get(to_name).copied();
+        let is_dir = self.node(source).is_dir();
+
+        if from_dir != to_dir {
+            // The source cannot become its own descendant, and the target cannot be an ancestor of the source
+            if is_dir && self.is_ancestor(source, to_dir) {
+                return Err(Errno::EINVAL);
+            }
+            if target.is_some_and(|target| self.is_ancestor(target, from_dir)) {
+                return Err(Errno::ENOTEMPTY);
+            }
+        }
+        if target == Some(source) {
+            return Ok(());
+        }
+
+        self.check_modify(actor, from_dir)?;
+        self.check_modify(actor, to_dir)?;
+        if let Some(target) = target {
+            match (is_dir, self.node(target).is_dir()) {
+                (true, false) => return Err(Errno::ENOTDIR),
+                (false, true) => return Err(Errno::EISDIR),
+                _ => (),
+            }
+        }
+        // The `..` entry of a directory moved to another parent is updated
+        if is_dir && from_dir != to_dir && !self.permitted(actor, source, WRITE) {
+            return Err(Errno::EACCES);
+        }
+        if target.is_some_and(|target| is_dir && !self.entries(target).is_empty()) {
+            return Err(Errno::ENOTEMPTY);
+        }
+
+        if target.is_some() {
+            self.remove(to_dir, to_name);
+        }
+        self.entries_mut(from_dir).remove(from_name);
+        self.entries_mut(to_dir).insert(to_name.to_owned(), source);
+        if is_dir {
+            self.node_mut(from_dir).nlink -= 1;
+            self.node_mut(to_dir).nlink += 1;
+            if let Kind::Dir { parent, .. } = &mut self.node_mut(source).kind {
+                *parent = to_dir;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the attributes `lstat` should report for `path`.
+    pub fn lstat(&self, actor: &Actor, path: &Path) -> Result<Attrs, Errno> {
+        let ino = self.lookup(actor, path, false)?;
+        let node = self.node(ino);
+        let (file_type, size) = match &node.kind {
+            Kind::Regular { size } => (SFlag::S_IFREG, Some(*size)),
+            Kind::Dir { .. } => (SFlag::S_IFDIR, None),
+            Kind::Symlink { target } => (SFlag::S_IFLNK, Some(target.len() as off_t)),
+        };
+
+        Ok(Attrs {
+            file_type: file_type.bits(),
+            mode: node.mode,
+            uid: node.uid,
+            gid: node.gid,
+            nlink: node.nlink,
+            size,
+        })
+    }
+}
+
+/// Whether hard links to files not owned by the caller are restricted.
+fn protected_hardlinks() -> bool {
+    if cfg!(target_os = "linux") {
+        std::fs::read_to_string("/proc/sys/fs/protected_hardlinks")
+            .is_ok_and(|value| value.trim() == "1")
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use nix::{
+        errno::Errno,
+        libc::mode_t,
+        unistd::{Gid, Uid},
+    };
+
+    use crate::{
+        config::GroupInheritance,
+        fuzz::op::{Actor, Op},
+    };
+
+    use super::{Model, EXEC, UNLINK_DIR_ERROR};
+
+    fn actor(uid: u32, gid: u32, groups: &[u32]) -> Actor {
+        Actor {
+            uid: Uid::from_raw(uid),
+            gid: Gid::from_raw(gid),
+            groups: groups.iter().copied().map(Gid::from_raw).collect(),
+        }
+    }
+
+    fn root() -> Actor {
+        actor(0, 0, &[])
+    }
+
+    /// Return a model of a directory owned by root and writable by everyone.
+    fn model(group_inheritance: GroupInheritance) -> Model {
+        Model::with_root(
+            0o777,
+            Uid::from_raw(0),
+            Gid::from_raw(0),
+            group_inheritance,
+            false,
+        )
+    }
+
+    fn create(path: &str, mode: mode_t) -> Op {
+        Op::Create {
+            path: PathBuf::from(path),
+            mode,
+        }
+    }
+
+    fn mkdir(path: &str, mode: mode_t) -> Op {
+        Op::Mkdir {
+            path: PathBuf::from(path),
+            mode,
+        }
+    }
+
+    fn chown(path: &str, uid: Option<u32>, gid: Option<u32>) -> Op {
+        Op::Chown {
+            path: PathBuf::from(path),
+            uid: uid.map(Uid::from_raw),
+            gid: gid.map(Gid::from_raw),
+            follow: true,
+        }
+    }
+
+    fn truncate(path: &str) -> Op {
+        Op::Truncate {
+            path: PathBuf::from(path),
+            size: 1,
+        }
+    }
+
+    fn unlink(path: &str) -> Op {
+        Op::Unlink {
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn rmdir(path: &str) -> Op {
+        Op::Rmdir {
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn rename(from: &str, to: &str) -> Op {
+        Op::Rename {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        }
+    }
+
+    fn gid(model: &Model, path: &str) -> Gid {
+        model.lstat(&root(), path.as_ref()).unwrap().gid
+    }
+
+    #[test]
+    fn group_permissions_with_supplementary_groups() {
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &create("a0", 0o060)), Ok(()));
+        assert_eq!(
+            model.apply(&root(), &chown("a0", Some(1), Some(20))),
+            Ok(())
+        );
+
+        assert_eq!(model.apply(&actor(2, 10, &[20]), &truncate("a0")), Ok(()));
+        assert_eq!(
+            model.apply(&actor(2, 10, &[30]), &truncate("a0")),
+            Err(Errno::EACCES)
+        );
+        // The owner class takes precedence, even if it grants less than the group class
+        assert_eq!(
+            model.apply(&actor(1, 20, &[20]), &truncate("a0")),
+            Err(Errno::EACCES)
+        );
+    }
+
+    #[test]
+    fn root_execute_needs_an_execute_bit() {
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &create("a0", 0o644)), Ok(()));
+        assert_eq!(model.apply(&root(), &create("a1", 0o010)), Ok(()));
+        assert_eq!(model.apply(&root(), &mkdir("a2", 0o000)), Ok(()));
+
+        let permitted = |path: &str| {
+            let ino = model.lookup(&root(), path.as_ref(), false).unwrap();
+            model.permitted(&root(), ino, EXEC)
+        };
+        assert!(!permitted("a0"));
+        assert!(permitted("a1"));
+        assert!(permitted("a2"));
+    }
+
+    #[test]
+    fn create_errno_order() {
+        let user = actor(1, 1, &[]);
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &mkdir("a0", 0o555)), Ok(()));
+        assert_eq!(model.apply(&root(), &mkdir("a1", 0o000)), Ok(()));
+        assert_eq!(model.apply(&root(), &create("a2", 0o644)), Ok(()));
+
+        // An existing entry is reported before the lack of write permission
+        assert_eq!(model.apply(&user, &create("a0", 0o644)), Err(Errno::EEXIST));
+        assert_eq!(
+            model.apply(&user, &create("a0/b0", 0o644)),
+            Err(Errno::EACCES)
+        );
+        // The lack of search permission is reported before a missing entry
+        assert_eq!(
+            model.apply(&user, &create("a1/b0/b1", 0o644)),
+            Err(Errno::EACCES)
+        );
+        assert_eq!(
+            model.apply(&user, &create("a3/b0", 0o644)),
+            Err(Errno::ENOENT)
+        );
+        assert_eq!(
+            model.apply(&user, &create("a2/b0", 0o644)),
+            Err(Errno::ENOTDIR)
+        );
+    }
+
+    #[test]
+    fn remove_errno_order() {
+        let user = actor(1, 1, &[]);
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &mkdir("a0", 0o755)), Ok(()));
+        assert_eq!(model.apply(&root(), &mkdir("a0/b0", 0o755)), Ok(()));
+        assert_eq!(model.apply(&root(), &create("a0/b1", 0o644)), Ok(()));
+        assert_eq!(model.apply(&root(), &mkdir("a1", 0o755)), Ok(()));
+
+        // The lack of write permission is reported before the type of the entry
+        assert_eq!(model.apply(&user, &unlink("a0/b0")), Err(Errno::EACCES));
+        assert_eq!(model.apply(&user, &rmdir("a0/b1")), Err(Errno::EACCES));
+        assert_eq!(
+            model.apply(&root(), &unlink("a0/b0")),
+            Err(UNLINK_DIR_ERROR)
+        );
+        assert_eq!(model.apply(&root(), &rmdir("a0/b1")), Err(Errno::ENOTDIR));
+        assert_eq!(model.apply(&root(), &rmdir("a0")), Err(Errno::ENOTEMPTY));
+        assert_eq!(model.apply(&user, &unlink("a2")), Err(Errno::ENOENT));
+        assert_eq!(model.apply(&user, &rmdir("a1")), Ok(()));
+    }
+
+    #[test]
+    fn rename_errno_order() {
+        let user = actor(1, 1, &[]);
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &mkdir("a0", 0o755)), Ok(()));
+        assert_eq!(model.apply(&root(), &mkdir("a0/b0", 0o755)), Ok(()));
+        assert_eq!(model.apply(&root(), &create("a1", 0o644)), Ok(()));
+
+        // Moving a directory into itself is reported before the lack of write permission
+        assert_eq!(
+            model.apply(&user, &rename("a0", "a0/b0/b1")),
+            Err(Errno::EINVAL)
+        );
+        assert_eq!(
+            model.apply(&user, &rename("a1", "a0/b1")),
+            Err(Errno::EACCES)
+        );
+        assert_eq!(
+            model.apply(&root(), &rename("a1", "a0")),
+            Err(Errno::EISDIR)
+        );
+        assert_eq!(
+            model.apply(&root(), &rename("a0", "a1")),
+            Err(Errno::ENOTDIR)
+        );
+        assert_eq!(
+            model.apply(&root(), &rename("a0/b0", "a0")),
+            Err(Errno::ENOTEMPTY)
+        );
+    }
+
+    #[test]
+    fn truncate_errno_order() {
+        let user = actor(1, 1, &[]);
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&root(), &mkdir("a0", 0o555)), Ok(()));
+        assert_eq!(model.apply(&root(), &create("a1", 0o444)), Ok(()));
+
+        assert_eq!(model.apply(&user, &truncate("a0")), Err(Errno::EISDIR));
+        assert_eq!(model.apply(&user, &truncate("a1")), Err(Errno::EACCES));
+        assert_eq!(model.apply(&root(), &truncate("a1")), Ok(()));
+    }
+
+    #[test]
+    fn chown_to_supplementary_group() {
+        let user = actor(1, 10, &[20]);
+        let mut model = model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&user, &create("a0", 0o644)), Ok(()));
+
+        assert_eq!(model.apply(&user, &chown("a0", None, Some(20))), Ok(()));
+        assert_eq!(gid(&model, "a0"), Gid::from_raw(20));
+        assert_eq!(
+            model.apply(&user, &chown("a0", None, Some(30))),
+            Err(Errno::EPERM)
+        );
+        assert_eq!(
+            model.apply(&user, &chown("a0", Some(2), None)),
+            Err(Errno::EPERM)
+        );
+        assert_eq!(
+            model.apply(&actor(2, 20, &[]), &chown("a0", None, Some(20))),
+            Err(Errno::EPERM)
+        );
+    }
+
+    #[test]
+    fn new_entry_group() {
+        let user = actor(1, 10, &[]);
+
+        let mut model = self::model(GroupInheritance::SystemV);
+        assert_eq!(model.apply(&user, &create("a0", 0o644)), Ok(()));
+        assert_eq!(gid(&model, "a0"), Gid::from_raw(10));
+
+        let mut model = self::model(GroupInheritance::Bsd);
+        assert_eq!(model.apply(&user, &create("a0", 0o644)), Ok(()));
+        assert_eq!(gid(&model, "a0"), Gid::from_raw(0));
+
+        // The S_ISGID bit of the parent directory takes precedence over System V semantics
+        let mut model = Model::with_root(
+            0o2777,
+            Uid::from_raw(0),
+            Gid::from_raw(0),
+            GroupInheritance::SystemV,
+            false,
+        );
+        assert_eq!(model.apply(&user, &mkdir("a0", 0o755)), Ok(()));
+        assert_eq!(gid(&model, "a0"), Gid::from_raw(0));
+        #[cfg(target_os = "linux")]
+        assert_eq!(model.lstat(&user, "a0".as_ref()).unwrap().mode, 0o2755);
+    }
+}
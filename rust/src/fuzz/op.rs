@@ -0,0 +1,244 @@
+//! Operations performed by the fuzzer, and their execution on the file system under test.
+
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    fcntl::OFlag,
+    libc::{mode_t, off_t},
+    sys::stat::Mode,
+    unistd::{mkdir, Gid, Uid},
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::utils::{chmod, chown, lchown, link, open, rename, rmdir, symlink, truncate, unlink};
+
+/// Credentials an operation is performed with.
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub uid: Uid,
+    pub gid: Gid,
+    /// Supplementary groups.
+    pub groups: Vec<Gid>,
+}
+
+impl Actor {
+    pub fn is_root(&self) -> bool {
+        self.uid.is_root()
+    }
+
+    /// Whether `gid` is the effective group or one of the supplementary groups.
+    pub fn in_group(&self, gid: Gid) -> bool {
+        self.gid == gid || self.groups.contains(&gid)
+    }
+}
+
+/// A namespace or metadata operation, with paths relative to the base directory.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// `open` with `O_CREAT | O_EXCL`.
+    Create {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    Mkdir {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    Symlink {
+        target: PathBuf,
+        path: PathBuf,
+    },
+    Link {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Unlink {
+        path: PathBuf,
+    },
+    Rmdir {
+        path: PathBuf,
+    },
+    Chmod {
+        path: PathBuf,
+        mode: mode_t,
+    },
+    /// `chown` if `follow` is set, `lchown` otherwise.
+    Chown {
+        path: PathBuf,
+        uid: Option<Uid>,
+        gid: Option<Gid>,
+        follow: bool,
+    },
+    Truncate {
+        path: PathBuf,
+        size: off_t,
+    },
+}
+
+/// A single step of a sequence: an operation and the index of the actor which performs it.
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub actor: usize,
+    pub op: Op,
+}
+
+/// Return the paths the operations are performed on.
+/// There are few of them, so that operations interact with each other.
+pub fn pool() -> Vec<PathBuf> {
+    let top: Vec<PathBuf> = (0..4).map(|i| PathBuf::from(format!("a{i}"))).collect();
+    let nested = top
+        .iter()
+        .flat_map(|dir| (0..2).map(move |i| dir.join(format!("b{i}"))));
+
+    top.iter().cloned().chain(nested).collect()
+}
+
+impl Op {
+    /// Generate a random operation on the paths of `pool`,
+    /// with owners chosen among `actors`.
+    pub fn random<R: Rng>(rng: &mut R, pool: &[PathBuf], actors: &[Actor]) -> Self {
+        let path = pool.choose(rng).unwrap().clone();
+        let other = pool.choose(rng).unwrap().clone();
+        let mode = rng.gen_range(0..=0o777);
+
+        match rng.gen_range(0..11) {
+            0 | 1 => Op::Create { path, mode },
+            2 | 3 => Op::Mkdir { path, mode },
+            4 => Op::Symlink {
+                target: other,
+                path,
+            },
+            5 => Op::Link {
+                from: path,
+                to: other,
+            },
+            6 => Op::Rename {
+                from: path,
+                to: other,
+            },
+            7 => {
+                if rng.gen() {
+                    Op::Unlink { path }
+                } else {
+                    Op::Rmdir { path }
+                }
+            }
+            8 => Op::Chmod { path, mode },
+            9 => {
+                let actor = actors.choose(rng).unwrap();
+                Op::Chown {
+                    path,
+                    uid: rng.gen::<bool>().then_some(actor.uid),
+                    gid: rng.gen::<bool>().then_some(actor.gid),
+                    follow: rng.gen(),
+                }
+            }
+            _ => Op::Truncate {
+                path,
+                size: rng.gen_range(0..10000),
+            },
+        }
+    }
+
+    /// Return the name of the syscall performing the operation.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Create { .. } => "open",
+            Op::Mkdir { .. } => "mkdir",
+            Op::Symlink { .. } => "symlink",
+            Op::Link { .. } => "link",
+            Op::Rename { .. } => "rename",
+            Op::Unlink { .. } => "unlink",
+            Op::Rmdir { .. } => "rmdir",
+            Op::Chmod { .. } => "chmod",
+            Op::Chown { follow: true, .. } => "chown",
+            Op::Chown { follow: false, .. } => "lchown",
+            Op::Truncate { .. } => "truncate",
+        }
+    }
+
+    /// Return the paths whose attributes can be changed by the operation.
+    pub fn paths(&self) -> Vec<&Path> {
+        match self {
+            Op::Link { from, to } | Op::Rename { from, to } => vec![from, to],
+            Op::Create { path, .. }
+            | Op::Mkdir { path, .. }
+            | Op::Symlink { path, .. }
+            | Op::Unlink { path }
+            | Op::Rmdir { path }
+            | Op::Chmod { path, .. }
+            | Op::Chown { path, .. }
+            | Op::Truncate { path, .. } => vec![path],
+        }
+    }
+
+    /// Execute the operation on the file system, relatively to `base`.
+    pub fn execute(&self, base: &Path) -> nix::Result<()> {
+        match self {
+            Op::Create { path, mode } => open(
+                &base.join(path),
+                OFlag::O_CREAT | OFlag::O_EXCL | OFlag::O_WRONLY,
+                Mode::from_bits_truncate(*mode),
+            )
+            .map(drop),
+            Op::Mkdir { path, mode } => mkdir(&base.join(path), Mode::from_bits_truncate(*mode)),
+            Op::Symlink { target, path } => symlink(target, &base.join(path)),
+            Op::Link { from, to } => link(&base.join(from), &base.join(to)),
+            Op::Rename { from, to } => rename(&base.join(from), &base.join(to)),
+            Op::Unlink { path } => unlink(&base.join(path)),
+            Op::Rmdir { path } => rmdir(&base.join(path)),
+            Op::Chmod { path, mode } => chmod(&base.join(path), Mode::from_bits_truncate(*mode)),
+            Op::Chown {
+                path,
+                uid,
+                gid,
+                follow: true,
+            } => chown(&base.join(path), *uid, *gid),
+            Op::Chown {
+                path,
+                uid,
+                gid,
+                follow: false,
+            } => lchown(&base.join(path), *uid, *gid),
+            Op::Truncate { path, size } => truncate(&base.join(path), *size),
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = |id: Option<u32>| id.map_or(-1, i64::from);
+        match self {
+            Op::Create { path, mode } => write!(
+                f,
+                "open({}, O_CREAT | O_EXCL | O_WRONLY, {mode:#o})",
+                path.display()
+            ),
+            Op::Mkdir { path, mode } => write!(f, "mkdir({}, {mode:#o})", path.display()),
+            Op::Symlink { target, path } => {
+                write!(f, "symlink({}, {})", target.display(), path.display())
+            }
+            Op::Link { from, to } => write!(f, "link({}, {})", from.display(), to.display()),
+            Op::Rename { from, to } => write!(f, "rename({}, {})", from.display(), to.display()),
+            Op::Unlink { path } => write!(f, "unlink({})", path.display()),
+            Op::Rmdir { path } => write!(f, "rmdir({})", path.display()),
+            Op::Chmod { path, mode } => write!(f, "chmod({}, {mode:#o})", path.display()),
+            Op::Chown { path, uid, gid, .. } => write!(
+                f,
+                "{}({}, {}, {})",
+                self.name(),
+                path.display(),
+                id(uid.map(Uid::as_raw)),
+                id(gid.map(Gid::as_raw))
+            ),
+            Op::Truncate { path, size } => write!(f, "truncate({}, {size})", path.display()),
+        }
+    }
+}
@@ -21,13 +21,15 @@ use std::{
     backtrace::{Backtrace, BacktraceStatus},
     collections::HashSet,
     env::current_dir,
+    fs,
     io::{stdout, Write},
     panic::{catch_unwind, set_hook},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Mutex,
 };
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{Parser, Subcommand};
 use colored::{Color, Colorize};
 use config::Config;
 use nix::{
@@ -42,14 +44,18 @@ mod config;
 mod context;
 mod features;
 mod flags;
+mod fuzz;
 
 mod macros;
 pub(crate) use macros::*;
 
+mod outcome;
 mod test;
 mod tests;
+mod trace;
 mod utils;
 
+use context::{unlock_tree, KeepPolicy};
 use test::{FileSystemFeature, SerializedTestContext, TestCase, TestContext, TestFn};
 
 use crate::utils::chmod;
@@ -63,7 +69,10 @@ static BACKTRACE: Mutex<Option<Backtrace>> = Mutex::new(None);
     version
 )]
 struct ArgOptions {
-    #[arg(short, long, help = "Path of the configuration file")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(short, long, global = true, help = "Path of the configuration file")]
     configuration_file: Option<PathBuf>,
 
     #[arg(short, long, help = "List opt-in features")]
@@ -75,7 +84,12 @@ struct ArgOptions {
     #[arg(short, long, help = "Verbose mode")]
     verbose: bool,
 
-    #[arg(short, long, help = "Path where the test suite will be executed")]
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Path where the test suite will be executed"
+    )]
     path: Option<PathBuf>,
 
     #[arg(help = "Filter test names")]
@@ -83,6 +97,45 @@ struct ArgOptions {
 
     #[arg(short, long, help = "Path to a secondary file system")]
     secondary_fs: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Directory where a trace of the syscalls of each test is written"
+    )]
+    trace: Option<PathBuf>,
+
+    #[arg(long, help = "Keep the directories of the failed tests")]
+    keep_failed: bool,
+
+    #[arg(long, help = "Keep the directories of all the tests")]
+    keep_all: bool,
+
+    #[arg(
+        long,
+        help = "Remove the permissions and flags which prevent the removal of the kept directories"
+    )]
+    unlock_kept: bool,
+}
+
+/// Name of the directory, in the path where the test suite is executed, where test directories are kept.
+const KEPT_DIR: &str = "pjdfstest-kept";
+
+/// Which test directories are kept, and where.
+struct KeepOptions {
+    policy: KeepPolicy,
+    unlock: bool,
+    dir: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Compare random sequences of operations against a reference model of POSIX semantics
+    Fuzz(fuzz::FuzzOptions),
+    /// Run random sequences of operations on the file system and on the secondary file system,
+    /// and report every difference in behaviour
+    Diff(fuzz::diff::DiffOptions),
+    /// Replay a syscall trace recorded with --trace, and report where the results diverge
+    Replay(trace::ReplayOptions),
 }
 
 #[derive(Clone, Copy, Debug, Default)]
@@ -135,40 +188,67 @@ fn main() -> anyhow::Result<()> {
         .path
         .ok_or_else(|| anyhow::anyhow!("cannot get current dir"))
         .or_else(|_| current_dir())?;
-    let base_dir = tempdir_in(path)?;
+
+    match &args.command {
+        Some(Command::Fuzz(options)) => {
+            umask(Mode::empty());
+            return fuzz::run(options, &config, &path);
+        }
+        Some(Command::Diff(options)) => {
+            umask(Mode::empty());
+            return match &options.tests {
+                Some(patterns) => {
+                    set_hook(Box::new(|_| ()));
+                    let test_cases = select_test_cases(patterns, false);
+                    fuzz::diff::run_test_cases(&test_cases, &config, &path)
+                }
+                None => fuzz::diff::run(&options.fuzz, &config, &path),
+            };
+        }
+        Some(Command::Replay(options)) => {
+            umask(Mode::empty());
+            return trace::replay(options, &path);
+        }
+        None => (),
+    }
+
+    let base_dir = tempdir_in(&path)?;
 
     set_hook(Box::new(|_| {
         *BACKTRACE.lock().unwrap() = Some(Backtrace::capture());
     }));
 
-    let test_cases = inventory::iter::<TestCase>;
-    let test_cases: Vec<_> = test_cases
-        .into_iter()
-        .filter(|case| {
-            args.test_patterns.is_empty()
-                || args.test_patterns.iter().any(|pat| {
-                    if args.exact {
-                        case.name == pat
-                    } else {
-                        case.name.contains(pat)
-                    }
-                })
-        })
-        .map(|tc: &TestCase| TestCase {
-            // Ideally trim_start_matches could be done in test_case!, but only
-            // const functions are allowed there.
-            name: tc.name.trim_start_matches("pjdfstest::tests::"),
-            description: tc.description,
-            require_root: tc.require_root,
-            fun: tc.fun,
-            required_features: tc.required_features,
-            guards: tc.guards,
-        })
-        .collect();
+    let test_cases = select_test_cases(&args.test_patterns, args.exact);
+
+    if let Some(trace_dir) = &args.trace {
+        fs::create_dir_all(trace_dir)?;
+    }
+
+    let keep = KeepOptions {
+        policy: if args.keep_all {
+            KeepPolicy::Always
+        } else if args.keep_failed {
+            KeepPolicy::Failed
+        } else {
+            KeepPolicy::Never
+        },
+        unlock: args.unlock_kept,
+        dir: path.join(KEPT_DIR),
+    };
+    if keep.policy != KeepPolicy::Never {
+        fs::create_dir_all(&keep.dir)?;
+    }
 
     umask(Mode::empty());
 
-    let overall_result = run_test_cases(&test_cases, args.verbose, &config, base_dir)?;
+    let overall_result = run_test_cases(
+        &test_cases,
+        args.verbose,
+        &config,
+        base_dir,
+        args.trace.as_deref(),
+        &keep,
+    )?;
 
     println!(
         "\n{}: {} {}, {} {}, {} {}, {} {}, {} total",
@@ -198,6 +278,8 @@ fn run_test_cases(
     verbose: bool,
     config: &Config,
     base_dir: TempDir,
+    trace_dir: Option<&Path>,
+    keep: &KeepOptions,
 ) -> Result<OverallResult, anyhow::Error> {
     let mut failed_tests_count: usize = 0;
     let mut succeeded_tests_count: usize = 0;
@@ -205,54 +287,15 @@ fn run_test_cases(
     let mut unexpected_success_count: usize = 0;
     let mut expected_fail_count: usize = 0;
 
-    let is_root = Uid::current().is_root();
-
-    let enabled_features: HashSet<_> = config.features.fs_features.keys().collect();
-
-    let entries = &config.dummy_auth.entries;
-
     for test_case in test_cases {
-        //TODO: There's probably a better way to do this...
-        let mut should_skip = test_case.require_root && !is_root;
-        let mut skip_reasons = Vec::<String>::new();
-
         let expect_fail = config.settings.expected_failures.contains(test_case.name);
-        if should_skip {
-            skip_reasons.push(String::from("requires root privileges"));
-        }
-
-        let features: HashSet<_> = test_case.required_features.iter().collect();
-        let missing_features: Vec<_> = features.difference(&enabled_features).collect();
-        if !missing_features.is_empty() {
-            should_skip = true;
-
-            let features = &missing_features
-                .iter()
-                .map(|feature| format!("{}", feature))
-                .collect::<Vec<_>>()
-                .join(", ");
-
-            skip_reasons.push(format!("requires features: {}", features));
-        }
 
         let temp_dir = tempdir_in(base_dir.path()).unwrap();
         // FIX: some tests need a 0o755 base dir
         chmod(temp_dir.path(), Mode::from_bits_truncate(0o755)).unwrap();
 
-        if test_case
-            .guards
-            .iter()
-            .any(|guard| guard(config, temp_dir.path()).is_err())
-        {
-            should_skip = true;
-            skip_reasons.extend(
-                test_case
-                    .guards
-                    .iter()
-                    .filter_map(|guard| guard(config, base_dir.path()).err())
-                    .map(|err| err.to_string()),
-            );
-        }
+        let skip_reasons = skip_reasons(test_case, config, temp_dir.path());
+        let should_skip = !skip_reasons.is_empty();
 
         stdout().lock().flush()?;
 
@@ -268,18 +311,34 @@ fn run_test_cases(
             continue;
         }
 
-        let result = catch_unwind(|| match test_case.fun {
-            TestFn::NonSerialized(fun) => {
-                let mut context = TestContext::new(config, entries, temp_dir.path());
+        trace::start(temp_dir.path(), trace_dir.is_some());
 
-                (fun)(&mut context)
-            }
-            TestFn::Serialized(fun) => {
-                let mut context = SerializedTestContext::new(config, entries, temp_dir.path());
+        // The permissions and flags of kept directories are only removed if asked
+        let context_keep = if keep.unlock {
+            KeepPolicy::Never
+        } else {
+            keep.policy
+        };
+        let result = execute_test_case(test_case, config, temp_dir.path(), context_keep);
 
-                (fun)(&mut context)
+        // Failing to keep the directory of one test should not abort the whole run
+        let (kept_dir, keep_error) = if keep.policy.keeps(result.is_err()) {
+            match keep_test_dir(temp_dir, &keep.dir, test_case.name) {
+                Ok(dir) => (Some(dir), None),
+                Err(e) => (None, Some(e)),
             }
-        });
+        } else {
+            (None, None)
+        };
+
+        let trace = trace::stop();
+        let observations = outcome::take();
+        if let (Some(trace_dir), Some(trace)) = (trace_dir, &trace) {
+            fs::write(
+                trace_dir.join(format!("{}.trace", test_case.name)),
+                format!("# pjdfstest trace: {}\n{}", test_case.name, trace.encode()),
+            )?;
+        }
 
         let error_info = match result {
             Ok(_) if !expect_fail => {
@@ -320,18 +379,32 @@ fn run_test_cases(
                 };
                 println!("{:73} {}", test_case.name.blue(), "FAILED".red());
                 failed_tests_count += 1;
-                Some((panic_information, backtrace))
+                Some((panic_information, backtrace, trace, kept_dir))
             }
         };
         if verbose && !test_case.description.is_empty() {
             println!("\t{}", test_case.description);
         }
-        if let Some((panic_information, backtrace)) = error_info {
+        if verbose || error_info.is_some() {
+            for observation in &observations {
+                println!("\t{} {}", "Observed".cyan(), observation);
+            }
+        }
+        if let Some((panic_information, backtrace, trace, kept_dir)) = error_info {
             println!("\t{}", panic_information);
+            if let Some(kept_dir) = kept_dir {
+                println!("\tDirectory kept in {}", kept_dir.display());
+            }
+            if let Some(trace) = trace {
+                print!("{}", trace.log());
+            }
             if let Some(backtrace) = backtrace {
                 println!("Backtrace:\n{}", backtrace);
             }
         }
+        if let Some(e) = keep_error {
+            println!("\t{} {e:#}", "Cannot keep the directory:".red());
+        }
 
         if verbose && !test_case.description.is_empty() {
             println!("\t{}", test_case.description);
@@ -346,3 +419,115 @@ fn run_test_cases(
         expect_fail: expected_fail_count,
     })
 }
+
+/// Return the registered test cases whose name matches one of `patterns`,
+/// or all of them if there is none.
+fn select_test_cases(patterns: &[String], exact: bool) -> Vec<TestCase> {
+    inventory::iter::<TestCase>
+        .into_iter()
+        .filter(|case| {
+            patterns.is_empty()
+                || patterns.iter().any(|pat| {
+                    if exact {
+                        case.name == pat
+                    } else {
+                        case.name.contains(pat)
+                    }
+                })
+        })
+        .map(|tc: &TestCase| TestCase {
+            // Ideally trim_start_matches could be done in test_case!, but only
+            // const functions are allowed there.
+            name: tc.name.trim_start_matches("pjdfstest::tests::"),
+            description: tc.description,
+            require_root: tc.require_root,
+            fun: tc.fun,
+            required_features: tc.required_features,
+            guards: tc.guards,
+        })
+        .collect()
+}
+
+/// Return the reasons why a test case cannot run in `dir`, which is empty if it can.
+fn skip_reasons(test_case: &TestCase, config: &Config, dir: &Path) -> Vec<String> {
+    let mut skip_reasons = Vec::<String>::new();
+
+    if test_case.require_root && !Uid::current().is_root() {
+        skip_reasons.push(String::from("requires root privileges"));
+    }
+
+    let enabled_features: HashSet<_> = config.features.fs_features.keys().collect();
+    let features: HashSet<_> = test_case.required_features.iter().collect();
+    let missing_features: Vec<_> = features.difference(&enabled_features).collect();
+    if !missing_features.is_empty() {
+        let features = &missing_features
+            .iter()
+            .map(|feature| format!("{}", feature))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        skip_reasons.push(format!("requires features: {}", features));
+    }
+
+    skip_reasons.extend(
+        test_case
+            .guards
+            .iter()
+            .filter_map(|guard| guard(config, dir).err())
+            .map(|err| err.to_string()),
+    );
+
+    skip_reasons
+}
+
+/// Run a test case in `dir`, and return the panic payload if it failed.
+fn execute_test_case(
+    test_case: &TestCase,
+    config: &Config,
+    dir: &Path,
+    keep: KeepPolicy,
+) -> std::thread::Result<()> {
+    let entries = &config.dummy_auth.entries;
+
+    catch_unwind(|| match test_case.fun {
+        TestFn::NonSerialized(fun) => {
+            let mut context = TestContext::new(config, entries, dir);
+            context.set_keep_policy(keep);
+
+            (fun)(&mut context)
+        }
+        TestFn::Serialized(fun) => {
+            let mut context = SerializedTestContext::new(config, entries, dir);
+            context.set_keep_policy(keep);
+
+            (fun)(&mut context)
+        }
+    })
+}
+
+/// Move the directory of a test to `kept_dir`, under the name of the test, and return its new path.
+fn keep_test_dir(temp_dir: TempDir, kept_dir: &Path, name: &str) -> anyhow::Result<PathBuf> {
+    let mut dest = kept_dir.join(name);
+    // The directory of a previous run might have been kept without being unlocked
+    if dest.symlink_metadata().is_ok() {
+        unlock_tree(&dest);
+        if let Err(e) = fs::remove_dir_all(&dest) {
+            let stale = dest;
+            dest = (1..)
+                .map(|i| kept_dir.join(format!("{name}.{i}")))
+                .find(|path| path.symlink_metadata().is_err())
+                .unwrap();
+            eprintln!(
+                "{} cannot remove previously kept {}: {e}, keeping this one as {}",
+                "warning:".yellow().bold(),
+                stale.display(),
+                dest.display()
+            );
+        }
+    }
+    let src = temp_dir.into_path();
+    fs::rename(&src, &dest)
+        .with_context(|| format!("cannot move {} to {}", src.display(), dest.display()))?;
+
+    Ok(dest)
+}
@@ -0,0 +1,82 @@
+//! Implementation-defined outcomes of the syscalls performed by the tests.
+//!
+//! POSIX allows some syscalls to either succeed or fail with one of several errors,
+//! depending on the implementation. Tests check such results with [`assert_outcome`],
+//! which records the observed outcome so that the runner can show it
+//! in verbose mode and when the test fails.
+
+use std::{fmt, sync::Mutex};
+
+use nix::errno::Errno;
+
+static OBSERVED: Mutex<Vec<Observation>> = Mutex::new(Vec::new());
+
+/// Outcome of a syscall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Error(Errno),
+}
+
+impl<T> From<&nix::Result<T>> for Outcome {
+    fn from(result: &nix::Result<T>) -> Self {
+        match result {
+            Ok(_) => Outcome::Success,
+            Err(e) => Outcome::Error(*e),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Success => write!(f, "success"),
+            Outcome::Error(e) => write!(f, "{e:?}"),
+        }
+    }
+}
+
+/// Outcome observed for an operation.
+pub struct Observation {
+    pub operation: String,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Observation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.operation, self.outcome)
+    }
+}
+
+/// Assert that the result of `operation` is one of the `allowed` outcomes,
+/// record the observed one and return the result.
+#[track_caller]
+pub fn assert_outcome<T>(
+    operation: &str,
+    result: nix::Result<T>,
+    allowed: &[Outcome],
+) -> nix::Result<T> {
+    let outcome = Outcome::from(&result);
+    // The outcome is recorded first, to be shown even if it is unexpected
+    OBSERVED.lock().unwrap().push(Observation {
+        operation: operation.to_owned(),
+        outcome,
+    });
+
+    assert!(
+        allowed.contains(&outcome),
+        "{operation} returned {outcome}, expected one of: {}",
+        allowed
+            .iter()
+            .map(Outcome::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    result
+}
+
+/// Return and clear the outcomes observed since the last call.
+pub fn take() -> Vec<Observation> {
+    std::mem::take(&mut OBSERVED.lock().unwrap())
+}
@@ -2,16 +2,13 @@ use crate::{
     context::{FileType, SerializedTestContext},
     test::TestContext,
     tests::{assert_ctime_changed, assert_ctime_unchanged},
-    utils::{chmod, ALLPERMS},
+    utils::{chmod, chown, ALLPERMS},
 };
 
 #[cfg(lchmod)]
 use crate::utils::lchmod;
 
-use nix::{
-    sys::stat::{lstat, stat, Mode},
-    unistd::chown,
-};
+use nix::sys::stat::{lstat, stat, Mode};
 
 use super::errors::{
     efault::efault_path_test_case,
@@ -24,6 +21,9 @@ use super::errors::{
     erofs::erofs_named_test_case,
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{eperm_append_test_case, eperm_immutable_test_case};
+
 #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
 const ALLPERMS_STICKY: nix::libc::mode_t = ALLPERMS | Mode::S_ISVTX.bits();
 
@@ -142,6 +142,14 @@ eloop_final_comp_test_case!(chmod(~path, Mode::empty()));
 // chmod/09.t
 erofs_named_test_case!(chmod(~path, Mode::empty()));
 
+// chmod/08.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(chmod(~path, Mode::from_bits_truncate(0o600)) => [Regular, Dir]);
+
+// chmod/08.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(chmod(~path, Mode::from_bits_truncate(0o600)) => [Regular, Dir]);
+
 // chmod/10.t
 efault_path_test_case!(chmod, |ptr| nix::libc::chmod(ptr, 0));
 
@@ -215,6 +223,14 @@ mod lchmod {
     // chmod/09.t
     erofs_named_test_case!(lchmod(~path, Mode::empty()));
 
+    // chmod/08.t
+    #[cfg(inode_flags)]
+    eperm_immutable_test_case!(lchmod(~path, Mode::from_bits_truncate(0o600)) => [Regular, Dir]);
+
+    // chmod/08.t
+    #[cfg(inode_flags)]
+    eperm_append_test_case!(lchmod(~path, Mode::from_bits_truncate(0o600)) => [Regular, Dir]);
+
     // chmod/10.t
     // TODO: lchmod is missing in libc
     efault_path_test_case!(lchmod, |ptr| nix::libc::fchmodat(
@@ -1,6 +1,17 @@
-use nix::unistd::chown;
+use std::path::Path;
 
-use crate::{context::TestContext, utils::lchown};
+use nix::{
+    errno::Errno,
+    sys::stat::{lstat, stat, FileStat, Mode},
+    unistd::{pathconf, Gid, PathconfVar, Uid},
+};
+
+use crate::{
+    context::{FileType, SerializedTestContext, TestContext},
+    utils::{chmod, chown, lchown},
+};
+
+use super::{assert_times_changed, assert_times_unchanged, CTIME};
 
 use super::errors::efault::efault_path_test_case;
 use super::errors::eloop::{eloop_comp_test_case, eloop_final_comp_test_case};
@@ -11,11 +22,336 @@ use super::errors::enoent::{
 use super::errors::enotdir::enotdir_comp_test_case;
 use super::errors::erofs::erofs_named_test_case;
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{eperm_append_test_case, eperm_immutable_test_case};
+
 fn chown_wrapper(ctx: &mut TestContext, path: &std::path::Path) -> nix::Result<()> {
     let user = ctx.get_new_user();
     chown(path, Some(user.uid), None)
 }
 
+/// Syscall which changes the ownership of a file, either following symlinks or not,
+/// so that the same semantics can be checked for `chown` and `lchown`.
+#[derive(Clone, Copy)]
+struct Chown {
+    syscall: fn(&Path, Option<Uid>, Option<Gid>) -> nix::Result<()>,
+    no_follow_symlink: bool,
+}
+
+const CHOWN: Chown = Chown {
+    syscall: chown::<Path>,
+    no_follow_symlink: false,
+};
+
+impl Chown {
+    fn call(&self, path: &Path, owner: Option<Uid>, group: Option<Gid>) -> nix::Result<()> {
+        (self.syscall)(path, owner, group)
+    }
+
+    fn stat(&self, path: &Path) -> FileStat {
+        if self.no_follow_symlink {
+            lstat(path)
+        } else {
+            stat(path)
+        }
+        .unwrap()
+    }
+
+    /// Return the owner and the group of the file.
+    fn ownership(&self, path: &Path) -> (Uid, Gid) {
+        let file_stat = self.stat(path);
+        (
+            Uid::from_raw(file_stat.st_uid),
+            Gid::from_raw(file_stat.st_gid),
+        )
+    }
+}
+
+fn assert_root_changes_ownership(ctx: &mut SerializedTestContext, ft: FileType, chown: Chown) {
+    let user = ctx.get_new_user();
+    let group = ctx.get_new_group();
+    let path = ctx.create(ft).unwrap();
+
+    chown.call(&path, Some(user.uid), Some(group.gid)).unwrap();
+    assert_eq!(chown.ownership(&path), (user.uid, group.gid));
+
+    chown
+        .call(&path, Some(Uid::from_raw(0)), Some(Gid::from_raw(0)))
+        .unwrap();
+    assert_eq!(chown.ownership(&path), (Uid::from_raw(0), Gid::from_raw(0)));
+}
+
+// chown/00.t
+crate::test_case! {
+    /// The super-user can always change the ownership of a file
+    root_changes_ownership, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn root_changes_ownership(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_root_changes_ownership(ctx, ft, CHOWN);
+}
+
+// chown/00.t
+crate::test_case! {
+    /// chown changes the ownership of the target of a symlink, not of the symlink itself
+    follow_symlink, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn follow_symlink(ctx: &mut SerializedTestContext, ft: FileType) {
+    let user = ctx.get_new_user();
+    let group = ctx.get_new_group();
+    let path = ctx.create(ft).unwrap();
+    let link = ctx.create(FileType::Symlink(Some(path.clone()))).unwrap();
+    let link_stat = lstat(&link).unwrap();
+
+    chown(&link, Some(user.uid), Some(group.gid)).unwrap();
+    assert_eq!(CHOWN.ownership(&path), (user.uid, group.gid));
+    assert_eq!(CHOWN.ownership(&link), (user.uid, group.gid));
+
+    let new_link_stat = lstat(&link).unwrap();
+    assert_eq!(
+        (link_stat.st_uid, link_stat.st_gid),
+        (new_link_stat.st_uid, new_link_stat.st_gid)
+    );
+}
+
+fn assert_owner_changes_group(ctx: &mut SerializedTestContext, ft: FileType, chown: Chown) {
+    let user = ctx.get_new_user();
+    let other_group = ctx.get_new_group();
+    let path = ctx.create(ft).unwrap();
+    chown.call(&path, Some(user.uid), Some(user.gid)).unwrap();
+
+    ctx.as_user(user, Some(&[user.gid, other_group.gid]), || {
+        // Supplementary group
+        assert!(chown.call(&path, None, Some(other_group.gid)).is_ok());
+        assert_eq!(chown.ownership(&path), (user.uid, other_group.gid));
+
+        // Effective group, without changing the owner
+        assert!(chown.call(&path, Some(user.uid), Some(user.gid)).is_ok());
+        assert_eq!(chown.ownership(&path), (user.uid, user.gid));
+    });
+}
+
+// chown/00.t
+crate::test_case! {
+    /// A non-super-user owner can change the group of a file
+    /// to its effective group or to one of its supplementary groups
+    owner_changes_group, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn owner_changes_group(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_owner_changes_group(ctx, ft, CHOWN);
+}
+
+fn assert_unchanged_ownership_not_owner(
+    ctx: &mut SerializedTestContext,
+    ft: FileType,
+    chown: Chown,
+) {
+    let owner = ctx.get_new_user();
+    let user = ctx.get_new_user();
+    let path = ctx.create(ft).unwrap();
+    chown.call(&path, Some(owner.uid), Some(owner.gid)).unwrap();
+
+    ctx.as_user(user, None, || {
+        assert!(chown.call(&path, None, None).is_ok());
+    });
+    assert_eq!(chown.ownership(&path), (owner.uid, owner.gid));
+}
+
+// chown/00.t
+crate::test_case! {
+    /// chown succeeds if the user is not the owner of the file
+    /// but both owner and group are -1
+    unchanged_ownership_not_owner, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn unchanged_ownership_not_owner(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_unchanged_ownership_not_owner(ctx, ft, CHOWN);
+}
+
+fn assert_update_ctime(ctx: &mut SerializedTestContext, ft: FileType, chown: Chown) {
+    let user = ctx.get_new_user();
+    let other_group = ctx.get_new_group();
+    let path = ctx.create(ft).unwrap();
+
+    assert_times_changed()
+        .path(&path, CTIME)
+        .execute(ctx, chown.no_follow_symlink, || {
+            assert!(chown.call(&path, Some(user.uid), Some(user.gid)).is_ok());
+        });
+
+    assert_times_changed()
+        .path(&path, CTIME)
+        .execute(ctx, chown.no_follow_symlink, || {
+            ctx.as_user(user, Some(&[user.gid, other_group.gid]), || {
+                assert!(chown.call(&path, None, Some(other_group.gid)).is_ok());
+            });
+        });
+}
+
+// chown/00.t
+crate::test_case! {
+    /// chown updates ctime when it succeeds
+    update_ctime, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn update_ctime(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_update_ctime(ctx, ft, CHOWN);
+}
+
+#[cfg(target_os = "linux")]
+fn assert_unchanged_ownership_update_ctime(ctx: &mut TestContext, ft: FileType, chown: Chown) {
+    let path = ctx.create(ft).unwrap();
+    let ownership = chown.ownership(&path);
+
+    assert_times_changed()
+        .path(&path, CTIME)
+        .execute(ctx, chown.no_follow_symlink, || {
+            assert!(chown.call(&path, None, None).is_ok());
+        });
+    assert_eq!(chown.ownership(&path), ownership);
+}
+
+// chown/00.t
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// chown updates ctime on Linux even if both owner and group are -1,
+    /// though POSIX does not require it
+    unchanged_ownership_update_ctime => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+#[cfg(target_os = "linux")]
+fn unchanged_ownership_update_ctime(ctx: &mut TestContext, ft: FileType) {
+    assert_unchanged_ownership_update_ctime(ctx, ft, CHOWN);
+}
+
+fn assert_eperm_not_owner(ctx: &mut SerializedTestContext, ft: FileType, chown: Chown) {
+    let owner = ctx.get_new_user();
+    let user = ctx.get_new_user();
+    let path = ctx.create(ft).unwrap();
+    chown.call(&path, Some(owner.uid), Some(owner.gid)).unwrap();
+
+    assert_times_unchanged()
+        .path(&path, CTIME)
+        .execute(ctx, chown.no_follow_symlink, || {
+            ctx.as_user(user, None, || {
+                assert_eq!(
+                    chown.call(&path, Some(user.uid), Some(user.gid)),
+                    Err(Errno::EPERM)
+                );
+                assert_eq!(
+                    chown.call(&path, Some(owner.uid), Some(owner.gid)),
+                    Err(Errno::EPERM)
+                );
+                assert_eq!(chown.call(&path, None, Some(user.gid)), Err(Errno::EPERM));
+            });
+            // The owner is not a member of the group
+            ctx.as_user(owner, None, || {
+                assert_eq!(chown.call(&path, None, Some(user.gid)), Err(Errno::EPERM));
+            });
+        });
+    assert_eq!(chown.ownership(&path), (owner.uid, owner.gid));
+}
+
+// chown/07.t
+crate::test_case! {
+    /// chown returns EPERM if the operation would change the ownership,
+    /// but the effective user ID is not the super-user and the process is not the owner of the file,
+    /// or the group is not one of the groups of the owner
+    eperm_not_owner, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn eperm_not_owner(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_eperm_not_owner(ctx, ft, CHOWN);
+}
+
+fn assert_chown_restricted(ctx: &mut SerializedTestContext, ft: FileType, chown: Chown) {
+    let owner = ctx.get_new_user();
+    let user = ctx.get_new_user();
+    let path = ctx.create(ft).unwrap();
+    chown.call(&path, Some(owner.uid), Some(owner.gid)).unwrap();
+
+    // The restriction applies to the files of the directory
+    let restricted = pathconf(ctx.base_path(), PathconfVar::_POSIX_CHOWN_RESTRICTED)
+        .unwrap()
+        .is_some_and(|value| value != -1);
+
+    ctx.as_user(owner, None, || {
+        let res = chown.call(&path, Some(user.uid), None);
+        if restricted {
+            assert_eq!(res, Err(Errno::EPERM));
+        } else {
+            assert_eq!(res, Ok(()));
+        }
+    });
+
+    let expected_owner = if restricted { owner.uid } else { user.uid };
+    assert_eq!(chown.ownership(&path), (expected_owner, owner.gid));
+}
+
+// chown/07.t
+crate::test_case! {
+    /// A non-super-user owner can give away a file only if _POSIX_CHOWN_RESTRICTED
+    /// is not in effect, as reported by pathconf
+    chown_restricted, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket]
+}
+fn chown_restricted(ctx: &mut SerializedTestContext, ft: FileType) {
+    assert_chown_restricted(ctx, ft, CHOWN);
+}
+
+// chown/00.t
+crate::test_case! {
+    /// The S_ISUID and S_ISGID bits of an executable regular file are cleared
+    /// when its group is changed by a non-super-user, unless both owner and group are -1
+    clear_suid_sgid_executable, serialized, root
+}
+fn clear_suid_sgid_executable(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    let other_group = ctx.get_new_group();
+    let path = ctx.create(FileType::Regular).unwrap();
+    chown(&path, Some(user.uid), Some(user.gid)).unwrap();
+    let mode = Mode::from_bits_truncate(0o6555);
+
+    chmod(&path, mode).unwrap();
+    ctx.as_user(user, Some(&[user.gid, other_group.gid]), || {
+        chown(&path, Some(user.uid), Some(other_group.gid)).unwrap();
+    });
+    assert_eq!(stat(&path).unwrap().st_mode & 0o7777, 0o555);
+
+    chmod(&path, mode).unwrap();
+    ctx.as_user(user, Some(&[user.gid, other_group.gid]), || {
+        chown(&path, None, Some(user.gid)).unwrap();
+    });
+    assert_eq!(stat(&path).unwrap().st_mode & 0o7777, 0o555);
+
+    // The bits may be cleared, or not
+    chmod(&path, mode).unwrap();
+    ctx.as_user(user, None, || {
+        chown(&path, None, None).unwrap();
+    });
+    assert!(matches!(
+        stat(&path).unwrap().st_mode & 0o7777,
+        0o6555 | 0o555
+    ));
+}
+
+// chown/00.t
+crate::test_case! {
+    /// The S_ISUID bit of a non-executable regular file is cleared
+    /// when its group is changed by a non-super-user
+    clear_suid_regular, serialized, root
+}
+fn clear_suid_regular(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    let other_group = ctx.get_new_group();
+    let path = ctx.create(FileType::Regular).unwrap();
+    chown(&path, Some(user.uid), Some(user.gid)).unwrap();
+
+    chmod(&path, Mode::from_bits_truncate(0o6444)).unwrap();
+    ctx.as_user(user, Some(&[user.gid, other_group.gid]), || {
+        chown(&path, None, Some(other_group.gid)).unwrap();
+    });
+
+    // Without group-execute permission, S_ISGID denotes mandatory locking on some systems,
+    // in which case it is preserved
+    let mode = stat(&path).unwrap().st_mode & 0o7777;
+    assert!(matches!(mode, 0o444 | 0o2444), "{mode:#o}");
+}
+
 // chown/01.t
 enotdir_comp_test_case!(chown, chown_wrapper);
 
@@ -43,6 +379,14 @@ eloop_final_comp_test_case!(chown, chown_wrapper);
 // chown/09.t
 erofs_named_test_case!(chown, chown_wrapper);
 
+// chown/08.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(chown, chown_wrapper => [Regular, Dir]);
+
+// chown/08.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(chown, chown_wrapper => [Regular, Dir]);
+
 // chown/10.t
 efault_path_test_case!(chown, |ptr| nix::libc::chown(ptr, 0, 0));
 
@@ -57,6 +401,82 @@ mod lchown {
         lchown(path, Some(user.uid), Some(user.gid))
     }
 
+    const LCHOWN: Chown = Chown {
+        syscall: lchown::<Path>,
+        no_follow_symlink: true,
+    };
+
+    // chown/00.t
+    crate::test_case! {
+        /// The super-user can always change the ownership of a file
+        root_changes_ownership, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn root_changes_ownership(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_root_changes_ownership(ctx, ft, LCHOWN);
+    }
+
+    // chown/00.t
+    crate::test_case! {
+        /// A non-super-user owner can change the group of a file
+        /// to its effective group or to one of its supplementary groups
+        owner_changes_group, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn owner_changes_group(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_owner_changes_group(ctx, ft, LCHOWN);
+    }
+
+    // chown/00.t
+    crate::test_case! {
+        /// lchown succeeds if the user is not the owner of the file
+        /// but both owner and group are -1
+        unchanged_ownership_not_owner, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn unchanged_ownership_not_owner(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_unchanged_ownership_not_owner(ctx, ft, LCHOWN);
+    }
+
+    // chown/00.t
+    crate::test_case! {
+        /// lchown updates ctime when it succeeds
+        update_ctime, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn update_ctime(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_update_ctime(ctx, ft, LCHOWN);
+    }
+
+    // chown/00.t
+    #[cfg(target_os = "linux")]
+    crate::test_case! {
+        /// lchown updates ctime on Linux even if both owner and group are -1,
+        /// though POSIX does not require it
+        unchanged_ownership_update_ctime => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    #[cfg(target_os = "linux")]
+    fn unchanged_ownership_update_ctime(ctx: &mut TestContext, ft: FileType) {
+        assert_unchanged_ownership_update_ctime(ctx, ft, LCHOWN);
+    }
+
+    // chown/07.t
+    crate::test_case! {
+        /// lchown returns EPERM if the operation would change the ownership,
+        /// but the effective user ID is not the super-user and the process is not the owner of the file,
+        /// or the group is not one of the groups of the owner
+        eperm_not_owner, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn eperm_not_owner(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_eperm_not_owner(ctx, ft, LCHOWN);
+    }
+
+    // chown/07.t
+    crate::test_case! {
+        /// A non-super-user owner can give away a file only if _POSIX_CHOWN_RESTRICTED
+        /// is not in effect, as reported by pathconf
+        chown_restricted, serialized, root => [Regular, Dir, Fifo, Block, Char, Socket, Symlink(None)]
+    }
+    fn chown_restricted(ctx: &mut SerializedTestContext, ft: FileType) {
+        assert_chown_restricted(ctx, ft, LCHOWN);
+    }
+
     // chown/01.t
     enotdir_comp_test_case!(lchown, lchown_wrapper);
 
@@ -76,6 +496,14 @@ mod lchown {
     // chown/09.t
     erofs_named_test_case!(lchown, lchown_wrapper);
 
+    // chown/08.t
+    #[cfg(inode_flags)]
+    eperm_immutable_test_case!(lchown, lchown_wrapper => [Regular, Dir]);
+
+    // chown/08.t
+    #[cfg(inode_flags)]
+    eperm_append_test_case!(lchown, lchown_wrapper => [Regular, Dir]);
+
     // chown/10.t
     efault_path_test_case!(lchown, |ptr| nix::libc::lchown(ptr, 0, 0));
 }
@@ -0,0 +1,265 @@
+//! Tests for the `copy_file_range` syscall.
+
+use std::os::fd::{AsFd, AsRawFd};
+
+use nix::{
+    errno::Errno,
+    fcntl::{copy_file_range, OFlag},
+    libc::off_t,
+    sys::{
+        stat::{fstat, Mode},
+        uio::pwrite,
+    },
+    unistd::{lseek, Whence},
+};
+
+use crate::{
+    context::{FileBuilder, FileType, TestContext},
+    test::FileSystemFeature,
+    tests::{
+        assert_times_changed,
+        data::{create_with, pattern, read_all},
+        CTIME, MTIME,
+    },
+    utils::open,
+};
+
+/// Size of the source files.
+const SIZE: usize = 100_000;
+
+/// Call `copy_file_range` until `len` bytes are copied or the end of the source file is reached,
+/// and return the number of copied bytes.
+fn copy_all<Fd1: AsFd, Fd2: AsFd>(
+    fd_in: Fd1,
+    mut off_in: i64,
+    fd_out: Fd2,
+    mut off_out: i64,
+    len: usize,
+) -> nix::Result<usize> {
+    let mut copied = 0;
+    while copied < len {
+        let n = copy_file_range(
+            &fd_in,
+            Some(&mut off_in),
+            &fd_out,
+            Some(&mut off_out),
+            len - copied,
+        )?;
+        if n == 0 {
+            break;
+        }
+        copied += n;
+    }
+
+    Ok(copied)
+}
+
+crate::test_case! {
+    /// copy_file_range copies the exact bytes of the requested range at the requested offset
+    byte_exact, FileSystemFeature::CopyFileRange
+}
+fn byte_exact(ctx: &mut TestContext) {
+    let data = pattern(SIZE as off_t, None);
+    let (_, src) = create_with(ctx, &data);
+
+    for (off_in, off_out, len) in [
+        (0, 0, SIZE),
+        (1, 7, 4097),
+        (65536, 0, 30000),
+        (12345, 99999, 5000),
+    ] {
+        let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+        assert_eq!(
+            copy_all(&src, off_in as i64, &dest, off_out as i64, len),
+            Ok(len)
+        );
+
+        let mut expected = vec![0; off_out];
+        expected.extend_from_slice(&data[off_in..off_in + len]);
+        assert_eq!(
+            read_all(&dest),
+            expected,
+            "offset in {off_in}, offset out {off_out}, length {len}"
+        );
+    }
+}
+
+crate::test_case! {
+    /// copy_file_range stops at the end of the source file
+    short_copy_at_eof, FileSystemFeature::CopyFileRange
+}
+fn short_copy_at_eof(ctx: &mut TestContext) {
+    let data = pattern(SIZE as off_t, None);
+    let (_, src) = create_with(ctx, &data);
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    let off_in = SIZE - 1000;
+    assert_eq!(copy_all(&src, off_in as i64, &dest, 0, 5000), Ok(1000));
+    assert_eq!(read_all(&dest), &data[off_in..]);
+
+    let mut off_in = SIZE as i64;
+    let mut off_out = 0;
+    assert_eq!(
+        copy_file_range(&src, Some(&mut off_in), &dest, Some(&mut off_out), 1000),
+        Ok(0)
+    );
+}
+
+crate::test_case! {
+    /// copy_file_range advances the file offsets only when no explicit offset is given
+    file_offsets, FileSystemFeature::CopyFileRange
+}
+fn file_offsets(ctx: &mut TestContext) {
+    let data = pattern(SIZE as off_t, None);
+    let (_, src) = create_with(ctx, &data);
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    lseek(src.as_raw_fd(), 10, Whence::SeekSet).unwrap();
+    let copied = copy_all(&src, 10, &dest, 0, 100).unwrap();
+    assert_eq!(copied, 100);
+    assert_eq!(lseek(src.as_raw_fd(), 0, Whence::SeekCur), Ok(10));
+    assert_eq!(lseek(dest.as_raw_fd(), 0, Whence::SeekCur), Ok(0));
+
+    let copied = copy_file_range(&src, None, &dest, None, 100).unwrap();
+    assert!(copied > 0);
+    assert_eq!(
+        lseek(src.as_raw_fd(), 0, Whence::SeekCur),
+        Ok(10 + copied as off_t)
+    );
+    assert_eq!(
+        lseek(dest.as_raw_fd(), 0, Whence::SeekCur),
+        Ok(copied as off_t)
+    );
+    assert_eq!(&read_all(&dest)[..copied], &data[10..10 + copied]);
+}
+
+crate::test_case! {
+    /// copy_file_range updates ctime and mtime of the destination file
+    changes_times, FileSystemFeature::CopyFileRange
+}
+fn changes_times(ctx: &mut TestContext) {
+    let (_, src) = create_with(ctx, &pattern(SIZE as off_t, None));
+    let (path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    assert_times_changed()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            assert_eq!(copy_all(&src, 0, &dest, 0, SIZE), Ok(SIZE));
+        });
+}
+
+crate::test_case! {
+    /// copy_file_range copies between non-overlapping ranges of the same file,
+    /// and returns EINVAL if the ranges overlap
+    same_file, FileSystemFeature::CopyFileRange
+}
+fn same_file(ctx: &mut TestContext) {
+    let data = pattern(SIZE as off_t, None);
+    let (_, fd) = create_with(ctx, &data);
+
+    let mut off_in = 0;
+    let mut off_out = 100;
+    assert_eq!(
+        copy_file_range(&fd, Some(&mut off_in), &fd, Some(&mut off_out), 1000),
+        Err(Errno::EINVAL)
+    );
+    assert_eq!(read_all(&fd), data);
+
+    let len = 1000;
+    let off_out = SIZE - len;
+    assert_eq!(copy_all(&fd, 0, &fd, off_out as i64, len), Ok(len));
+
+    let mut expected = data.clone();
+    expected[off_out..].copy_from_slice(&data[..len]);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// copy_file_range returns EBADF if the source is not open for reading,
+    /// or the destination is not open for writing or open in append mode
+    ebadf, FileSystemFeature::CopyFileRange
+}
+fn ebadf(ctx: &mut TestContext) {
+    let (src_path, src) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    assert_eq!(
+        pwrite(&src, &pattern(SIZE as off_t, None), 0).unwrap(),
+        SIZE
+    );
+    let (dest_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    let src_wronly = open(&src_path, OFlag::O_WRONLY, Mode::empty()).unwrap();
+    assert_eq!(copy_all(&src_wronly, 0, &dest, 0, 100), Err(Errno::EBADF));
+
+    let dest_rdonly = open(&dest_path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    assert_eq!(copy_all(&src, 0, &dest_rdonly, 0, 100), Err(Errno::EBADF));
+
+    let dest_append = open(&dest_path, OFlag::O_WRONLY | OFlag::O_APPEND, Mode::empty()).unwrap();
+    assert_eq!(copy_all(&src, 0, &dest_append, 0, 100), Err(Errno::EBADF));
+
+    assert_eq!(fstat(dest.as_raw_fd()).unwrap().st_size, 0);
+}
+
+crate::test_case! {
+    /// copy_file_range returns EISDIR if the source is a directory
+    eisdir, FileSystemFeature::CopyFileRange
+}
+fn eisdir(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let dir = open(&dir, OFlag::O_RDONLY | OFlag::O_DIRECTORY, Mode::empty()).unwrap();
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    assert_eq!(copy_all(&dir, 0, &dest, 0, 100), Err(Errno::EISDIR));
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// copy_file_range returns EINVAL if flags is not 0
+    einval_flags, FileSystemFeature::CopyFileRange
+}
+#[cfg(target_os = "linux")]
+fn einval_flags(ctx: &mut TestContext) {
+    let (_, src) = create_with(ctx, &pattern(SIZE as off_t, None));
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    // nix does not expose the flags argument
+    let res = unsafe {
+        nix::libc::syscall(
+            nix::libc::SYS_copy_file_range,
+            src.as_raw_fd(),
+            std::ptr::null_mut::<nix::libc::loff_t>(),
+            dest.as_raw_fd(),
+            std::ptr::null_mut::<nix::libc::loff_t>(),
+            100usize,
+            1u32,
+        )
+    };
+    assert_eq!(Errno::result(res), Err(Errno::EINVAL));
+}
+
+crate::test_case! {
+    /// copy_file_range either copies the exact bytes or returns EXDEV or EINVAL
+    /// when the destination is on a different file system
+    cross_fs, FileSystemFeature::CopyFileRange; crate::tests::errors::exdev::secondary_fs_available
+}
+fn cross_fs(ctx: &mut TestContext) {
+    let data = pattern(SIZE as off_t, None);
+    let (_, src) = create_with(ctx, &data);
+    let secondary_fs = ctx.features_config().secondary_fs.as_ref().unwrap();
+    let (dest_path, dest) = FileBuilder::new(FileType::Regular, secondary_fs)
+        .open(OFlag::O_RDWR)
+        .unwrap();
+
+    let res = copy_all(&src, 0, &dest, 0, SIZE);
+    let content = read_all(&dest);
+    // The secondary file system isn't cleaned up with the context
+    std::fs::remove_file(&dest_path).unwrap();
+
+    match res {
+        Ok(copied) => {
+            assert_eq!(copied, SIZE);
+            assert_eq!(content, data);
+        }
+        Err(e) => assert!(matches!(e, Errno::EXDEV | Errno::EINVAL), "{e}"),
+    }
+}
@@ -0,0 +1,100 @@
+//! Helpers for the tests checking the content of regular files.
+
+use std::{
+    os::fd::{AsRawFd, OwnedFd},
+    path::PathBuf,
+};
+
+#[cfg(target_os = "linux")]
+use nix::unistd::fsync;
+use nix::{
+    fcntl::OFlag,
+    libc::off_t,
+    sys::{
+        stat::fstat,
+        uio::{pread, pwrite},
+    },
+};
+
+use crate::context::TestContext;
+
+/// Granularity used for the ranges, which should be a multiple
+/// of the allocation unit of most file systems.
+#[cfg(seek_hole)]
+pub(super) const MIN_UNIT: off_t = 64 * 1024;
+
+/// Return the size of the ranges to use for a file,
+/// which is a multiple of its block size.
+#[cfg(seek_hole)]
+pub(super) fn range_unit(fd: &OwnedFd) -> off_t {
+    let blksize = fstat(fd.as_raw_fd()).unwrap().st_blksize as off_t;
+    let blksize = blksize.max(1);
+
+    (MIN_UNIT + blksize - 1) / blksize * blksize
+}
+
+/// Return a recognizable non-zero pattern of `len` bytes, which differs for each `seed`.
+pub(super) fn pattern(len: off_t, seed: Option<off_t>) -> Vec<u8> {
+    let seed = seed.unwrap_or(0);
+    (0..len).map(|i| ((i + seed) % 251 + 1) as u8).collect()
+}
+
+/// Create a file filled with `data`.
+pub(super) fn create_with(ctx: &TestContext, data: &[u8]) -> (PathBuf, OwnedFd) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    assert_eq!(pwrite(&fd, data, 0).unwrap(), data.len());
+
+    (path, fd)
+}
+
+/// Create a file filled with `units` ranges of pattern data,
+/// and return it along with its content and the size of a range.
+#[cfg(target_os = "linux")]
+pub(super) fn create_filled(ctx: &TestContext, units: off_t) -> (PathBuf, OwnedFd, Vec<u8>, off_t) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let data = pattern(units * unit, None);
+
+    assert_eq!(pwrite(&fd, &data, 0).unwrap(), data.len());
+    // Make sure that the blocks are allocated before checking st_blocks
+    fsync(fd.as_raw_fd()).unwrap();
+
+    (path, fd, data, unit)
+}
+
+/// Read `len` bytes at `offset`, which should all be available.
+pub(super) fn pread_exact(fd: &OwnedFd, len: usize, offset: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    assert_eq!(pread(fd, &mut buf, offset as off_t).unwrap(), len);
+
+    buf
+}
+
+/// Read the whole content of the file.
+pub(super) fn read_all(fd: &OwnedFd) -> Vec<u8> {
+    let size = fstat(fd.as_raw_fd()).unwrap().st_size as usize;
+    let mut buf = vec![0; size];
+    let mut read = 0;
+    while read < size {
+        let n = pread(fd, &mut buf[read..], read as off_t).unwrap();
+        assert_ne!(n, 0, "unexpected EOF");
+        read += n;
+    }
+
+    buf
+}
+
+pub(super) fn st_size(fd: &OwnedFd) -> off_t {
+    fstat(fd.as_raw_fd()).unwrap().st_size
+}
+
+#[cfg(seek_hole)]
+pub(super) fn st_blocks(fd: &OwnedFd) -> nix::libc::blkcnt_t {
+    fstat(fd.as_raw_fd()).unwrap().st_blocks
+}
+
+/// Convert a length in bytes to a number of 512-byte blocks.
+#[cfg(seek_hole)]
+pub(super) fn to_blocks(len: off_t) -> nix::libc::blkcnt_t {
+    (len / 512) as nix::libc::blkcnt_t
+}
@@ -6,6 +6,8 @@ pub(super) mod eloop;
 pub(super) mod enametoolong;
 pub(super) mod enoent;
 pub(super) mod enotdir;
+#[cfg(inode_flags)]
+pub(super) mod eperm;
 pub(super) mod erofs;
 pub(super) mod etxtbsy;
 pub(super) mod exdev;
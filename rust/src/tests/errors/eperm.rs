@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use nix::{
+    libc::{gid_t, ino_t, mode_t, nlink_t, off_t, uid_t},
+    sys::stat::lstat,
+};
+use strum::IntoEnumIterator;
+
+use crate::{config::Config, flags::InodeFlag};
+
+/// Guard to allow execution of this test only if the inode flags can be set on the file system.
+pub(crate) fn supports_inode_flags(_: &Config, base_path: &Path) -> anyhow::Result<()> {
+    let file = tempfile::NamedTempFile::new_in(base_path)?;
+    for flag in InodeFlag::iter() {
+        flag.set(file.path())
+            .and_then(|()| flag.clear(file.path()))
+            .map_err(|e| anyhow::anyhow!("The {flag} inode flag cannot be set: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Attributes of a file which should not change when an operation is denied.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Attributes {
+    ino: ino_t,
+    mode: mode_t,
+    uid: uid_t,
+    gid: gid_t,
+    nlink: nlink_t,
+    size: off_t,
+}
+
+impl Attributes {
+    pub(crate) fn of(path: &Path) -> nix::Result<Self> {
+        lstat(path).map(|st| Attributes {
+            ino: st.st_ino,
+            mode: st.st_mode,
+            uid: st.st_uid,
+            gid: st.st_gid,
+            nlink: st.st_nlink,
+            size: st.st_size,
+        })
+    }
+}
+
+/// Create a test case which asserts that the syscall returns EPERM and leaves the file unchanged
+/// if the named file has the provided [`InodeFlag`].
+/// It is used through [`eperm_immutable_test_case`] and [`eperm_append_test_case`].
+macro_rules! eperm_flag_test_case {
+    ($name: ident, $flag: ident, $desc: literal, $syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::test_case! {
+            #[doc = concat!(stringify!($syscall), " returns EPERM if the named file ", $desc)]
+            $name, root; crate::tests::errors::eperm::supports_inode_flags => $fts
+        }
+        fn $name(ctx: &mut crate::context::TestContext, ft: crate::context::FileType) {
+            use crate::{flags::InodeFlag, tests::errors::eperm::Attributes};
+
+            let path = ctx.create(ft).unwrap();
+            InodeFlag::$flag.set(&path).unwrap();
+            let attributes = Attributes::of(&path).unwrap();
+
+            $( assert_eq!($f(ctx, &path), Err(nix::errno::Errno::EPERM)); )+
+            assert_eq!(Attributes::of(&path).unwrap(), attributes);
+        }
+    };
+}
+
+pub(crate) use eperm_flag_test_case;
+
+/// Create a test case which asserts that the syscall returns EPERM and leaves the file unchanged
+/// if the named file has the immutable flag.
+/// There are multiple forms for this macro, which all end with the file types to test:
+///
+/// - A basic form which takes the syscall, and optionally a `~path` argument
+///   to indicate where the `path` argument should be substituted if the path
+///   is not the only argument taken by the syscall.
+///
+/// ```
+/// // `unlink` accepts only a path as argument.
+/// eperm_immutable_test_case!(unlink => [Regular]);
+/// // `truncate` takes a path and the new size as arguments.
+/// // We need to add `~path` where the path argument should normally be taken.
+/// eperm_immutable_test_case!(truncate(~path, 123) => [Regular]);
+/// ```
+///
+/// - A more complex form which takes multiple functions
+///   with the context and the path as arguments for syscalls
+///   requring to compute other arguments.
+///
+/// ```
+/// eperm_immutable_test_case!(link, |ctx: &mut TestContext, path: &Path| {
+///   link(path, &ctx.gen_path())
+/// } => [Regular]);
+/// ```
+macro_rules! eperm_immutable_test_case {
+    ($syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_flag_test_case!(
+            eperm_immutable, Immutable, "is immutable", $syscall, $($f),+ => $fts
+        );
+    };
+
+    ($syscall: ident $( ($( $($before:expr),* ,)? ~path $(, $($after:expr),*)?) )? => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_immutable_test_case!($syscall, |_ctx: &mut crate::context::TestContext, path: &std::path::Path| {
+                $syscall($( $($($before),* ,)? )? path $( $(, $($after),*)? )?)
+        } => $fts);
+    };
+}
+
+pub(crate) use eperm_immutable_test_case;
+
+/// Create a test case which asserts that the syscall returns EPERM and leaves the file unchanged
+/// if the named file is append-only.
+/// It accepts the same forms as [`eperm_immutable_test_case`].
+macro_rules! eperm_append_test_case {
+    ($syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_flag_test_case!(
+            eperm_append, Append, "is append-only", $syscall, $($f),+ => $fts
+        );
+    };
+
+    ($syscall: ident $( ($( $($before:expr),* ,)? ~path $(, $($after:expr),*)?) )? => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_append_test_case!($syscall, |_ctx: &mut crate::context::TestContext, path: &std::path::Path| {
+                $syscall($( $($($before),* ,)? )? path $( $(, $($after),*)? )?)
+        } => $fts);
+    };
+}
+
+pub(crate) use eperm_append_test_case;
+
+/// Create a test case which asserts that the syscall returns EPERM if the parent directory
+/// of the named file has the provided [`InodeFlag`],
+/// and that neither the existing entry nor the new one are changed.
+/// It is used through [`eperm_parent_immutable_test_case`] and [`eperm_parent_append_test_case`].
+macro_rules! eperm_parent_flag_test_case {
+    ($name: ident, $flag: ident, $desc: literal, $syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::test_case! {
+            #[doc = concat!(stringify!($syscall), " returns EPERM if the parent directory of the named file ", $desc)]
+            $name, root; crate::tests::errors::eperm::supports_inode_flags => $fts
+        }
+        fn $name(ctx: &mut crate::context::TestContext, ft: crate::context::FileType) {
+            use crate::{context::FileType, flags::InodeFlag, tests::errors::eperm::Attributes};
+
+            let dir = ctx.create(FileType::Dir).unwrap();
+            let existing = ctx.new_file(ft).name(dir.join("existing")).create().unwrap();
+            let new = dir.join("new");
+            InodeFlag::$flag.set(&dir).unwrap();
+            let attributes = Attributes::of(&existing).unwrap();
+
+            $( assert_eq!($f(ctx, &existing, &new), Err(nix::errno::Errno::EPERM)); )+
+            assert_eq!(Attributes::of(&existing).unwrap(), attributes);
+            assert_eq!(Attributes::of(&new), Err(nix::errno::Errno::ENOENT));
+        }
+    };
+}
+
+pub(crate) use eperm_parent_flag_test_case;
+
+/// Create a test case which asserts that the syscall returns EPERM
+/// if the parent directory of the named file is immutable.
+/// It takes the syscall and functions with the context, an existing entry of the directory
+/// of the provided file type and a path in the directory which does not exist yet as arguments,
+/// followed by the file types to test.
+///
+/// ```
+/// eperm_parent_immutable_test_case!(unlink, |_: &mut TestContext, existing: &Path, _: &Path| {
+///   unlink(existing)
+/// } => [Regular]);
+/// eperm_parent_immutable_test_case!(mkdir, |_: &mut TestContext, _: &Path, new: &Path| {
+///   mkdir(new, Mode::from_bits_truncate(0o755))
+/// } => [Regular]);
+/// ```
+macro_rules! eperm_parent_immutable_test_case {
+    ($syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_parent_flag_test_case!(
+            eperm_parent_immutable, Immutable, "is immutable", $syscall, $($f),+ => $fts
+        );
+    };
+}
+
+pub(crate) use eperm_parent_immutable_test_case;
+
+/// Create a test case which asserts that the syscall returns EPERM
+/// if the parent directory of the named file is append-only.
+/// It accepts the same form as [`eperm_parent_immutable_test_case`].
+macro_rules! eperm_parent_append_test_case {
+    ($syscall: ident, $($f: expr),+ => $fts: tt) => {
+        crate::tests::errors::eperm::eperm_parent_flag_test_case!(
+            eperm_parent_append, Append, "is append-only", $syscall, $($f),+ => $fts
+        );
+    };
+}
+
+pub(crate) use eperm_parent_append_test_case;
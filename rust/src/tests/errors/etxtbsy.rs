@@ -32,24 +32,12 @@ macro_rules! etxtbsy_test_case {
             etxtbsy; $crate::tests::errors::etxtbsy::exec_mounted
         }
         fn etxtbsy (ctx: &mut crate::context::TestContext) {
-            use std::{fs::File, process::Command};
+            use std::process::Command;
 
-            use nix::{errno::Errno, sys::stat::Mode};
-
-            use crate::utils::chmod;
-
-            let sleep_path =
-                String::from_utf8(Command::new("which").arg("sleep").output().unwrap().stdout).unwrap();
-            let sleep_path = sleep_path.trim();
+            use nix::errno::Errno;
 
             let exec_path = ctx.gen_path();
-            std::io::copy(
-                &mut File::open(sleep_path).unwrap(),
-                &mut File::create(&exec_path).unwrap(),
-            )
-            .unwrap();
-
-            chmod(&exec_path, Mode::from_bits_truncate(0o755)).unwrap();
+            crate::tests::copy_sleep(&exec_path);
 
             let mut sleep_process = Command::new(&exec_path).arg("10").spawn().unwrap();
             $( assert_eq!($f(&exec_path).unwrap_err(), Errno::ETXTBSY); )+
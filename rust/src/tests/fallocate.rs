@@ -0,0 +1,393 @@
+//! Tests for the Linux-specific modes of the `fallocate` syscall.
+
+use std::{os::fd::AsRawFd, path::Path};
+
+use nix::{
+    errno::Errno,
+    fcntl::{fallocate, FallocateFlags, OFlag},
+    sys::stat::Mode,
+};
+
+use crate::{
+    context::{FileType, TestContext},
+    test::FileSystemFeature,
+    utils::{get_inode_flags, iflags::FS_APPEND_FL, open, set_inode_flags},
+};
+
+use super::{
+    assert_times_changed,
+    data::{create_filled, read_all, st_blocks, st_size, to_blocks},
+    errors::etxtbsy::etxtbsy_test_case,
+    CTIME, MTIME,
+};
+
+/// Assert that `f` changes the ctime and mtime of `path`.
+fn assert_data_times_changed<F>(ctx: &TestContext, path: &Path, f: F)
+where
+    F: FnOnce(),
+{
+    assert_times_changed()
+        .path(path, CTIME | MTIME)
+        .execute(ctx, false, f)
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_KEEP_SIZE allocates blocks beyond EOF without changing the size
+    keep_size, FileSystemFeature::FallocateKeepSize
+}
+fn keep_size(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 1);
+    let blocks = st_blocks(&fd);
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        unit,
+        4 * unit,
+    )
+    .unwrap();
+
+    assert_eq!(st_size(&fd), unit);
+    assert!(st_blocks(&fd) >= blocks + to_blocks(4 * unit));
+    assert_eq!(read_all(&fd), data);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_PUNCH_HOLE deallocates the range, which then reads back as zeros
+    punch_hole, FileSystemFeature::FallocatePunchHole
+}
+fn punch_hole(ctx: &mut TestContext) {
+    let (path, fd, mut expected, unit) = create_filled(ctx, 4);
+    let blocks = st_blocks(&fd);
+
+    assert_data_times_changed(ctx, &path, || {
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+            unit,
+            2 * unit,
+        )
+        .unwrap();
+    });
+    expected[unit as usize..3 * unit as usize].fill(0);
+
+    assert_eq!(st_size(&fd), 4 * unit);
+    assert!(st_blocks(&fd) <= blocks - to_blocks(2 * unit));
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_PUNCH_HOLE zeroes partial blocks at the edges of an unaligned range
+    punch_hole_unaligned, FileSystemFeature::FallocatePunchHole
+}
+fn punch_hole_unaligned(ctx: &mut TestContext) {
+    let (_path, fd, mut expected, unit) = create_filled(ctx, 2);
+    let offset = unit / 2 + 123;
+    let len = unit + 456;
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        offset,
+        len,
+    )
+    .unwrap();
+    expected[offset as usize..(offset + len) as usize].fill(0);
+
+    assert_eq!(st_size(&fd), 2 * unit);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_PUNCH_HOLE beyond EOF does not change the size
+    punch_hole_beyond_eof, FileSystemFeature::FallocatePunchHole
+}
+fn punch_hole_beyond_eof(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 1);
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        unit / 2,
+        4 * unit,
+    )
+    .unwrap();
+
+    let mut expected = data;
+    expected[unit as usize / 2..].fill(0);
+    assert_eq!(st_size(&fd), unit);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate returns EOPNOTSUPP if FALLOC_FL_PUNCH_HOLE is not ORed with FALLOC_FL_KEEP_SIZE
+    punch_hole_eopnotsupp_without_keep_size, FileSystemFeature::FallocatePunchHole
+}
+fn punch_hole_eopnotsupp_without_keep_size(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 2);
+
+    assert_eq!(
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_PUNCH_HOLE,
+            0,
+            unit
+        ),
+        Err(Errno::EOPNOTSUPP)
+    );
+    assert_eq!(read_all(&fd), data);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_ZERO_RANGE zeroes the range without changing the size
+    zero_range, FileSystemFeature::FallocateZeroRange
+}
+fn zero_range(ctx: &mut TestContext) {
+    let (path, fd, mut expected, unit) = create_filled(ctx, 4);
+    let offset = unit / 2 + 7;
+    let len = 2 * unit;
+
+    assert_data_times_changed(ctx, &path, || {
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_ZERO_RANGE,
+            offset,
+            len,
+        )
+        .unwrap();
+    });
+    expected[offset as usize..(offset + len) as usize].fill(0);
+
+    assert_eq!(st_size(&fd), 4 * unit);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_ZERO_RANGE beyond EOF extends the file,
+    /// unless FALLOC_FL_KEEP_SIZE is specified
+    zero_range_beyond_eof, FileSystemFeature::FallocateZeroRange
+}
+fn zero_range_beyond_eof(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 1);
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_ZERO_RANGE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        unit,
+        unit,
+    )
+    .unwrap();
+    assert_eq!(st_size(&fd), unit);
+    assert_eq!(read_all(&fd), data);
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_ZERO_RANGE,
+        unit,
+        unit,
+    )
+    .unwrap();
+    assert_eq!(st_size(&fd), 2 * unit);
+
+    let mut expected = data;
+    expected.resize(2 * unit as usize, 0);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_COLLAPSE_RANGE removes the range and shifts the following data
+    collapse_range, FileSystemFeature::FallocateCollapseRange
+}
+fn collapse_range(ctx: &mut TestContext) {
+    let (path, fd, data, unit) = create_filled(ctx, 4);
+    let blocks = st_blocks(&fd);
+
+    assert_data_times_changed(ctx, &path, || {
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_COLLAPSE_RANGE,
+            unit,
+            unit,
+        )
+        .unwrap();
+    });
+
+    let expected: Vec<_> = data[..unit as usize]
+        .iter()
+        .chain(&data[2 * unit as usize..])
+        .copied()
+        .collect();
+
+    assert_eq!(st_size(&fd), 3 * unit);
+    assert!(st_blocks(&fd) <= blocks - to_blocks(unit));
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_COLLAPSE_RANGE returns EINVAL if the range
+    /// is not aligned on the file system block size or reaches EOF
+    collapse_range_einval, FileSystemFeature::FallocateCollapseRange
+}
+fn collapse_range_einval(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 4);
+
+    for (offset, len) in [
+        (1, unit),
+        (unit, unit - 1),
+        (unit + 1, unit + 1),
+        // Range reaches EOF
+        (2 * unit, 2 * unit),
+        (3 * unit, 2 * unit),
+    ] {
+        assert_eq!(
+            fallocate(
+                fd.as_raw_fd(),
+                FallocateFlags::FALLOC_FL_COLLAPSE_RANGE,
+                offset,
+                len
+            ),
+            Err(Errno::EINVAL)
+        );
+    }
+
+    // FALLOC_FL_COLLAPSE_RANGE cannot be combined with other flags,
+    // older kernels reject it with EINVAL and newer ones with EOPNOTSUPP
+    assert!(matches!(
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_COLLAPSE_RANGE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+            unit,
+            unit
+        ),
+        Err(Errno::EINVAL | Errno::EOPNOTSUPP)
+    ));
+
+    assert_eq!(st_size(&fd), 4 * unit);
+    assert_eq!(read_all(&fd), data);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_INSERT_RANGE inserts a hole and shifts the following data
+    insert_range, FileSystemFeature::FallocateInsertRange
+}
+fn insert_range(ctx: &mut TestContext) {
+    let (path, fd, data, unit) = create_filled(ctx, 2);
+    let blocks = st_blocks(&fd);
+
+    assert_data_times_changed(ctx, &path, || {
+        fallocate(
+            fd.as_raw_fd(),
+            FallocateFlags::FALLOC_FL_INSERT_RANGE,
+            unit,
+            unit,
+        )
+        .unwrap();
+    });
+
+    let expected: Vec<_> = data[..unit as usize]
+        .iter()
+        .copied()
+        .chain(std::iter::repeat(0).take(unit as usize))
+        .chain(data[unit as usize..].iter().copied())
+        .collect();
+
+    assert_eq!(st_size(&fd), 3 * unit);
+    // The inserted range is a hole
+    assert!(st_blocks(&fd) <= blocks);
+    assert_eq!(read_all(&fd), expected);
+}
+
+crate::test_case! {
+    /// fallocate with FALLOC_FL_INSERT_RANGE returns EINVAL if the range
+    /// is not aligned on the file system block size or starts at or beyond EOF
+    insert_range_einval, FileSystemFeature::FallocateInsertRange
+}
+fn insert_range_einval(ctx: &mut TestContext) {
+    let (_path, fd, data, unit) = create_filled(ctx, 2);
+
+    for (offset, len) in [
+        (1, unit),
+        (unit, unit - 1),
+        (unit + 1, unit + 1),
+        // Offset at or beyond EOF
+        (2 * unit, unit),
+        (3 * unit, unit),
+    ] {
+        assert_eq!(
+            fallocate(
+                fd.as_raw_fd(),
+                FallocateFlags::FALLOC_FL_INSERT_RANGE,
+                offset,
+                len
+            ),
+            Err(Errno::EINVAL)
+        );
+    }
+
+    assert_eq!(st_size(&fd), 2 * unit);
+    assert_eq!(read_all(&fd), data);
+}
+
+crate::test_case! {
+    /// fallocate returns EPERM when deallocating or shifting data of an append-only file
+    append_only_eperm, root, FileSystemFeature::FallocatePunchHole
+}
+fn append_only_eperm(ctx: &mut TestContext) {
+    let (path, fd, data, unit) = create_filled(ctx, 4);
+    drop(fd);
+
+    let flags = get_inode_flags(&path).unwrap();
+    set_inode_flags(&path, flags | FS_APPEND_FL).unwrap();
+
+    // The flag is cleared before asserting, so that the file can be removed afterwards
+    let results: Vec<_> = open(&path, OFlag::O_WRONLY | OFlag::O_APPEND, Mode::empty())
+        .map(|fd| {
+            [
+                FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+                FallocateFlags::FALLOC_FL_ZERO_RANGE,
+                FallocateFlags::FALLOC_FL_COLLAPSE_RANGE,
+                FallocateFlags::FALLOC_FL_INSERT_RANGE,
+            ]
+            .into_iter()
+            .map(|mode| fallocate(fd.as_raw_fd(), mode, unit, unit))
+            .collect()
+        })
+        .unwrap_or_default();
+
+    set_inode_flags(&path, flags).unwrap();
+
+    assert_eq!(results, [Err(Errno::EPERM); 4]);
+    assert_eq!(std::fs::read(&path).unwrap(), data);
+}
+
+crate::test_case! {
+    /// fallocate returns EBADF if the file descriptor is not opened for writing
+    ebadf_read_only
+}
+fn ebadf_read_only(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDONLY, None).unwrap();
+
+    assert_eq!(
+        fallocate(fd.as_raw_fd(), FallocateFlags::empty(), 0, 1),
+        Err(Errno::EBADF)
+    );
+}
+
+crate::test_case! {
+    /// fallocate returns ESPIPE if the file descriptor refers to a FIFO
+    espipe_fifo
+}
+fn espipe_fifo(ctx: &mut TestContext) {
+    let fifo = ctx.create(FileType::Fifo).unwrap();
+    let fd = open(&fifo, OFlag::O_RDWR, Mode::empty()).unwrap();
+
+    assert_eq!(
+        fallocate(fd.as_raw_fd(), FallocateFlags::empty(), 0, 1),
+        Err(Errno::ESPIPE)
+    );
+}
+
+etxtbsy_test_case!(fallocate, |path: &Path| {
+    open(path, OFlag::O_WRONLY, Mode::empty())
+        .and_then(|fd| fallocate(fd.as_raw_fd(), FallocateFlags::empty(), 0, 1))
+});
@@ -0,0 +1,183 @@
+//! Tests for the Linux-specific `FICLONE` and `FICLONERANGE` ioctls,
+//! which share data between files (reflink).
+
+use std::os::fd::AsRawFd;
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    sys::{
+        stat::{fstat, Mode},
+        uio::pwrite,
+    },
+    unistd::fsync,
+};
+
+use crate::{
+    context::{FileBuilder, FileType, TestContext},
+    test::FileSystemFeature,
+    tests::{
+        assert_times_changed,
+        data::{create_filled, pattern, read_all, MIN_UNIT},
+        CTIME, MTIME,
+    },
+    utils::{ficlone, ficlonerange, open},
+};
+
+crate::test_case! {
+    /// FICLONE shares the whole content of the source file with the destination,
+    /// and updates ctime and mtime of the destination
+    clone_file, FileSystemFeature::Reflink
+}
+fn clone_file(ctx: &mut TestContext) {
+    let (_, src, data, _) = create_filled(ctx, 4);
+    let (path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    assert_times_changed()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            ficlone(&dest, &src).unwrap();
+        });
+
+    assert_eq!(read_all(&dest), data);
+    assert_eq!(read_all(&src), data);
+}
+
+crate::test_case! {
+    /// Clones are copy-on-write: writing to one of the files does not change the other
+    copy_on_write, FileSystemFeature::Reflink
+}
+fn copy_on_write(ctx: &mut TestContext) {
+    let (_, src, data, unit) = create_filled(ctx, 4);
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    ficlone(&dest, &src).unwrap();
+
+    let mut expected_src = data.clone();
+    let zeros = vec![0; unit as usize];
+    assert_eq!(pwrite(&src, &zeros, unit).unwrap(), zeros.len());
+    expected_src[unit as usize..2 * unit as usize].fill(0);
+    fsync(src.as_raw_fd()).unwrap();
+
+    assert_eq!(read_all(&src), expected_src);
+    assert_eq!(read_all(&dest), data);
+
+    let mut expected_dest = data;
+    let ones = vec![1; 100];
+    assert_eq!(pwrite(&dest, &ones, 3 * unit + 1).unwrap(), ones.len());
+    expected_dest[3 * unit as usize + 1..3 * unit as usize + 101].fill(1);
+    fsync(dest.as_raw_fd()).unwrap();
+
+    assert_eq!(read_all(&dest), expected_dest);
+    assert_eq!(read_all(&src), expected_src);
+}
+
+crate::test_case! {
+    /// FICLONERANGE shares the requested range of the source file at the requested offset
+    clone_range, FileSystemFeature::Reflink
+}
+fn clone_range(ctx: &mut TestContext) {
+    let (_, src, data, unit) = create_filled(ctx, 4);
+    let (_, dest, mut expected, _) = create_filled(ctx, 4);
+    expected.iter_mut().for_each(|b| *b = b.wrapping_add(1));
+    assert_eq!(pwrite(&dest, &expected, 0).unwrap(), expected.len());
+
+    let (unit_u, unit_s) = (unit as u64, unit as usize);
+    ficlonerange(&dest, 2 * unit_u, &src, unit_u, unit_u).unwrap();
+    expected[2 * unit_s..3 * unit_s].copy_from_slice(&data[unit_s..2 * unit_s]);
+
+    assert_eq!(read_all(&dest), expected);
+    assert_eq!(read_all(&src), data);
+
+    // A length of zero clones up to the end of the source file
+    ficlonerange(&dest, 0, &src, 3 * unit_u, 0).unwrap();
+    expected[..unit_s].copy_from_slice(&data[3 * unit_s..]);
+
+    assert_eq!(read_all(&dest), expected);
+}
+
+crate::test_case! {
+    /// FICLONERANGE returns EINVAL if the ranges are not aligned with the block size,
+    /// or overlap in the same file
+    clone_range_einval, FileSystemFeature::Reflink
+}
+fn clone_range_einval(ctx: &mut TestContext) {
+    let (_, src, data, unit) = create_filled(ctx, 4);
+    let (_, dest, _, _) = create_filled(ctx, 4);
+    let dest_data = read_all(&dest);
+    let unit = unit as u64;
+
+    for (dest_offset, src_offset, len) in [
+        (1, 0, unit),
+        (0, 1, unit),
+        (0, 0, unit - 1),
+        (unit + 1, unit + 1, unit),
+    ] {
+        assert_eq!(
+            ficlonerange(&dest, dest_offset, &src, src_offset, len),
+            Err(Errno::EINVAL),
+            "destination offset {dest_offset}, source offset {src_offset}, length {len}"
+        );
+    }
+    assert_eq!(
+        ficlonerange(&src, unit, &src, 0, 2 * unit),
+        Err(Errno::EINVAL)
+    );
+
+    assert_eq!(read_all(&src), data);
+    assert_eq!(read_all(&dest), dest_data);
+}
+
+crate::test_case! {
+    /// FICLONE returns EBADF if the source is not open for reading,
+    /// or the destination is not open for writing or open in append mode
+    ebadf, FileSystemFeature::Reflink
+}
+fn ebadf(ctx: &mut TestContext) {
+    let (src_path, src) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    assert_eq!(
+        pwrite(&src, &pattern(MIN_UNIT, None), 0).unwrap(),
+        MIN_UNIT as usize
+    );
+    let (dest_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    let src_wronly = open(&src_path, OFlag::O_WRONLY, Mode::empty()).unwrap();
+    assert_eq!(ficlone(&dest, &src_wronly), Err(Errno::EBADF));
+
+    let dest_rdonly = open(&dest_path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    assert_eq!(ficlone(&dest_rdonly, &src), Err(Errno::EBADF));
+
+    let dest_append = open(&dest_path, OFlag::O_WRONLY | OFlag::O_APPEND, Mode::empty()).unwrap();
+    assert_eq!(ficlone(&dest_append, &src), Err(Errno::EBADF));
+
+    assert_eq!(fstat(dest.as_raw_fd()).unwrap().st_size, 0);
+}
+
+crate::test_case! {
+    /// FICLONE returns EISDIR if the source is a directory
+    eisdir, FileSystemFeature::Reflink
+}
+fn eisdir(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let dir = open(&dir, OFlag::O_RDONLY | OFlag::O_DIRECTORY, Mode::empty()).unwrap();
+    let (_path, dest) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    assert_eq!(ficlone(&dest, &dir), Err(Errno::EISDIR));
+}
+
+crate::test_case! {
+    /// FICLONE returns EXDEV when the files are on different file systems
+    exdev, FileSystemFeature::Reflink; crate::tests::errors::exdev::secondary_fs_available
+}
+fn exdev(ctx: &mut TestContext) {
+    let (_, src, _, _) = create_filled(ctx, 1);
+    let secondary_fs = ctx.features_config().secondary_fs.as_ref().unwrap();
+    let (dest_path, dest) = FileBuilder::new(FileType::Regular, secondary_fs)
+        .open(OFlag::O_RDWR)
+        .unwrap();
+
+    let res = ficlone(&dest, &src);
+    // The secondary file system isn't cleaned up with the context
+    std::fs::remove_file(&dest_path).unwrap();
+
+    assert_eq!(res, Err(Errno::EXDEV));
+}
@@ -4,7 +4,6 @@ use nix::{
     errno::Errno,
     fcntl::OFlag,
     sys::stat::{lstat, Mode},
-    unistd::ftruncate,
 };
 use rand::random;
 
@@ -12,7 +11,7 @@ use crate::{
     context::FileType,
     test::{SerializedTestContext, TestContext},
     tests::{assert_ctime_changed, assert_ctime_unchanged},
-    utils::{chmod, open},
+    utils::{chmod, ftruncate, open},
 };
 
 crate::test_case! {
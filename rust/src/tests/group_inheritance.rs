@@ -0,0 +1,197 @@
+//! Group ID of the entries created by `open`, `mkdir`, `mkfifo`, `mknod`, `symlink` and `bind`,
+//! inside and outside of directories with the S_ISGID bit set.
+
+use std::path::{Path, PathBuf};
+
+use nix::{
+    sys::stat::{lstat, FileStat, Mode},
+    unistd::{mkdir, Gid, User},
+};
+
+use super::mksyscalls::CREATORS;
+use crate::{
+    config::{Config, GroupInheritance},
+    context::{FileType, SerializedTestContext},
+    utils::{chmod, chown},
+};
+
+const S_ISGID: nix::libc::mode_t = Mode::S_ISGID.bits();
+
+/// Return the group inheritance semantics from the configuration,
+/// or detect them from the mount options of the file system.
+pub(crate) fn group_inheritance(
+    conf: &Config,
+    base_path: &Path,
+) -> anyhow::Result<GroupInheritance> {
+    match conf.settings.group_inheritance {
+        Some(inheritance) => Ok(inheritance),
+        None => detect_group_inheritance(base_path),
+    }
+}
+
+/// Detect the group inheritance semantics from the `grpid`/`bsdgroups`
+/// and `nogrpid`/`sysvgroups` mount options.
+#[cfg(target_os = "linux")]
+fn detect_group_inheritance(base_path: &Path) -> anyhow::Result<GroupInheritance> {
+    use crate::utils::get_mountpoint;
+
+    let base_path = base_path.canonicalize()?;
+    let mountpoint = get_mountpoint(&base_path)?;
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+
+    // The last entry of the mount point is the topmost mount
+    let options = mountinfo
+        .lines()
+        .map(|line| line.split(' ').collect::<Vec<_>>())
+        .rfind(|fields| fields.get(4).map(Path::new) == Some(mountpoint))
+        .map(|fields| {
+            // Per-mount options, then per-superblock options after the separator
+            let super_options = fields
+                .iter()
+                .position(|&f| f == "-")
+                .and_then(|sep| fields.get(sep + 3))
+                .copied()
+                .unwrap_or_default();
+            format!("{},{}", fields.get(5).unwrap_or(&""), super_options)
+        })
+        .ok_or_else(|| anyhow::anyhow!("Cannot find the mount point of the file system"))?;
+
+    Ok(options
+        .split(',')
+        .rev()
+        .find_map(|option| match option {
+            "grpid" | "bsdgroups" => Some(GroupInheritance::Bsd),
+            "nogrpid" | "sysvgroups" => Some(GroupInheritance::SystemV),
+            _ => None,
+        })
+        .unwrap_or(GroupInheritance::SystemV))
+}
+
+/// BSD systems always give the group ID of the parent directory.
+#[cfg(not(target_os = "linux"))]
+fn detect_group_inheritance(_: &Path) -> anyhow::Result<GroupInheritance> {
+    Ok(GroupInheritance::Bsd)
+}
+
+/// Guard which checks that the file system follows BSD semantics.
+fn bsd_groups(conf: &Config, base_path: &Path) -> anyhow::Result<()> {
+    if group_inheritance(conf, base_path)? != GroupInheritance::Bsd {
+        anyhow::bail!("The file system follows System V group semantics")
+    }
+
+    Ok(())
+}
+
+/// Guard which checks that the file system follows System V semantics.
+fn system_v_groups(conf: &Config, base_path: &Path) -> anyhow::Result<()> {
+    if group_inheritance(conf, base_path)? != GroupInheritance::SystemV {
+        anyhow::bail!("The file system follows BSD group semantics")
+    }
+
+    Ok(())
+}
+
+/// Create a directory in which anyone can create entries,
+/// owned by `owner` and its group, with the S_ISGID bit set if `sgid` is true.
+fn create_dir(ctx: &SerializedTestContext, owner: &User, sgid: bool) -> PathBuf {
+    let path = ctx.create(FileType::Dir).unwrap();
+    chown(&path, Some(owner.uid), Some(owner.gid)).unwrap();
+    let mode = if sgid { 0o777 | S_ISGID } else { 0o777 };
+    chmod(&path, Mode::from_bits_truncate(mode)).unwrap();
+
+    path
+}
+
+/// Create an entry in `dir` with each syscall as `user` with `gid` as effective group ID,
+/// and return their status.
+fn create_entries(
+    ctx: &SerializedTestContext,
+    dir: &Path,
+    user: &User,
+    gid: Gid,
+) -> Vec<(&'static str, FileStat)> {
+    CREATORS
+        .iter()
+        .map(|&(name, create)| {
+            let path = dir.join(name);
+            ctx.as_user(user, Some(&[gid]), || {
+                create(&path, Mode::from_bits_truncate(0o755))
+                    .unwrap_or_else(|e| panic!("{name} failed: {e}"))
+            });
+
+            let stat = lstat(&path).unwrap();
+            assert_eq!(stat.st_uid, user.uid.as_raw(), "{name}");
+
+            (name, stat)
+        })
+        .collect()
+}
+
+crate::test_case! {
+    /// An entry created in a directory with the S_ISGID bit set
+    /// gets the group ID of the directory
+    sgid_dir_parent_gid, serialized, root
+}
+fn sgid_dir_parent_gid(ctx: &mut SerializedTestContext) {
+    let owner = ctx.get_new_user();
+    let (caller, caller_group) = ctx.get_new_entry();
+    let dir = create_dir(ctx, owner, true);
+
+    for (name, stat) in create_entries(ctx, &dir, caller, caller_group.gid) {
+        assert_eq!(stat.st_gid, owner.gid.as_raw(), "{name}");
+    }
+}
+
+crate::test_case! {
+    /// With System V semantics, a directory created in a directory with the S_ISGID bit set
+    /// inherits the S_ISGID bit, even when it is not requested by the mode
+    sgid_dir_subdir_sgid, serialized, root; system_v_groups
+}
+fn sgid_dir_subdir_sgid(ctx: &mut SerializedTestContext) {
+    let owner = ctx.get_new_user();
+    let (caller, caller_group) = ctx.get_new_entry();
+    let dir = create_dir(ctx, owner, true);
+
+    let subdir = dir.join("subdir");
+    let nested = subdir.join("nested");
+    ctx.as_user(caller, Some(&[caller_group.gid]), || {
+        mkdir(&subdir, Mode::from_bits_truncate(0o755)).unwrap();
+        mkdir(&nested, Mode::from_bits_truncate(0o755)).unwrap();
+    });
+
+    for path in [&subdir, &nested] {
+        let stat = lstat(path).unwrap();
+        assert_eq!(stat.st_gid, owner.gid.as_raw(), "{}", path.display());
+        assert_eq!(stat.st_mode & 0o7777, 0o755 | S_ISGID, "{}", path.display());
+    }
+}
+
+crate::test_case! {
+    /// With BSD semantics, an entry gets the group ID of its parent directory
+    bsd_parent_gid, serialized, root; bsd_groups
+}
+fn bsd_parent_gid(ctx: &mut SerializedTestContext) {
+    let owner = ctx.get_new_user();
+    let (caller, caller_group) = ctx.get_new_entry();
+    let dir = create_dir(ctx, owner, false);
+
+    for (name, stat) in create_entries(ctx, &dir, caller, caller_group.gid) {
+        assert_eq!(stat.st_gid, owner.gid.as_raw(), "{name}");
+    }
+}
+
+crate::test_case! {
+    /// With System V semantics, an entry created in a directory without the S_ISGID bit
+    /// gets the effective group ID of the process
+    system_v_egid, serialized, root; system_v_groups
+}
+fn system_v_egid(ctx: &mut SerializedTestContext) {
+    let owner = ctx.get_new_user();
+    let (caller, caller_group) = ctx.get_new_entry();
+    let dir = create_dir(ctx, owner, false);
+
+    for (name, stat) in create_entries(ctx, &dir, caller, caller_group.gid) {
+        assert_eq!(stat.st_gid, caller_group.gid.as_raw(), "{name}");
+        assert_eq!(stat.st_mode & S_ISGID, 0, "{name}");
+    }
+}
@@ -0,0 +1,497 @@
+//! Ownership through an idmapped mount of the test directory.
+//!
+//! Each test creates a directory and mounts it on another one
+//! with [`mount_setattr(MOUNT_ATTR_IDMAP)`](https://man7.org/linux/man-pages/man2/mount_setattr.2.html).
+//! The user namespace attached to the mount swaps the users and groups of the two first dummy entries,
+//! and maps root to itself, so that an entry owned by one of them on disk
+//! is seen as owned by the other one through the mount.
+
+use std::{
+    fs::{read_to_string, write},
+    mem::size_of,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::CommandExt,
+    },
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use exacl::{getfacl, setfacl, AclEntry, AclEntryKind, AclOption, Perm};
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::{
+        c_char, syscall, SYS_mount_setattr, SYS_move_mount, SYS_open_tree, AT_EMPTY_PATH, AT_FDCWD,
+        MOVE_MOUNT_F_EMPTY_PATH, OPEN_TREE_CLOEXEC, OPEN_TREE_CLONE,
+    },
+    sched::{unshare, CloneFlags},
+    sys::stat::{lstat, Mode},
+    unistd::{mkdir, Gid, Group, Uid, User},
+    NixPath,
+};
+
+use super::{
+    mksyscalls::{assert_uid_gid_in, CREATORS},
+    scratch_mount::ScratchMount,
+};
+use crate::{
+    config::Config,
+    context::{FileType, SerializedTestContext},
+    features::FileSystemFeature,
+    utils::{chmod, chown, open},
+};
+
+// Not exposed by the libc crate yet
+const MOUNT_ATTR_IDMAP: u64 = 0x0010_0000;
+
+#[repr(C)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+const EMPTY_PATH: &[u8] = b"\0";
+
+/// Create a user namespace which maps root to itself and swaps the IDs of each pair,
+/// and return a file descriptor referring to it.
+fn swapping_userns((uid1, uid2): (Uid, Uid), (gid1, gid2): (Gid, Gid)) -> OwnedFd {
+    let mut command = Command::new("sleep");
+    command.arg("inf");
+    // SAFETY: unshare is async-signal-safe
+    unsafe {
+        command.pre_exec(|| unshare(CloneFlags::CLONE_NEWUSER).map_err(Into::into));
+    }
+    // The child has unshared its user namespace once spawn returns
+    let mut child = command.spawn().unwrap();
+    let pid = child.id();
+
+    let map = |id1: u32, id2: u32| format!("0 0 1\n{id1} {id2} 1\n{id2} {id1} 1\n");
+    write(
+        format!("/proc/{pid}/uid_map"),
+        map(uid1.as_raw(), uid2.as_raw()),
+    )
+    .unwrap();
+    write(
+        format!("/proc/{pid}/gid_map"),
+        map(gid1.as_raw(), gid2.as_raw()),
+    )
+    .unwrap();
+
+    let userns = open(
+        Path::new(&format!("/proc/{pid}/ns/user")),
+        OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+    .unwrap();
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    userns
+}
+
+/// A directory mounted with swapped IDs on another one, which is unmounted when dropped.
+struct IdmappedMount {
+    /// Directory seen without the mapping.
+    source: PathBuf,
+    /// Mount point of the idmapped mount.
+    target: ScratchMount,
+    uids: (Uid, Uid),
+    gids: (Gid, Gid),
+}
+
+impl IdmappedMount {
+    /// Create a new directory and mount it with the users and groups of the two entries swapped.
+    fn new(
+        ctx: &SerializedTestContext,
+        (user1, group1): (&User, &Group),
+        (user2, group2): (&User, &Group),
+    ) -> Self {
+        let uids = (user1.uid, user2.uid);
+        let gids = (group1.gid, group2.gid);
+        let source = ctx.create(FileType::Dir).unwrap();
+        let target = ctx.create(FileType::Dir).unwrap();
+        let userns = swapping_userns(uids, gids);
+
+        // SAFETY: The path is a valid NUL-terminated string for the duration of the call.
+        let res = source.with_nix_path(|cstr| unsafe {
+            syscall(
+                SYS_open_tree,
+                AT_FDCWD,
+                cstr.as_ptr(),
+                OPEN_TREE_CLONE | OPEN_TREE_CLOEXEC,
+            )
+        });
+        // SAFETY: open_tree returned a new file descriptor, which isn't owned by anything else.
+        let tree = unsafe { OwnedFd::from_raw_fd(Errno::result(res.unwrap()).unwrap() as RawFd) };
+
+        let attr = MountAttr {
+            attr_set: MOUNT_ATTR_IDMAP,
+            attr_clr: 0,
+            propagation: 0,
+            userns_fd: userns.as_raw_fd() as u64,
+        };
+        // SAFETY: `tree` is open, EMPTY_PATH is NUL-terminated and `attr` is a `struct mount_attr`
+        // of the size passed, all valid for the duration of the call.
+        let res = unsafe {
+            syscall(
+                SYS_mount_setattr,
+                tree.as_raw_fd(),
+                EMPTY_PATH.as_ptr() as *const c_char,
+                AT_EMPTY_PATH,
+                &attr,
+                size_of::<MountAttr>(),
+            )
+        };
+        Errno::result(res).unwrap();
+
+        // SAFETY: `tree` is open, and EMPTY_PATH and the path are NUL-terminated strings
+        // valid for the duration of the call.
+        let res = target.with_nix_path(|cstr| unsafe {
+            syscall(
+                SYS_move_mount,
+                tree.as_raw_fd(),
+                EMPTY_PATH.as_ptr() as *const c_char,
+                AT_FDCWD,
+                cstr.as_ptr(),
+                MOVE_MOUNT_F_EMPTY_PATH,
+            )
+        });
+        Errno::result(res.unwrap()).unwrap();
+
+        IdmappedMount {
+            source,
+            target: ScratchMount::new(target),
+            uids,
+            gids,
+        }
+    }
+
+    /// Return the user ID seen through the mount for a user ID on disk, and conversely.
+    fn map_uid(&self, uid: u32) -> u32 {
+        swap(uid, (self.uids.0.as_raw(), self.uids.1.as_raw()))
+    }
+
+    /// Return the group ID seen through the mount for a group ID on disk, and conversely.
+    fn map_gid(&self, gid: u32) -> u32 {
+        swap(gid, (self.gids.0.as_raw(), self.gids.1.as_raw()))
+    }
+}
+
+fn swap(id: u32, (id1, id2): (u32, u32)) -> u32 {
+    if id == id1 {
+        id2
+    } else if id == id2 {
+        id1
+    } else {
+        id
+    }
+}
+
+/// Assert the owner of the entry at `name` on disk and through the mount.
+#[track_caller]
+fn assert_owner(mount: &IdmappedMount, name: &str, on_disk: (u32, u32), visible: (u32, u32)) {
+    let stat = lstat(&mount.source.join(name)).unwrap();
+    assert_eq!((stat.st_uid, stat.st_gid), on_disk, "{name} on disk");
+    let stat = lstat(&mount.target.path().join(name)).unwrap();
+    assert_eq!(
+        (stat.st_uid, stat.st_gid),
+        visible,
+        "{name} through the mount"
+    );
+}
+
+crate::test_case! {
+    /// The owner of an entry is seen with the IDs mapped through an idmapped mount
+    visible_ids, serialized, root, FileSystemFeature::IdmappedMounts
+}
+fn visible_ids(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+    let (user2, group2) = entry2;
+
+    // IDs which are not mapped are seen as the overflow IDs
+    let unmapped = 0x7FFF_FFF0;
+    let overflow_id = |name| {
+        read_to_string(format!("/proc/sys/kernel/{name}"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    };
+    let overflow = (overflow_id("overflowuid"), overflow_id("overflowgid"));
+
+    for (name, on_disk, visible) in [
+        (
+            "user1",
+            (user1.uid.as_raw(), group1.gid.as_raw()),
+            (user2.uid.as_raw(), group2.gid.as_raw()),
+        ),
+        (
+            "user2",
+            (user2.uid.as_raw(), group2.gid.as_raw()),
+            (user1.uid.as_raw(), group1.gid.as_raw()),
+        ),
+        ("root", (0, 0), (0, 0)),
+        ("unmapped", (unmapped, unmapped), overflow),
+    ] {
+        let path = mount.source.join(name);
+        write(&path, "").unwrap();
+        chown(
+            &path,
+            Some(Uid::from_raw(on_disk.0)),
+            Some(Gid::from_raw(on_disk.1)),
+        )
+        .unwrap();
+
+        assert_owner(&mount, name, on_disk, visible);
+    }
+}
+
+crate::test_case! {
+    /// An entry created through an idmapped mount is owned by the mapped IDs of the process on disk
+    creation_ownership, serialized, root, FileSystemFeature::IdmappedMounts
+}
+fn creation_ownership(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+
+    for (name, create) in CREATORS {
+        // The IDs seen through the mount follow the usual rules
+        assert_uid_gid_in(ctx, mount.target.path(), user1, entry2, create);
+
+        let path = mount.target.path().join(name);
+        ctx.as_user(user1, Some(&[group1.gid]), || {
+            create(&path, Mode::from_bits_truncate(0o755)).unwrap();
+        });
+
+        // The directory is owned by `user1` and writable by anyone at this point
+        let visible = lstat(&path).unwrap();
+        assert_eq!(visible.st_uid, user1.uid.as_raw(), "{name}");
+        assert!(
+            visible.st_gid == group1.gid.as_raw() || visible.st_gid == user1.gid.as_raw(),
+            "{name}"
+        );
+        assert_owner(
+            &mount,
+            name,
+            (mount.map_uid(visible.st_uid), mount.map_gid(visible.st_gid)),
+            (visible.st_uid, visible.st_gid),
+        );
+    }
+}
+
+crate::test_case! {
+    /// chown through an idmapped mount stores the mapped IDs on disk,
+    /// and returns EOVERFLOW for IDs which are not mapped
+    chown_translation, serialized, root, FileSystemFeature::IdmappedMounts
+}
+fn chown_translation(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+    let (user2, group2) = entry2;
+
+    let path = mount.target.path().join("file");
+    write(&path, "").unwrap();
+
+    chown(&path, Some(user1.uid), Some(group1.gid)).unwrap();
+    assert_owner(
+        &mount,
+        "file",
+        (user2.uid.as_raw(), group2.gid.as_raw()),
+        (user1.uid.as_raw(), group1.gid.as_raw()),
+    );
+
+    let unmapped = 0x7FFF_FFF0;
+    assert_eq!(
+        chown(&path, Some(Uid::from_raw(unmapped)), None),
+        Err(Errno::EOVERFLOW)
+    );
+    assert_eq!(
+        chown(&path, None, Some(Gid::from_raw(unmapped))),
+        Err(Errno::EOVERFLOW)
+    );
+    assert_owner(
+        &mount,
+        "file",
+        (user2.uid.as_raw(), group2.gid.as_raw()),
+        (user1.uid.as_raw(), group1.gid.as_raw()),
+    );
+
+    // The owner can change the group to one of its groups
+    ctx.as_user(user1, Some(&[group2.gid]), || {
+        chown(&path, None, Some(group2.gid)).unwrap();
+    });
+    assert_owner(
+        &mount,
+        "file",
+        (user2.uid.as_raw(), group1.gid.as_raw()),
+        (user1.uid.as_raw(), group2.gid.as_raw()),
+    );
+}
+
+crate::test_case! {
+    /// Permissions are checked against the IDs seen through an idmapped mount
+    permissions, serialized, root, FileSystemFeature::IdmappedMounts
+}
+fn permissions(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+    let (user2, group2) = entry2;
+
+    // Readable only by `user2` on disk, and by `user1` through the mount
+    let owner_file = mount.source.join("owner");
+    write(&owner_file, "").unwrap();
+    chown(&owner_file, Some(user2.uid), Some(Gid::from_raw(0))).unwrap();
+    chmod(&owner_file, Mode::from_bits_truncate(0o600)).unwrap();
+
+    // Readable only by `group2` on disk, and by `group1` through the mount
+    let group_file = mount.source.join("group");
+    write(&group_file, "").unwrap();
+    chown(&group_file, Some(Uid::from_raw(0)), Some(group2.gid)).unwrap();
+    chmod(&group_file, Mode::from_bits_truncate(0o060)).unwrap();
+
+    for (user, gid, readable_through_mount) in
+        [(user1, group1.gid, true), (user2, group2.gid, false)]
+    {
+        for name in ["owner", "group"] {
+            for (dir, through_mount) in
+                [(mount.target.path(), true), (mount.source.as_path(), false)]
+            {
+                let path = dir.join(name);
+                let allowed = readable_through_mount == through_mount;
+                ctx.as_user(user, Some(&[gid]), || {
+                    let res = open(&path, OFlag::O_RDONLY, Mode::empty()).map(drop);
+                    if allowed {
+                        assert!(res.is_ok(), "{} {name}: {res:?}", user.name);
+                    } else {
+                        assert_eq!(res, Err(Errno::EACCES), "{} {name}", user.name);
+                    }
+                });
+            }
+        }
+    }
+}
+
+crate::test_case! {
+    /// An entry created through an idmapped mount in a directory with the S_ISGID bit set
+    /// gets the group ID of the directory on disk, and S_ISGID is cleared by chmod
+    /// when the group seen through the mount is not one of the caller's groups
+    sgid_dir, serialized, root, FileSystemFeature::IdmappedMounts
+}
+fn sgid_dir(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+    let (user2, group2) = entry2;
+
+    let dir = mount.source.join("dir");
+    mkdir(&dir, Mode::from_bits_truncate(0o777)).unwrap();
+    chown(&dir, None, Some(group2.gid)).unwrap();
+    chmod(&dir, Mode::from_bits_truncate(0o777 | Mode::S_ISGID.bits())).unwrap();
+
+    let path = mount.target.path().join("dir/file");
+    ctx.as_user(user2, Some(&[group2.gid]), || {
+        write(&path, "").unwrap();
+    });
+    assert_owner(
+        &mount,
+        "dir/file",
+        (user1.uid.as_raw(), group2.gid.as_raw()),
+        (user2.uid.as_raw(), group1.gid.as_raw()),
+    );
+
+    // `group2` is the group of the file on disk, but not through the mount
+    let sgid_mode = Mode::from_bits_truncate(0o755 | Mode::S_ISGID.bits());
+    ctx.as_user(user2, Some(&[group2.gid]), || {
+        chmod(&path, sgid_mode).unwrap();
+    });
+    assert_eq!(lstat(&path).unwrap().st_mode & 0o7777, 0o755);
+
+    ctx.as_user(user2, Some(&[group1.gid]), || {
+        chmod(&path, sgid_mode).unwrap();
+    });
+    assert_eq!(lstat(&path).unwrap().st_mode & 0o7777, sgid_mode.bits());
+}
+
+/// Guard which checks that POSIX ACLs are supported by the file system.
+pub(crate) fn posix_acls(_: &Config, base_path: &Path) -> anyhow::Result<()> {
+    // Set an ACL equivalent to the current mode
+    let mode = lstat(base_path)?.st_mode;
+    setfacl(&[base_path], &exacl::from_mode(mode), AclOption::empty())?;
+
+    Ok(())
+}
+
+/// Return the names of the named user and group entries of the ACL of `path`.
+fn acl_names(path: &Path) -> (String, String) {
+    let entries = getfacl(path, AclOption::empty()).unwrap();
+    let name = |kind| {
+        entries
+            .iter()
+            .find(|e| e.kind == kind && !e.name.is_empty())
+            .map(|e| e.name.clone())
+            .unwrap()
+    };
+
+    (name(AclEntryKind::User), name(AclEntryKind::Group))
+}
+
+crate::test_case! {
+    /// The users and groups of the ACL entries set through an idmapped mount
+    /// are mapped on disk, and permissions are checked against the mapped entries
+    acl_mapping, serialized, root, FileSystemFeature::IdmappedMounts; posix_acls
+}
+fn acl_mapping(ctx: &mut SerializedTestContext) {
+    let entry1 = ctx.get_new_entry();
+    let entry2 = ctx.get_new_entry();
+    let mount = IdmappedMount::new(ctx, entry1, entry2);
+    let (user1, group1) = entry1;
+    let (user2, group2) = entry2;
+
+    let path = mount.target.path().join("file");
+    write(&path, "").unwrap();
+
+    let mut entries = exacl::from_mode(0o600);
+    entries.extend([
+        AclEntry::allow_user(&user1.name, Perm::READ, None),
+        AclEntry::allow_group(&group1.name, Perm::READ, None),
+        AclEntry::allow_mask(Perm::READ, None),
+    ]);
+    setfacl(&[&path], &entries, AclOption::empty()).unwrap();
+
+    assert_eq!(acl_names(&path), (user1.name.clone(), group1.name.clone()));
+    assert_eq!(
+        acl_names(&mount.source.join("file")),
+        (user2.name.clone(), group2.name.clone())
+    );
+
+    // The named user entry grants access to `user2` on disk, and to `user1` through the mount.
+    // Neither of them is a member of the named groups.
+    for user in [user1, user2] {
+        for (dir, through_mount) in [(mount.target.path(), true), (mount.source.as_path(), false)] {
+            let path = dir.join("file");
+            let allowed = (user.uid == user1.uid) == through_mount;
+            ctx.as_user(user, Some(&[Gid::from_raw(0)]), || {
+                let res = open(&path, OFlag::O_RDONLY, Mode::empty()).map(drop);
+                if allowed {
+                    assert!(res.is_ok(), "{}: {res:?}", user.name);
+                } else {
+                    assert_eq!(res, Err(Errno::EACCES), "{}", user.name);
+                }
+            });
+        }
+    }
+}
@@ -2,7 +2,6 @@ use nix::{
     errno::Errno,
     sys::stat::{lstat, Mode},
     unistd::pathconf,
-    unistd::{chown, unlink},
 };
 
 use std::path::Path;
@@ -18,7 +17,13 @@ use super::{
     CTIME, MTIME,
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{
+    eperm_append_test_case, eperm_immutable_test_case, eperm_parent_immutable_test_case,
+};
+
 use crate::config::Config;
+use crate::outcome::{assert_outcome, Outcome};
 use crate::{
     context::{FileType, SerializedTestContext, TestContext},
     tests::{
@@ -26,7 +31,7 @@ use crate::{
         errors::enoent::enoent_either_named_file_test_case,
         errors::enotdir::enotdir_comp_either_test_case, AsTimeInvariant,
     },
-    utils::{chmod, link},
+    utils::{chmod, chown, link, unlink},
 };
 
 crate::test_case! {
@@ -220,6 +225,24 @@ erofs_named_test_case!(link, |ctx: &mut TestContext, file| {
     link(file, &path)
 });
 
+// link/12.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(link, |ctx: &mut TestContext, file: &Path| {
+    link(file, &ctx.gen_path())
+} => [Regular]);
+
+// link/12.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(link, |ctx: &mut TestContext, file: &Path| {
+    link(file, &ctx.gen_path())
+} => [Regular]);
+
+// link/13.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(link, |_: &mut TestContext, existing: &Path, new: &Path| {
+    link(existing, new)
+} => [Regular]);
+
 // link/09.t
 crate::test_case! {
     /// link returns ENOENT if the source file does not exist
@@ -244,6 +267,53 @@ fn eexist_dest_exists(ctx: &mut TestContext, ft: FileType) {
     assert_eq!(link(&regular_file, &path), Err(nix::errno::Errno::EEXIST));
 }
 
+crate::test_case! {
+    /// link may return EPERM if the source file is a directory
+    /// and the calling process has appropriate privileges
+    // link/11.t
+    eperm_dir_root, root
+}
+fn eperm_dir_root(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let new_path = ctx.gen_path();
+
+    // Some systems (e.g. Solaris with UFS) allow the super-user to link directories
+    let res = assert_outcome(
+        "link of a directory by the super-user",
+        link(&dir, &new_path),
+        &[Outcome::Error(Errno::EPERM), Outcome::Success],
+    );
+    if res.is_ok() {
+        assert_eq!(
+            lstat(&dir).unwrap().st_ino,
+            lstat(&new_path).unwrap().st_ino
+        );
+        unlink(&new_path).unwrap();
+    }
+}
+
+crate::test_case! {
+    /// link returns EPERM if the source file is a directory
+    /// and the calling process does not have appropriate privileges
+    // link/11.t
+    eperm_dir_unprivileged, serialized, root
+}
+fn eperm_dir_unprivileged(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    chown(ctx.base_path(), Some(user.uid), Some(user.gid)).unwrap();
+    let dir = ctx.gen_path();
+    let new_path = ctx.gen_path();
+
+    ctx.as_user(user, None, || {
+        ctx.new_file(FileType::Dir)
+            .name(&dir)
+            .mode(0o755)
+            .create()
+            .unwrap();
+        assert_eq!(link(&dir, &new_path), Err(Errno::EPERM));
+    });
+}
+
 // link/14.t
 exdev_target_test_case!(link);
 
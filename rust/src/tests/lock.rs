@@ -0,0 +1,475 @@
+//! Tests for advisory file locking with `fcntl` byte-range locks, open file description locks and `flock`.
+//!
+//! Byte-range locks are owned by a process, so conflicts are checked against a child process,
+//! which takes locks on behalf of the test.
+
+use std::{
+    mem::size_of,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, OFlag},
+    libc::{self, c_short, off_t},
+    sys::{stat::Mode, wait::waitpid},
+    unistd::{close, pipe, read, write, Pid},
+};
+
+use crate::{
+    context::{FileType, TestContext},
+    tests::spawn_child,
+    utils::{open, rename},
+};
+
+/// Return a `flock` structure describing a lock of type `l_type` on `len` bytes at `start`.
+fn flock(l_type: c_short, start: off_t, len: off_t) -> libc::flock {
+    // SAFETY: flock is a plain C structure, for which all zeroes is a valid value
+    let mut fl: libc::flock = unsafe { std::mem::zeroed() };
+    fl.l_type = l_type;
+    fl.l_whence = libc::SEEK_SET as c_short;
+    fl.l_start = start;
+    fl.l_len = len;
+
+    fl
+}
+
+/// Set a process-associated lock without waiting.
+fn setlk(fd: RawFd, l_type: c_short, start: off_t, len: off_t) -> nix::Result<()> {
+    fcntl(fd, FcntlArg::F_SETLK(&flock(l_type, start, len))).map(drop)
+}
+
+/// Return the first lock which would prevent a lock of type `l_type`
+/// on `len` bytes at `start`, or `None` if there is no such lock.
+fn getlk(fd: RawFd, l_type: c_short, start: off_t, len: off_t) -> Option<libc::flock> {
+    let mut fl = flock(l_type, start, len);
+    fcntl(fd, FcntlArg::F_GETLK(&mut fl)).unwrap();
+
+    (fl.l_type != libc::F_UNLCK as c_short).then_some(fl)
+}
+
+/// Assert that a conflicting lock was reported.
+/// POSIX allows both `EACCES` and `EAGAIN` for `F_SETLK`.
+fn assert_conflict(res: nix::Result<()>) {
+    assert!(
+        matches!(res, Err(Errno::EACCES | Errno::EAGAIN)),
+        "expected EACCES or EAGAIN, got {res:?}"
+    );
+}
+
+/// Assert that a lock reported by `F_GETLK` has the expected type and range.
+fn assert_lock(fl: Option<libc::flock>, l_type: c_short, start: off_t, len: off_t) {
+    let fl = fl.expect("no conflicting lock reported");
+    assert_eq!(
+        (fl.l_type, fl.l_start, fl.l_len),
+        (l_type, start, len),
+        "unexpected conflicting lock"
+    );
+}
+
+const RD: c_short = libc::F_RDLCK as c_short;
+const WR: c_short = libc::F_WRLCK as c_short;
+const UN: c_short = libc::F_UNLCK as c_short;
+
+/// Requests which can be sent to a [`Locker`].
+#[derive(Debug, Clone, Copy)]
+enum Request {
+    /// Set a lock with `F_SETLK`.
+    SetLk(c_short, off_t, off_t),
+    /// Reply immediately, then wait for the delay before removing the lock on the range.
+    UnlockAfter(Duration, off_t, off_t),
+    Exit,
+}
+
+/// Size of an encoded [`Request`].
+const REQUEST_LEN: usize = 4 * size_of::<off_t>();
+
+impl Request {
+    fn encode(self) -> [u8; REQUEST_LEN] {
+        let fields: [off_t; 4] = match self {
+            Request::SetLk(l_type, start, len) => [0, l_type.into(), start, len],
+            Request::UnlockAfter(delay, start, len) => [1, delay.as_millis() as off_t, start, len],
+            Request::Exit => [2, 0, 0, 0],
+        };
+
+        let mut buf = [0; REQUEST_LEN];
+        for (chunk, field) in buf.chunks_mut(size_of::<off_t>()).zip(fields) {
+            chunk.copy_from_slice(&field.to_ne_bytes());
+        }
+        buf
+    }
+
+    fn decode(buf: &[u8; REQUEST_LEN]) -> Self {
+        let mut fields = buf
+            .chunks(size_of::<off_t>())
+            .map(|chunk| off_t::from_ne_bytes(chunk.try_into().unwrap()));
+        let mut next = || fields.next().unwrap();
+
+        match next() {
+            0 => Request::SetLk(next() as c_short, next(), next()),
+            1 => Request::UnlockAfter(Duration::from_millis(next() as u64), next(), next()),
+            _ => Request::Exit,
+        }
+    }
+}
+
+/// Child process which opens a file and takes locks on it on behalf of the test.
+struct Locker {
+    pid: Pid,
+    requests: OwnedFd,
+    replies: OwnedFd,
+}
+
+impl Locker {
+    /// Fork a child process which opens `path` for reading and writing.
+    fn spawn(path: &Path) -> Self {
+        let (requests_rx, requests_tx) = pipe().unwrap();
+        let (replies_rx, replies_tx) = pipe().unwrap();
+
+        let (parent_requests, parent_replies) = (requests_tx.as_raw_fd(), replies_rx.as_raw_fd());
+        let pid = spawn_child(move || {
+            // Only keep the ends of the pipes used by the child process
+            let _ = close(parent_requests);
+            let _ = close(parent_replies);
+            Self::serve(path, requests_rx, replies_tx);
+        });
+
+        Self {
+            pid,
+            requests: requests_tx,
+            replies: replies_rx,
+        }
+    }
+
+    fn serve(path: &Path, requests: OwnedFd, replies: OwnedFd) {
+        let fd = open(path, OFlag::O_RDWR, Mode::empty()).unwrap();
+
+        loop {
+            let mut buf = [0; REQUEST_LEN];
+            if read(requests.as_raw_fd(), &mut buf) != Ok(buf.len()) {
+                return;
+            }
+
+            let res = match Request::decode(&buf) {
+                Request::SetLk(l_type, start, len) => setlk(fd.as_raw_fd(), l_type, start, len),
+                Request::UnlockAfter(delay, start, len) => {
+                    write(&replies, &0i32.to_ne_bytes()).unwrap();
+                    std::thread::sleep(delay);
+                    setlk(fd.as_raw_fd(), UN, start, len).unwrap();
+                    continue;
+                }
+                Request::Exit => return,
+            };
+
+            let errno = res.err().map_or(0, |e| e as i32);
+            write(&replies, &errno.to_ne_bytes()).unwrap();
+        }
+    }
+
+    fn request(&self, request: Request) -> nix::Result<()> {
+        write(&self.requests, &request.encode()).unwrap();
+
+        let mut buf = [0; size_of::<i32>()];
+        assert_eq!(read(self.replies.as_raw_fd(), &mut buf), Ok(buf.len()));
+        match i32::from_ne_bytes(buf) {
+            0 => Ok(()),
+            errno => Err(Errno::from_raw(errno)),
+        }
+    }
+
+    /// Set a lock in the child process with `F_SETLK`.
+    fn setlk(&self, l_type: c_short, start: off_t, len: off_t) -> nix::Result<()> {
+        self.request(Request::SetLk(l_type, start, len))
+    }
+}
+
+impl Drop for Locker {
+    fn drop(&mut self) {
+        let _ = write(&self.requests, &Request::Exit.encode());
+        let status = waitpid(self.pid, None);
+        if !std::thread::panicking() {
+            assert_eq!(
+                status,
+                Ok(nix::sys::wait::WaitStatus::Exited(self.pid, 0)),
+                "locker process failed"
+            );
+        }
+    }
+}
+
+crate::test_case! {
+    /// F_SETLK fails when the range overlaps a conflicting lock of another process
+    setlk_conflicts
+}
+fn setlk_conflicts(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd = fd.as_raw_fd();
+    let locker = Locker::spawn(&path);
+
+    locker.setlk(WR, 100, 100).unwrap();
+    locker.setlk(RD, 300, 100).unwrap();
+
+    assert_conflict(setlk(fd, WR, 150, 10));
+    assert_conflict(setlk(fd, RD, 150, 10));
+    assert_conflict(setlk(fd, WR, 50, 51));
+    assert_conflict(setlk(fd, WR, 199, 1));
+    // Adjacent ranges do not conflict
+    setlk(fd, WR, 0, 100).unwrap();
+    setlk(fd, WR, 200, 10).unwrap();
+
+    // Read locks are shared
+    setlk(fd, RD, 300, 100).unwrap();
+    assert_conflict(setlk(fd, WR, 350, 10));
+
+    // A length of 0 extends to the end of the file, however it grows
+    assert_conflict(setlk(fd, WR, 0, 0));
+    setlk(fd, WR, 400, 0).unwrap();
+    assert_conflict(locker.setlk(RD, 1_000_000, 1));
+}
+
+crate::test_case! {
+    /// F_GETLK reports a conflicting lock of another process, or F_UNLCK if there is none
+    getlk_reports_conflict
+}
+fn getlk_reports_conflict(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd = fd.as_raw_fd();
+    let locker = Locker::spawn(&path);
+
+    locker.setlk(WR, 100, 100).unwrap();
+
+    let fl = getlk(fd, WR, 0, 1000);
+    assert_lock(fl, WR, 100, 100);
+    assert_eq!(fl.unwrap().l_pid, locker.pid.as_raw());
+    assert_lock(getlk(fd, RD, 150, 1), WR, 100, 100);
+    assert!(getlk(fd, WR, 0, 100).is_none());
+    assert!(getlk(fd, WR, 200, 0).is_none());
+
+    // Locks owned by the calling process never conflict
+    setlk(fd, WR, 300, 100).unwrap();
+    assert!(getlk(fd, WR, 300, 100).is_none());
+}
+
+crate::test_case! {
+    /// Unlocking or changing the type of the middle of a locked range splits the lock
+    lock_splitting
+}
+fn lock_splitting(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd = fd.as_raw_fd();
+    let locker = Locker::spawn(&path);
+
+    locker.setlk(WR, 0, 300).unwrap();
+    locker.setlk(UN, 100, 100).unwrap();
+
+    assert!(getlk(fd, WR, 100, 100).is_none());
+    assert_lock(getlk(fd, WR, 0, 150), WR, 0, 100);
+    assert_lock(getlk(fd, WR, 150, 150), WR, 200, 100);
+    setlk(fd, WR, 100, 100).unwrap();
+    setlk(fd, UN, 100, 100).unwrap();
+
+    // Downgrade the middle of a write lock to a read lock
+    locker.setlk(WR, 0, 300).unwrap();
+    locker.setlk(RD, 100, 100).unwrap();
+
+    setlk(fd, RD, 100, 100).unwrap();
+    assert_conflict(setlk(fd, RD, 50, 60));
+    assert_conflict(setlk(fd, RD, 190, 20));
+    assert_lock(getlk(fd, WR, 100, 100), RD, 100, 100);
+
+    // Merging adjacent locks of the same type
+    setlk(fd, UN, 0, 0).unwrap();
+    locker.setlk(WR, 100, 100).unwrap();
+    assert_lock(getlk(fd, RD, 0, 0), WR, 0, 300);
+}
+
+crate::test_case! {
+    /// F_SETLKW waits until the conflicting lock is released
+    setlkw_waits
+}
+fn setlkw_waits(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd = fd.as_raw_fd();
+    let locker = Locker::spawn(&path);
+    let delay = Duration::from_millis(200);
+
+    locker.setlk(WR, 0, 100).unwrap();
+    let start = Instant::now();
+    locker.request(Request::UnlockAfter(delay, 0, 100)).unwrap();
+
+    fcntl(fd, FcntlArg::F_SETLKW(&flock(WR, 50, 10))).unwrap();
+    assert!(start.elapsed() >= delay, "F_SETLKW did not wait");
+    assert_conflict(locker.setlk(RD, 50, 10));
+}
+
+crate::test_case! {
+    /// Closing any file descriptor of a file releases all the locks the process holds on it
+    close_releases_locks
+}
+fn close_releases_locks(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let locker = Locker::spawn(&path);
+
+    setlk(fd.as_raw_fd(), WR, 0, 100).unwrap();
+    setlk(fd.as_raw_fd(), RD, 200, 100).unwrap();
+    assert_conflict(locker.setlk(WR, 0, 1));
+
+    // Closing an unrelated descriptor of another file does not release the locks
+    let (_other_path, other_fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    drop(other_fd);
+    assert_conflict(locker.setlk(WR, 0, 1));
+
+    // Closing another descriptor of the same file does
+    let fd2 = open(&path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    drop(fd2);
+
+    locker.setlk(WR, 0, 0).unwrap();
+}
+
+crate::test_case! {
+    /// Locks stay attached to the file when its path is renamed or unlinked
+    survive_rename_unlink
+}
+fn survive_rename_unlink(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let locker = Locker::spawn(&path);
+    locker.setlk(WR, 0, 100).unwrap();
+
+    let new_path = ctx.gen_path();
+    rename(&path, &new_path).unwrap();
+    let fd2 = open(&new_path, OFlag::O_RDWR, Mode::empty()).unwrap();
+    assert_lock(getlk(fd2.as_raw_fd(), WR, 0, 0), WR, 0, 100);
+    assert_conflict(setlk(fd2.as_raw_fd(), WR, 0, 1));
+
+    std::fs::remove_file(&new_path).unwrap();
+    assert_lock(getlk(fd.as_raw_fd(), WR, 0, 0), WR, 0, 100);
+    assert_conflict(setlk(fd.as_raw_fd(), WR, 50, 1));
+
+    // A new file at the same path is unrelated
+    let (_, fd3) = ctx
+        .new_file(FileType::Regular)
+        .name(&new_path)
+        .open(OFlag::O_RDWR)
+        .unwrap();
+    setlk(fd3.as_raw_fd(), WR, 0, 100).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+/// Set an open file description lock without waiting.
+fn ofd_setlk(fd: &impl AsRawFd, l_type: c_short, start: off_t, len: off_t) -> nix::Result<()> {
+    fcntl(
+        fd.as_raw_fd(),
+        FcntlArg::F_OFD_SETLK(&flock(l_type, start, len)),
+    )
+    .map(drop)
+}
+
+#[cfg(target_os = "linux")]
+/// Wrapper for `flock(2)`.
+fn bsd_flock(fd: &impl AsRawFd, operation: libc::c_int) -> nix::Result<()> {
+    Errno::result(unsafe { libc::flock(fd.as_raw_fd(), operation) }).map(drop)
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// Open file description locks are owned by the open file description
+    /// rather than by the process
+    ofd_per_description
+}
+#[cfg(target_os = "linux")]
+fn ofd_per_description(ctx: &mut TestContext) {
+    let (path, fd1) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd2 = open(&path, OFlag::O_RDWR, Mode::empty()).unwrap();
+
+    ofd_setlk(&fd1, WR, 0, 100).unwrap();
+    // Another open file description conflicts, even in the same process
+    assert_conflict(ofd_setlk(&fd2, WR, 50, 10));
+    assert_conflict(ofd_setlk(&fd2, RD, 0, 1));
+    // A duplicate shares the open file description
+    let dup = fd1.try_clone().unwrap();
+    ofd_setlk(&dup, WR, 50, 10).unwrap();
+
+    // F_OFD_GETLK reports -1 as the owner
+    let mut fl = flock(WR, 0, 0);
+    fcntl(fd2.as_raw_fd(), FcntlArg::F_OFD_GETLK(&mut fl)).unwrap();
+    assert_eq!((fl.l_type, fl.l_start, fl.l_len), (WR, 0, 100));
+    assert_eq!(fl.l_pid, -1);
+
+    // Closing other descriptors of the file does not release the lock
+    drop(fd2);
+    drop(dup);
+    let fd3 = open(&path, OFlag::O_RDWR, Mode::empty()).unwrap();
+    assert_conflict(ofd_setlk(&fd3, WR, 0, 1));
+
+    // Process-associated locks conflict with open file description locks
+    let locker = Locker::spawn(&path);
+    assert_conflict(locker.setlk(WR, 0, 1));
+    assert_conflict(setlk(fd3.as_raw_fd(), WR, 0, 1));
+    // The child process inherited a descriptor of the open file description
+    drop(locker);
+
+    // Closing the last descriptor of the open file description releases the lock
+    drop(fd1);
+    ofd_setlk(&fd3, WR, 0, 100).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// F_OFD_SETLKW waits until the conflicting lock is released by another thread
+    ofd_setlkw_thread
+}
+#[cfg(target_os = "linux")]
+fn ofd_setlkw_thread(ctx: &mut TestContext) {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    let (path, fd1) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd2 = open(&path, OFlag::O_RDWR, Mode::empty()).unwrap();
+    ofd_setlk(&fd1, WR, 0, 100).unwrap();
+
+    let locked = Arc::new(AtomicBool::new(false));
+    let waiter = {
+        let locked = Arc::clone(&locked);
+        std::thread::spawn(move || {
+            let res = fcntl(fd2.as_raw_fd(), FcntlArg::F_OFD_SETLKW(&flock(WR, 50, 10)));
+            locked.store(true, Ordering::SeqCst);
+            res.map(drop)
+        })
+    };
+
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(!locked.load(Ordering::SeqCst), "F_OFD_SETLKW did not wait");
+
+    ofd_setlk(&fd1, libc::F_UNLCK as c_short, 0, 100).unwrap();
+    waiter.join().unwrap().unwrap();
+    assert!(locked.load(Ordering::SeqCst));
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// flock and fcntl locks are independent from each other
+    flock_fcntl_independent
+}
+#[cfg(target_os = "linux")]
+fn flock_fcntl_independent(ctx: &mut TestContext) {
+    let (path, fd1) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd2 = open(&path, OFlag::O_RDWR, Mode::empty()).unwrap();
+    let locker = Locker::spawn(&path);
+
+    bsd_flock(&fd1, libc::LOCK_EX).unwrap();
+    // flock locks are per open file description
+    assert_eq!(
+        bsd_flock(&fd2, libc::LOCK_EX | libc::LOCK_NB),
+        Err(Errno::EWOULDBLOCK)
+    );
+    locker.setlk(WR, 0, 0).unwrap();
+    assert_conflict(ofd_setlk(&fd2, RD, 0, 0));
+
+    bsd_flock(&fd1, libc::LOCK_UN).unwrap();
+    // The byte-range lock of the child doesn't prevent flock
+    bsd_flock(&fd2, libc::LOCK_EX | libc::LOCK_NB).unwrap();
+}
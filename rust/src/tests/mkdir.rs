@@ -9,6 +9,8 @@ use super::errors::efault::efault_path_test_case;
 use super::errors::eloop::eloop_comp_test_case;
 use super::errors::enametoolong::{enametoolong_comp_test_case, enametoolong_path_test_case};
 use super::errors::enoent::enoent_comp_test_case;
+#[cfg(inode_flags)]
+use super::errors::eperm::eperm_parent_immutable_test_case;
 use super::errors::erofs::erofs_new_file_test_case;
 use super::mksyscalls::{assert_perms_from_mode_and_umask, assert_uid_gid};
 use super::{assert_times_changed, errors::enotdir::enotdir_comp_test_case, ATIME, CTIME, MTIME};
@@ -72,6 +74,12 @@ eloop_comp_test_case!(mkdir(~path, Mode::empty()));
 // mkdir/09.t
 erofs_new_file_test_case!(mkdir(~path, Mode::empty()));
 
+// mkdir/08.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(mkdir, |_: &mut TestContext, _: &std::path::Path, new: &std::path::Path| {
+    mkdir(new, Mode::from_bits_truncate(0o755))
+} => [Regular]);
+
 // mkdir/10.t
 eexist_file_exists_test_case!(mkdir(~path, Mode::empty()));
 
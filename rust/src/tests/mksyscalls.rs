@@ -2,18 +2,22 @@
 
 use std::{
     fs::{metadata, FileType},
-    os::unix::prelude::PermissionsExt,
+    os::{fd::AsRawFd, unix::prelude::PermissionsExt},
     path::Path,
 };
 
 use nix::{
-    sys::stat::{lstat, mode_t, Mode},
-    unistd::{chown, Gid, Uid, User},
+    fcntl::OFlag,
+    sys::{
+        socket::{bind, socket, AddressFamily, SockFlag, SockType, UnixAddr},
+        stat::{lstat, mknod, mode_t, Mode, SFlag},
+    },
+    unistd::{mkdir, mkfifo, Gid, Group, Uid, User},
 };
 
 use crate::{
     context::SerializedTestContext,
-    utils::{chmod, ALLPERMS},
+    utils::{chmod, chown, open, symlink, ALLPERMS},
 };
 
 /// Assert that the created entry gets its permission bits from the mode
@@ -86,11 +90,26 @@ pub(super) fn assert_uid_gid<F, T>(ctx: &mut SerializedTestContext, f: F)
 where
     F: Fn(&Path, Mode) -> nix::Result<T>,
 {
-    fn doit<F, T>(ctx: &SerializedTestContext, user: &User, gid: Option<Gid>, f: F)
+    let user1 = ctx.get_new_user();
+    let entry2 = ctx.get_new_entry();
+    assert_uid_gid_in(ctx, ctx.base_path(), user1, entry2, f);
+}
+
+/// Same as [`assert_uid_gid`], but creating the entries in `dir` as the provided users.
+pub(super) fn assert_uid_gid_in<F, T>(
+    ctx: &SerializedTestContext,
+    dir: &Path,
+    user1: &User,
+    (user2, group2): (&User, &Group),
+    f: F,
+) where
+    F: Fn(&Path, Mode) -> nix::Result<T>,
+{
+    fn doit<F, T>(ctx: &SerializedTestContext, dir: &Path, user: &User, gid: Option<Gid>, f: F)
     where
         F: Fn(&Path, Mode) -> nix::Result<T>,
     {
-        let path = ctx.gen_path();
+        let path = dir.join(ctx.gen_path().file_name().unwrap());
         ctx.as_user(user, gid.map(|g| vec![g]).as_deref(), || {
             f(&path, Mode::from_bits_truncate(0o755)).unwrap();
         });
@@ -99,21 +118,44 @@ where
         assert_eq!(filestat.st_uid, user.uid.as_raw());
 
         let egid = gid.unwrap_or(user.gid).as_raw();
-        let dirstat = lstat(ctx.base_path()).unwrap();
+        let dirstat = lstat(dir).unwrap();
         assert!(filestat.st_gid == egid || filestat.st_gid == dirstat.st_gid);
     }
 
     let user0 = User::from_uid(Uid::effective()).unwrap().unwrap();
-    doit(ctx, &user0, None, &f);
+    doit(ctx, dir, &user0, None, &f);
 
-    let user1 = ctx.get_new_user();
     // To check that the entry gid is either parent gid or egid
-    chown(ctx.base_path(), Some(user1.uid), Some(user1.gid)).unwrap();
+    chown(dir, Some(user1.uid), Some(user1.gid)).unwrap();
+
+    doit(ctx, dir, user1, Some(group2.gid), &f);
 
-    let (user2, group2) = ctx.get_new_entry();
-    doit(ctx, user1, Some(group2.gid), &f);
+    chmod(dir, Mode::from_bits_truncate(ALLPERMS)).unwrap();
 
-    chmod(ctx.base_path(), Mode::from_bits_truncate(ALLPERMS)).unwrap();
+    doit(ctx, dir, user2, Some(group2.gid), f);
+}
 
-    doit(ctx, user2, Some(group2.gid), f);
+fn bind_socket(path: &Path) -> nix::Result<()> {
+    let fd = socket(
+        AddressFamily::Unix,
+        SockType::Stream,
+        SockFlag::empty(),
+        None,
+    )?;
+    bind(fd.as_raw_fd(), &UnixAddr::new(path)?)
 }
+
+/// Function creating a new entry at the provided path with the provided mode.
+pub(super) type Create = fn(&Path, Mode) -> nix::Result<()>;
+
+/// Syscalls which create a new entry, `symlink` and `bind` ignoring the mode.
+pub(super) const CREATORS: [(&str, Create); 6] = [
+    ("open", |path, mode| {
+        open(path, OFlag::O_CREAT | OFlag::O_WRONLY, mode).map(drop)
+    }),
+    ("mkdir", mkdir),
+    ("mkfifo", mkfifo),
+    ("mknod", |path, mode| mknod(path, SFlag::S_IFIFO, mode, 0)),
+    ("symlink", |path, _| symlink(Path::new("test"), path)),
+    ("bind", |path, _| bind_socket(path)),
+];
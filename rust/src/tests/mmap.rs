@@ -0,0 +1,233 @@
+//! Tests for memory-mapped I/O with `mmap`.
+
+use std::{
+    ffi::c_void,
+    num::NonZeroUsize,
+    os::fd::{AsFd, AsRawFd},
+    ptr::NonNull,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::off_t,
+    sys::{
+        mman::{mmap, msync, munmap, MapFlags, MsFlags, ProtFlags},
+        signal::Signal,
+        stat::{fstat, Mode},
+        uio::pwrite,
+        wait::WaitStatus,
+    },
+    unistd::{sysconf, SysconfVar},
+};
+
+use crate::{
+    context::TestContext,
+    tests::{
+        assert_times_changed,
+        data::{create_with, pattern, pread_exact},
+        run_in_child, CTIME, MTIME,
+    },
+    utils::{ftruncate, open},
+};
+
+/// A memory mapping of a file, which is unmapped on drop.
+pub(super) struct Mapping {
+    ptr: NonNull<c_void>,
+    len: usize,
+}
+
+impl Mapping {
+    /// Map `len` bytes of the file from the beginning.
+    pub(super) fn new<F: AsFd>(
+        fd: F,
+        len: usize,
+        prot: ProtFlags,
+        flags: MapFlags,
+    ) -> nix::Result<Self> {
+        let length = NonZeroUsize::new(len).unwrap();
+        // SAFETY: the mapping is only accessed through the bounds-checked slices below
+        let ptr = unsafe { mmap(None, length, prot, flags, fd, 0)? };
+
+        Ok(Self { ptr, len })
+    }
+
+    pub(super) fn as_slice(&self) -> &[u8] {
+        // SAFETY: the mapping is valid for `len` bytes until it is dropped
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr().cast(), self.len) }
+    }
+
+    pub(super) fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is valid for `len` bytes until it is dropped
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr().cast(), self.len) }
+    }
+
+    /// Synchronously write back the mapping to the file.
+    pub(super) fn sync(&self) {
+        unsafe { msync(self.ptr, self.len, MsFlags::MS_SYNC) }.unwrap();
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.ptr, self.len) };
+    }
+}
+
+pub(super) fn page_size() -> usize {
+    sysconf(SysconfVar::PAGE_SIZE).unwrap().unwrap() as usize
+}
+
+crate::test_case! {
+    /// Writes through a shared mapping are visible to read after msync
+    shared_write_visible_to_read
+}
+fn shared_write_visible_to_read(ctx: &mut TestContext) {
+    let len = 2 * page_size();
+    let mut expected = pattern(len as off_t, None);
+    let (path, fd) = create_with(ctx, &expected);
+    let mut mapping = Mapping::new(
+        &fd,
+        len,
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        MapFlags::MAP_SHARED,
+    )
+    .unwrap();
+    assert_eq!(mapping.as_slice(), expected);
+
+    // Across the page boundary
+    let offset = page_size() - 10;
+    mapping.as_mut_slice()[offset..offset + 20].fill(0);
+    expected[offset..offset + 20].fill(0);
+    mapping.as_mut_slice()[len - 1] = 0;
+    expected[len - 1] = 0;
+    mapping.sync();
+
+    assert_eq!(pread_exact(&fd, len, 0), expected);
+    // Another open file description sees the changes as well
+    let fd2 = open(&path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    assert_eq!(pread_exact(&fd2, len, 0), expected);
+}
+
+crate::test_case! {
+    /// Writes with write are visible through an existing shared mapping
+    write_visible_through_mapping
+}
+fn write_visible_through_mapping(ctx: &mut TestContext) {
+    let len = 2 * page_size();
+    let mut expected = pattern(len as off_t, None);
+    let (_path, fd) = create_with(ctx, &expected);
+    let mapping = Mapping::new(&fd, len, ProtFlags::PROT_READ, MapFlags::MAP_SHARED).unwrap();
+    // Fault the pages in before writing
+    assert_eq!(mapping.as_slice(), expected);
+
+    let offset = page_size() - 5;
+    let data = [0; 10];
+    assert_eq!(pwrite(&fd, &data, offset as off_t).unwrap(), data.len());
+    expected[offset..offset + data.len()].copy_from_slice(&data);
+
+    assert_eq!(mapping.as_slice(), expected);
+}
+
+crate::test_case! {
+    /// Private mappings do not write back to the file
+    private_write_not_visible
+}
+fn private_write_not_visible(ctx: &mut TestContext) {
+    let len = page_size();
+    let expected = pattern(len as off_t, None);
+    let (_path, fd) = create_with(ctx, &expected);
+    let mut mapping = Mapping::new(
+        &fd,
+        len,
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        MapFlags::MAP_PRIVATE,
+    )
+    .unwrap();
+
+    mapping.as_mut_slice().fill(0);
+    mapping.sync();
+
+    assert_eq!(pread_exact(&fd, len, 0), expected);
+}
+
+crate::test_case! {
+    /// Accessing a page which is entirely beyond the end of the file after ftruncate delivers SIGBUS,
+    /// while the rest of the last page reads as zeros
+    sigbus_beyond_eof
+}
+fn sigbus_beyond_eof(ctx: &mut TestContext) {
+    let page_size = page_size();
+    let len = 2 * page_size;
+    let data = pattern(len as off_t, None);
+    let (_path, fd) = create_with(ctx, &data);
+    let mapping = Mapping::new(&fd, len, ProtFlags::PROT_READ, MapFlags::MAP_SHARED).unwrap();
+    assert_eq!(mapping.as_slice(), data);
+
+    ftruncate(&fd, 100).unwrap();
+    assert_eq!(fstat(fd.as_raw_fd()).unwrap().st_size, 100);
+
+    assert_eq!(&mapping.as_slice()[..100], &data[..100]);
+    assert!(mapping.as_slice()[100..page_size].iter().all(|&b| b == 0));
+
+    let status = run_in_child(|| {
+        // SAFETY: the address is inside the mapping
+        let _ = unsafe { std::ptr::read_volatile(mapping.as_slice().as_ptr().add(page_size)) };
+    });
+    assert!(
+        matches!(status, WaitStatus::Signaled(_, Signal::SIGBUS, _)),
+        "expected the child process to be killed by SIGBUS, got {status:?}"
+    );
+}
+
+crate::test_case! {
+    /// Modifying a file through a shared mapping updates ctime and mtime
+    mapping_write_changes_times
+}
+fn mapping_write_changes_times(ctx: &mut TestContext) {
+    let len = page_size();
+    let (path, fd) = create_with(ctx, &pattern(len as off_t, None));
+    let mut mapping = Mapping::new(
+        &fd,
+        len,
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        MapFlags::MAP_SHARED,
+    )
+    .unwrap();
+
+    assert_times_changed()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            mapping.as_mut_slice()[0] = 0;
+            mapping.sync();
+        });
+}
+
+crate::test_case! {
+    /// mmap returns EACCES if the protection requested is not allowed by the open mode of the file
+    eacces_open_mode
+}
+fn eacces_open_mode(ctx: &mut TestContext) {
+    let len = page_size();
+    let (path, _fd) = create_with(ctx, &pattern(len as off_t, None));
+    let rdonly = open(&path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    let wronly = open(&path, OFlag::O_WRONLY, Mode::empty()).unwrap();
+    let read_write = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+
+    assert_eq!(
+        Mapping::new(&rdonly, len, read_write, MapFlags::MAP_SHARED).err(),
+        Some(Errno::EACCES)
+    );
+    assert_eq!(
+        Mapping::new(&wronly, len, ProtFlags::PROT_READ, MapFlags::MAP_SHARED).err(),
+        Some(Errno::EACCES)
+    );
+    assert_eq!(
+        Mapping::new(&wronly, len, ProtFlags::PROT_READ, MapFlags::MAP_PRIVATE).err(),
+        Some(Errno::EACCES)
+    );
+
+    // Private writable mappings only require read access
+    assert!(Mapping::new(&rdonly, len, read_write, MapFlags::MAP_PRIVATE).is_ok());
+    assert!(Mapping::new(&rdonly, len, ProtFlags::PROT_READ, MapFlags::MAP_SHARED).is_ok());
+}
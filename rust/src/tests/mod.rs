@@ -11,33 +11,72 @@ use std::fs::symlink_metadata;
 use std::ops::{BitAnd, BitOr};
 use std::os::unix::fs::MetadataExt as StdMetadataExt;
 
-use std::{fs::metadata, path::Path};
-
-use nix::sys::time::TimeSpec;
-
-use crate::test::TestContext;
+use std::{
+    fs::{metadata, File},
+    path::Path,
+    process::Command,
+};
+
+use nix::{
+    libc,
+    sys::{
+        stat::Mode,
+        time::TimeSpec,
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::{fork, ForkResult, Pid},
+};
+
+use crate::{test::TestContext, utils::chmod};
 
 #[cfg(chflags)]
 pub mod chflags;
 pub mod chmod;
 pub mod chown;
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+pub mod copy_file_range;
+mod data;
 pub mod errors;
+#[cfg(target_os = "linux")]
+pub mod fallocate;
+#[cfg(target_os = "linux")]
+pub mod ficlone;
 pub mod ftruncate;
+pub mod group_inheritance;
+#[cfg(target_os = "linux")]
+pub mod idmapped_mount;
 pub mod link;
+pub mod lock;
 pub mod mkdir;
 pub mod mkfifo;
 pub mod mknod;
 mod mksyscalls;
+pub mod mmap;
+#[cfg(target_os = "linux")]
+pub mod mount_options;
 #[cfg(any(target_os = "macos", target_os = "freebsd"))]
 pub mod nfsv4acl;
 pub mod open;
+#[cfg(target_os = "linux")]
+pub mod overlay;
 pub mod posix_fallocate;
+pub mod readdir;
 pub mod rename;
 pub mod rmdir;
+#[cfg(target_os = "linux")]
+mod scratch_mount;
+#[cfg(seek_hole)]
+pub mod sparse;
+#[cfg(statx)]
+pub mod statx;
+pub mod stress;
+pub mod suid_sgid;
 pub mod symlink;
 pub mod truncate;
 pub mod unlink;
+pub mod unlinked;
 pub mod utimensat;
+pub mod write;
 
 /// Argument to set which fields should be compared for [`TimeAssertion::path`].
 #[derive(Debug, Clone, Copy)]
@@ -140,6 +179,26 @@ impl AsTimeInvariant for nix::sys::stat::FileStat {
     }
 }
 
+#[cfg(statx)]
+impl AsTimeInvariant for nix::libc::statx {
+    fn as_time_invariant(&self) -> InvariantTimeMetadata {
+        use crate::utils::dev::makedev;
+
+        InvariantTimeMetadata {
+            st_dev: makedev(self.stx_dev_major.into(), self.stx_dev_minor.into()),
+            st_ino: self.stx_ino as nix::libc::ino_t,
+            st_mode: self.stx_mode.into(),
+            st_nlink: self.stx_nlink as nix::libc::nlink_t,
+            st_uid: self.stx_uid,
+            st_gid: self.stx_gid,
+            st_rdev: makedev(self.stx_rdev_major.into(), self.stx_rdev_minor.into()),
+            st_size: self.stx_size as nix::libc::off_t,
+            st_blksize: self.stx_blksize as nix::libc::blksize_t,
+            st_blocks: self.stx_blocks as nix::libc::blkcnt_t,
+        }
+    }
+}
+
 #[cfg(birthtime)]
 // Note: can't be a method of MetadataExt, because StdMetadataExt lacks a
 // birthtime() method.
@@ -296,3 +355,38 @@ where
         .path(path, CTIME)
         .execute(ctx, true, f)
 }
+
+/// Copy the `sleep` executable to `path`, so that it can be executed from the tested file system.
+fn copy_sleep(path: &Path) {
+    let sleep_path =
+        String::from_utf8(Command::new("which").arg("sleep").output().unwrap().stdout).unwrap();
+
+    std::io::copy(
+        &mut File::open(sleep_path.trim()).unwrap(),
+        &mut File::create(path).unwrap(),
+    )
+    .unwrap();
+    chmod(path, Mode::from_bits_truncate(0o755)).unwrap();
+}
+
+/// Run `f` in a child process and return its pid without waiting for it.
+///
+/// The child process exits with status 0 once `f` returns, or 1 if it panics.
+fn spawn_child<F: FnOnce()>(f: F) -> Pid {
+    // SAFETY: the test runner is single-threaded, and the child process
+    // exits without returning to the caller.
+    match unsafe { fork() }.unwrap() {
+        ForkResult::Child => {
+            // Never unwind into the test runner from the child process
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+            // SAFETY: _exit does not run the destructors and exit handlers of the test runner
+            unsafe { libc::_exit(i32::from(res.is_err())) }
+        }
+        ForkResult::Parent { child } => child,
+    }
+}
+
+/// Run `f` in a child process and wait for it to terminate.
+fn run_in_child<F: FnOnce()>(f: F) -> WaitStatus {
+    waitpid(spawn_child(f), None).unwrap()
+}
@@ -0,0 +1,207 @@
+//! Semantics of the `noexec`, `nodev`, `nosuid`, `noatime`, `relatime` and `strictatime` mount options.
+//!
+//! Each test creates scratch mounts in its directory, either of tmpfs
+//! or of the file system under test through a configured device.
+//! These tests only run when the `[mount]` section is present in the configuration.
+
+use std::{
+    fs::{metadata, read, write},
+    path::Path,
+    process::Command,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    mount::{mount, MsFlags},
+    sys::{stat::Mode, time::TimeSpec},
+    unistd::Uid,
+};
+
+use super::{copy_sleep, scratch_mount::ScratchMount, MetadataExt};
+use crate::{
+    config::Config,
+    context::{FileType, TestContext},
+    utils::{chmod, chown, open},
+};
+
+/// Guard which checks if the mount option tests have been enabled.
+pub(crate) fn mount_enabled(config: &Config, _: &Path) -> anyhow::Result<()> {
+    if config.mount.is_none() {
+        anyhow::bail!("Mount option tests are not enabled in the configuration file")
+    }
+
+    Ok(())
+}
+
+/// Mount the configured file system with `flags` in a new directory.
+fn scratch_mount(ctx: &TestContext, flags: MsFlags) -> ScratchMount {
+    let config = ctx.mount_config().unwrap();
+    let path = ctx.create(FileType::Dir).unwrap();
+    let source = config
+        .device
+        .as_deref()
+        .unwrap_or_else(|| Path::new(&config.fs_type));
+
+    mount(
+        Some(source),
+        &path,
+        Some(config.fs_type.as_str()),
+        flags,
+        config.data.as_deref(),
+    )
+    .unwrap();
+    // Do not propagate anything to the other mount namespaces
+    mount(
+        None::<&str>,
+        &path,
+        None::<&str>,
+        MsFlags::MS_PRIVATE,
+        None::<&str>,
+    )
+    .unwrap();
+
+    ScratchMount::new(path)
+}
+
+crate::test_case! {
+    /// execve returns EACCES if the file resides on a file system mounted with noexec
+    noexec, root; mount_enabled
+}
+fn noexec(ctx: &mut TestContext) {
+    for (flags, denied) in [(MsFlags::empty(), false), (MsFlags::MS_NOEXEC, true)] {
+        let mount = scratch_mount(ctx, flags);
+        let exec_path = mount.gen_path(ctx);
+        copy_sleep(&exec_path);
+
+        let res = Command::new(&exec_path).arg("0").status();
+        if denied {
+            assert_eq!(res.unwrap_err().raw_os_error(), Some(Errno::EACCES as i32));
+        } else {
+            assert!(res.unwrap().success());
+        }
+    }
+}
+
+crate::test_case! {
+    /// open returns EACCES if the device resides on a file system mounted with nodev
+    nodev, root; mount_enabled => [Block, Char]
+}
+fn nodev(ctx: &mut TestContext, ft: FileType) {
+    for (flags, denied) in [(MsFlags::empty(), false), (MsFlags::MS_NODEV, true)] {
+        let mount = scratch_mount(ctx, flags);
+        let path = ctx
+            .new_file(ft.clone())
+            .name(mount.gen_path(ctx))
+            .create()
+            .unwrap();
+
+        // The device doesn't exist, so the open fails anyway without nodev
+        let res = open(&path, OFlag::O_RDONLY, Mode::empty()).map(drop);
+        if denied {
+            assert_eq!(res, Err(Errno::EACCES));
+        } else {
+            assert_ne!(res, Err(Errno::EACCES));
+        }
+    }
+}
+
+/// Return the effective user ID of a process.
+fn process_euid(pid: u32) -> Uid {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).unwrap();
+    // Real, effective, saved set and file system user IDs
+    let uids = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .unwrap();
+
+    Uid::from_raw(uids.split_whitespace().nth(1).unwrap().parse().unwrap())
+}
+
+crate::test_case! {
+    /// The S_ISUID bit of an executable is ignored if it resides on a file system mounted with nosuid
+    nosuid, root; mount_enabled
+}
+fn nosuid(ctx: &mut TestContext) {
+    let user = ctx.get_new_user();
+
+    for (flags, expected_euid) in [
+        (MsFlags::empty(), user.uid),
+        (MsFlags::MS_NOSUID, Uid::effective()),
+    ] {
+        let mount = scratch_mount(ctx, flags);
+        let exec_path = mount.gen_path(ctx);
+        copy_sleep(&exec_path);
+        chown(&exec_path, Some(user.uid), None).unwrap();
+        chmod(&exec_path, Mode::from_bits_truncate(0o4755)).unwrap();
+
+        // The process has been executed once spawn returns
+        let mut sleep_process = Command::new(&exec_path).arg("10").spawn().unwrap();
+        let euid = process_euid(sleep_process.id());
+        sleep_process.kill().unwrap();
+        sleep_process.wait().unwrap();
+
+        assert_eq!(euid, expected_euid, "{flags:?}");
+    }
+}
+
+/// Read the file and return its atime afterwards.
+fn read_atime(ctx: &TestContext, path: &Path) -> TimeSpec {
+    ctx.nap();
+    read(path).unwrap();
+
+    metadata(path).unwrap().atime_ts()
+}
+
+crate::test_case! {
+    /// Reading a file doesn't update its atime on a file system mounted with noatime
+    noatime, root; mount_enabled
+}
+fn noatime(ctx: &mut TestContext) {
+    let mount = scratch_mount(ctx, MsFlags::MS_NOATIME);
+    let path = mount.gen_path(ctx);
+    write(&path, "data").unwrap();
+
+    let atime = metadata(&path).unwrap().atime_ts();
+    assert_eq!(read_atime(ctx, &path), atime);
+    assert_eq!(read_atime(ctx, &path), atime);
+}
+
+crate::test_case! {
+    /// Reading a file updates its atime on a file system mounted with relatime
+    /// only if it is older than its mtime or ctime
+    relatime, root; mount_enabled
+}
+fn relatime(ctx: &mut TestContext) {
+    let mount = scratch_mount(ctx, MsFlags::MS_RELATIME);
+    let path = mount.gen_path(ctx);
+    write(&path, "data").unwrap();
+
+    let atime = metadata(&path).unwrap().atime_ts();
+    let new_atime = read_atime(ctx, &path);
+    assert!(new_atime > atime);
+
+    // The atime is more recent than the mtime and the ctime
+    assert_eq!(read_atime(ctx, &path), new_atime);
+
+    ctx.nap();
+    write(&path, "more data").unwrap();
+    assert!(read_atime(ctx, &path) > new_atime);
+}
+
+crate::test_case! {
+    /// Reading a file always updates its atime on a file system mounted with strictatime
+    strictatime, root; mount_enabled
+}
+fn strictatime(ctx: &mut TestContext) {
+    let mount = scratch_mount(ctx, MsFlags::MS_STRICTATIME);
+    let path = mount.gen_path(ctx);
+    write(&path, "data").unwrap();
+
+    let mut atime = metadata(&path).unwrap().atime_ts();
+    for _ in 0..2 {
+        let new_atime = read_atime(ctx, &path);
+        assert!(new_atime > atime);
+        atime = new_atime;
+    }
+}
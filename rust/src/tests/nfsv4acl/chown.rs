@@ -4,14 +4,14 @@ use std::{fs, os::unix::fs::MetadataExt};
 use nix::{
     errno::Errno,
     fcntl::OFlag,
-    unistd::{chown, Gid, Uid},
+    unistd::{Gid, Uid},
 };
 
 use super::prependacl;
 use crate::{
     context::{FileType, SerializedTestContext},
     test::FileSystemFeature,
-    utils::chmod,
+    utils::{chmod, chown},
     Mode,
 };
 
@@ -1,11 +1,10 @@
 //! Tests for ACL_WRITE_DATA
-use nix::unistd::unlink;
 
 use super::prependacl;
 use crate::{
     context::{FileBuilder, FileType, SerializedTestContext},
     test::FileSystemFeature,
-    utils::{rename, rmdir},
+    utils::{rename, rmdir, unlink},
 };
 
 crate::test_case! {
@@ -4,9 +4,10 @@
 use std::io::ErrorKind;
 
 use exacl::{getfacl, AclOption};
-use nix::{sys::stat::stat, unistd::chown};
+use nix::sys::stat::stat;
 
 use super::prependacl;
+use crate::utils::chown;
 use crate::{
     context::{FileType, SerializedTestContext},
     test::FileSystemFeature,
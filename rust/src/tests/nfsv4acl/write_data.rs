@@ -1,11 +1,11 @@
 //! Tests for ACL_WRITE_DATA
-use nix::{errno::Errno, unistd::unlink};
+use nix::errno::Errno;
 
 use super::prependacl;
 use crate::{
     context::{FileBuilder, FileType, SerializedTestContext},
     test::FileSystemFeature,
-    utils::{rename, rmdir},
+    utils::{rename, rmdir, unlink},
 };
 
 crate::test_case! {
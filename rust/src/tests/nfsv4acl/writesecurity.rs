@@ -7,14 +7,13 @@ use exacl::{AclEntry, AclOption};
 use nix::{
     errno::Errno,
     sys::stat::{stat, Mode},
-    unistd::chown,
 };
 
 use super::prependacl;
 use crate::{
     context::{FileType, SerializedTestContext},
     test::FileSystemFeature,
-    utils::{chmod, ALLPERMS},
+    utils::{chmod, chown, ALLPERMS},
 };
 
 crate::test_case! {
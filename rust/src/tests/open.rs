@@ -1,21 +1,26 @@
 use std::fs::{metadata, symlink_metadata, FileType as StdFileType};
 use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
 use std::os::unix::prelude::{MetadataExt, RawFd};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use nix::errno::Errno;
 use nix::fcntl::{open, OFlag};
-use nix::sys::stat::Mode;
+use nix::sys::stat::{mode_t, Mode};
 use nix::sys::uio::pwrite;
-use nix::unistd::close;
+use nix::unistd::{close, Group, User};
 
 use crate::context::{FileType, SerializedTestContext, TestContext};
+use crate::utils::{chmod, chown};
 
 use super::errors::eexist::eexist_file_exists_test_case;
 use super::errors::efault::efault_path_test_case;
 use super::errors::eloop::eloop_comp_test_case;
 use super::errors::enametoolong::{enametoolong_comp_test_case, enametoolong_path_test_case};
 use super::errors::enoent::{enoent_comp_test_case, enoent_named_file_test_case};
+#[cfg(inode_flags)]
+use super::errors::eperm::{
+    eperm_append_test_case, eperm_immutable_test_case, eperm_parent_immutable_test_case,
+};
 use super::errors::erofs::{erofs_named_test_case, erofs_new_file_test_case};
 use super::errors::etxtbsy::etxtbsy_test_case;
 use super::mksyscalls::{assert_perms_from_mode_and_umask, assert_uid_gid};
@@ -219,6 +224,228 @@ enoent_comp_test_case!(open(~path, OFlag::O_CREAT, Mode::from_bits_truncate(0o64
 // open/04.t
 enoent_named_file_test_case!(open(~path, OFlag::O_RDONLY, Mode::empty()));
 
+/// Class of the permission bits which apply to the caller.
+#[derive(Debug, Clone, Copy)]
+enum PermClass {
+    /// The caller is the owner of the file.
+    Owner,
+    /// The effective group ID of the caller is the group of the file.
+    Group,
+    /// The group of the file is one of the supplementary groups of the caller.
+    SupplementaryGroup,
+    /// The caller is neither the owner of the file nor a member of its group.
+    Other,
+}
+
+const PERM_CLASSES: [PermClass; 4] = [
+    PermClass::Owner,
+    PermClass::Group,
+    PermClass::SupplementaryGroup,
+    PermClass::Other,
+];
+
+impl PermClass {
+    /// Return a file mode granting `perm` to the class,
+    /// and every permission to the other classes.
+    fn mode(self, perm: mode_t) -> mode_t {
+        match self {
+            PermClass::Owner => perm << 6 | 0o077,
+            PermClass::Group | PermClass::SupplementaryGroup => perm << 3 | 0o707,
+            PermClass::Other => perm | 0o770,
+        }
+    }
+}
+
+/// Owner of the files of the permission matrix, and another user.
+struct PermMatrix<'a> {
+    owner: &'a User,
+    group: &'a Group,
+    other: &'a User,
+}
+
+impl<'a> PermMatrix<'a> {
+    fn new(ctx: &'a SerializedTestContext) -> Self {
+        let (owner, group) = ctx.get_new_entry();
+        Self {
+            owner,
+            group,
+            other: ctx.get_new_user(),
+        }
+    }
+
+    /// Create a file owned by the owner with `mode`.
+    fn create(&self, ctx: &SerializedTestContext, ft: FileType, mode: mode_t) -> PathBuf {
+        let path = ctx.create(ft.clone()).unwrap();
+        if ft == FileType::Regular {
+            std::fs::write(&path, "data").unwrap();
+        }
+        chown(&path, Some(self.owner.uid), Some(self.group.gid)).unwrap();
+        chmod(&path, Mode::from_bits_truncate(mode)).unwrap();
+
+        path
+    }
+
+    /// Execute the function as a caller of the class.
+    fn run<F>(&self, ctx: &SerializedTestContext, class: PermClass, f: F)
+    where
+        F: FnOnce(),
+    {
+        match class {
+            PermClass::Owner => ctx.as_user(self.owner, Some(&[self.group.gid]), f),
+            PermClass::Group => ctx.as_user(self.other, Some(&[self.group.gid]), f),
+            PermClass::SupplementaryGroup => {
+                ctx.as_user(self.other, Some(&[self.other.gid, self.group.gid]), f)
+            }
+            PermClass::Other => ctx.as_user(self.other, None, f),
+        }
+    }
+}
+
+/// Assert that each `open` of `denied`, performed by a caller of its class, fails with EACCES,
+/// without changing the size and the timestamps of the file.
+/// The timestamps of all the files are compared across a single nap.
+fn assert_eacces_unchanged(
+    ctx: &SerializedTestContext,
+    matrix: &PermMatrix,
+    denied: &[(PermClass, PathBuf, OFlag)],
+) {
+    let sizes: Vec<_> = denied
+        .iter()
+        .map(|(_, path, _)| symlink_metadata(path).unwrap().size())
+        .collect();
+
+    denied
+        .iter()
+        .fold(assert_times_unchanged(), |assertion, (_, path, _)| {
+            assertion.path(path, ATIME | CTIME | MTIME)
+        })
+        .execute(ctx, false, || {
+            for (class, path, flags) in denied {
+                matrix.run(ctx, *class, || {
+                    assert_eq!(
+                        open(path, *flags, Mode::empty()),
+                        Err(Errno::EACCES),
+                        "{class:?} {flags:?}"
+                    );
+                })
+            }
+        });
+
+    for ((_, path, _), size) in denied.iter().zip(sizes) {
+        assert_eq!(symlink_metadata(path).unwrap().size(), size);
+    }
+}
+
+crate::test_case! {
+    /// open returns EACCES when the required permissions (for reading and/or writing)
+    /// are denied for the given flags
+    // open/06.t
+    eacces_access_mode, serialized, root => [Regular, Fifo, Dir]
+}
+fn eacces_access_mode(ctx: &mut SerializedTestContext, ft: FileType) {
+    // Access modes, with the permission bits they require
+    let access_modes: &[(OFlag, mode_t)] = match ft {
+        // Opening a directory for writing returns EISDIR
+        FileType::Dir => &[(OFlag::O_RDONLY, 0o4)],
+        // Opening a FIFO would block until the other end is opened
+        FileType::Fifo => &[
+            (OFlag::O_RDONLY | OFlag::O_NONBLOCK, 0o4),
+            (OFlag::O_WRONLY | OFlag::O_NONBLOCK, 0o2),
+            (OFlag::O_RDWR, 0o6),
+        ],
+        _ => &[
+            (OFlag::O_RDONLY, 0o4),
+            (OFlag::O_WRONLY, 0o2),
+            (OFlag::O_RDWR, 0o6),
+        ],
+    };
+    let matrix = PermMatrix::new(ctx);
+    let mut denied = vec![];
+
+    for class in PERM_CLASSES {
+        for perm in [0o6, 0o4, 0o2, 0o1, 0o0] {
+            let path = matrix.create(ctx, ft.clone(), class.mode(perm));
+
+            for &(flags, required) in access_modes {
+                if perm & required != required {
+                    denied.push((class, path.clone(), flags));
+                    continue;
+                }
+
+                matrix.run(ctx, class, || match open(&path, flags, Mode::empty()) {
+                    Ok(fd) => close(fd).unwrap(),
+                    // No process has the FIFO open for reading
+                    Err(Errno::ENXIO) if ft == FileType::Fifo => (),
+                    Err(e) => panic!("{class:?} {perm:#o} {flags:?}: {e}"),
+                });
+            }
+        }
+    }
+
+    assert_eacces_unchanged(ctx, &matrix, &denied);
+}
+
+crate::test_case! {
+    /// open returns EACCES when O_TRUNC is specified and write permission is denied
+    // open/07.t
+    eacces_trunc, serialized, root
+}
+fn eacces_trunc(ctx: &mut SerializedTestContext) {
+    let matrix = PermMatrix::new(ctx);
+    let mut denied = vec![];
+
+    for class in PERM_CLASSES {
+        for perm in [0o4, 0o1, 0o0] {
+            let path = matrix.create(ctx, FileType::Regular, class.mode(perm));
+            denied.push((class, path, OFlag::O_RDONLY | OFlag::O_TRUNC));
+        }
+    }
+
+    assert_eacces_unchanged(ctx, &matrix, &denied);
+}
+
+crate::test_case! {
+    /// open returns EACCES when O_CREAT is specified, the file does not exist,
+    /// and the directory in which it is to be created does not permit writing
+    // open/08.t
+    eacces_creat, serialized, root
+}
+fn eacces_creat(ctx: &mut SerializedTestContext) {
+    let matrix = PermMatrix::new(ctx);
+    let dirs: Vec<_> = PERM_CLASSES
+        .into_iter()
+        .flat_map(|class| [0o5, 0o1].map(|perm| (class, perm)))
+        .map(|(class, perm)| {
+            let dir = matrix.create(ctx, FileType::Dir, class.mode(perm));
+            (class, perm, dir)
+        })
+        .collect();
+
+    dirs.iter()
+        .fold(assert_times_unchanged(), |assertion, (_, _, dir)| {
+            assertion.path(dir, ATIME | CTIME | MTIME)
+        })
+        .execute(ctx, false, || {
+            for (class, perm, dir) in &dirs {
+                matrix.run(ctx, *class, || {
+                    assert_eq!(
+                        open(
+                            &dir.join("file"),
+                            OFlag::O_RDONLY | OFlag::O_CREAT,
+                            Mode::from_bits_truncate(0o644)
+                        ),
+                        Err(Errno::EACCES),
+                        "{class:?} {perm:#o}"
+                    );
+                })
+            }
+        });
+
+    for (_, _, dir) in &dirs {
+        assert!(!dir.join("file").exists());
+    }
+}
+
 fn open_flag_wrapper_ctx(flags: OFlag) -> impl Fn(&mut TestContext, &Path) -> nix::Result<RawFd> {
     move |_, path| open(path, flags, Mode::empty())
 }
@@ -237,6 +464,57 @@ erofs_new_file_test_case!(
     open_flag_wrapper_ctx(OFlag::O_RDONLY | OFlag::O_CREAT)
 );
 
+// open/09.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(open, |_: &mut TestContext, _: &Path, new: &Path| {
+    open(new, OFlag::O_RDONLY | OFlag::O_CREAT, Mode::from_bits_truncate(0o644))
+} => [Regular]);
+
+// open/10.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(
+    open,
+    open_flag_wrapper_ctx(OFlag::O_WRONLY),
+    open_flag_wrapper_ctx(OFlag::O_RDWR),
+    open_flag_wrapper_ctx(OFlag::O_RDONLY | OFlag::O_TRUNC)
+    => [Regular]
+);
+
+// open/11.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(
+    open,
+    open_flag_wrapper_ctx(OFlag::O_WRONLY),
+    open_flag_wrapper_ctx(OFlag::O_RDWR),
+    open_flag_wrapper_ctx(OFlag::O_RDONLY | OFlag::O_TRUNC),
+    open_flag_wrapper_ctx(OFlag::O_RDONLY | OFlag::O_APPEND | OFlag::O_TRUNC),
+    open_flag_wrapper_ctx(OFlag::O_WRONLY | OFlag::O_APPEND | OFlag::O_TRUNC),
+    open_flag_wrapper_ctx(OFlag::O_RDWR | OFlag::O_APPEND | OFlag::O_TRUNC)
+    => [Regular]
+);
+
+#[cfg(inode_flags)]
+crate::test_case! {
+    /// open succeeds with O_APPEND if the named file is append-only
+    // open/11.t
+    append_only_o_append, root; super::errors::eperm::supports_inode_flags
+}
+#[cfg(inode_flags)]
+fn append_only_o_append(ctx: &mut TestContext) {
+    use crate::flags::InodeFlag;
+
+    let path = ctx.create(FileType::Regular).unwrap();
+    InodeFlag::Append.set(&path).unwrap();
+
+    for flags in [
+        OFlag::O_WRONLY | OFlag::O_APPEND,
+        OFlag::O_RDWR | OFlag::O_APPEND,
+    ] {
+        let fd = open(&path, flags, Mode::empty()).unwrap();
+        close(fd).unwrap();
+    }
+}
+
 // open/12.t
 eloop_comp_test_case!(open(~path, OFlag::empty(), Mode::empty()));
 
@@ -0,0 +1,392 @@
+//! Copy-up semantics of an overlay mount whose layers reside on the tested file system.
+//!
+//! Each test creates lower, upper and work directories in its directory,
+//! populates the lower one and mounts an overlay of them, so that the entries are copied up
+//! to the upper layer when they are modified through the overlay.
+//! These tests only run when the `[overlay]` section is present in the configuration.
+
+use std::{
+    fs::{read, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use nix::{
+    errno::Errno,
+    mount::{mount, MsFlags},
+    sys::{
+        stat::{lstat, FileStat, Mode, SFlag, UtimensatFlags},
+        time::{TimeSpec, TimeValLike},
+    },
+};
+
+use super::scratch_mount::ScratchMount;
+use crate::{
+    config::Config,
+    context::{FileBuilder, FileType, TestContext},
+    utils::{chmod, chown, lgetxattr, link, rename, rmdir, setxattr, unlink, utimensat},
+};
+
+/// Guard which checks if the overlayfs copy-up tests have been enabled.
+pub(crate) fn overlay_enabled(config: &Config, _: &Path) -> anyhow::Result<()> {
+    if config.overlay.is_none() {
+        anyhow::bail!("Overlayfs copy-up tests are not enabled in the configuration file")
+    }
+
+    Ok(())
+}
+
+/// Return the value of the last occurrence of the overlay mount option `name`.
+fn overlay_option<'a>(options: &'a str, name: &str) -> Option<&'a str> {
+    options
+        .split(',')
+        .filter_map(|option| option.split_once('='))
+        .rfind(|&(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+/// Return whether the overlayfs feature `name` is enabled,
+/// either with the `name=on` mount option or by the default of the module.
+fn overlay_feature(options: Option<&str>, name: &str) -> bool {
+    match options.and_then(|options| overlay_option(options, name)) {
+        Some(value) => value == "on",
+        None => read_to_string(format!("/sys/module/overlay/parameters/{name}"))
+            .map(|value| value.trim() == "Y")
+            .unwrap_or(false),
+    }
+}
+
+/// Guard which checks that the overlay mounts index the copied up hard links,
+/// either with the `index=on` option or by default.
+pub(crate) fn overlay_index(config: &Config, base_path: &Path) -> anyhow::Result<()> {
+    overlay_enabled(config, base_path)?;
+
+    let options = config.overlay.as_ref().unwrap().options.as_deref();
+    if !overlay_feature(options, "index") {
+        anyhow::bail!("The index feature of overlayfs is not enabled")
+    }
+
+    Ok(())
+}
+
+/// Lower, upper and work directories of an overlay mount, and its mount point.
+struct Layers {
+    lower: PathBuf,
+    upper: PathBuf,
+    work: PathBuf,
+    merged: PathBuf,
+}
+
+impl Layers {
+    /// Create the directories in the test directory.
+    fn new(ctx: &TestContext) -> Self {
+        Layers {
+            lower: ctx.create(FileType::Dir).unwrap(),
+            upper: ctx.create(FileType::Dir).unwrap(),
+            work: ctx.create(FileType::Dir).unwrap(),
+            merged: ctx.create(FileType::Dir).unwrap(),
+        }
+    }
+
+    /// Return a builder for the entry `name` of the lower layer.
+    fn lower_file(&self, ctx: &TestContext, ft: FileType, name: &str) -> FileBuilder {
+        ctx.new_file(ft).name(self.lower.join(name))
+    }
+
+    /// Mount the overlay of the layers, which must not be modified afterwards.
+    fn mount(self, ctx: &TestContext) -> OverlayMount {
+        let options = ctx.overlay_config().unwrap().options.clone();
+        let mut data = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            self.lower.display(),
+            self.upper.display(),
+            self.work.display()
+        );
+        if let Some(options) = &options {
+            data = format!("{data},{options}");
+        }
+
+        mount(
+            Some("overlay"),
+            &self.merged,
+            Some("overlay"),
+            MsFlags::empty(),
+            Some(data.as_str()),
+        )
+        .unwrap();
+
+        OverlayMount {
+            mount: ScratchMount::new(self.merged.clone()),
+            layers: self,
+            options: options.unwrap_or_default(),
+        }
+    }
+}
+
+/// An overlay mount in the test directory.
+struct OverlayMount {
+    layers: Layers,
+    options: String,
+    mount: ScratchMount,
+}
+
+impl OverlayMount {
+    /// Return the path of the entry `name` through the overlay.
+    fn merged(&self, name: &str) -> PathBuf {
+        self.mount.path().join(name)
+    }
+
+    /// Return the path of the entry `name` in the upper layer.
+    fn upper(&self, name: &str) -> PathBuf {
+        self.layers.upper.join(name)
+    }
+
+    /// Return whether the entry at `path` has the overlay extended attribute `name`.
+    fn has_xattr(&self, path: &Path, name: &str) -> bool {
+        // Overlayfs uses the `user` namespace instead of the `trusted` one with `userxattr`
+        let namespace = if self.options.split(',').any(|o| o == "userxattr") {
+            "user"
+        } else {
+            "trusted"
+        };
+
+        let name = format!("{namespace}.overlay.{name}");
+
+        match lgetxattr(path, &name) {
+            Ok(_) => true,
+            Err(Errno::ENODATA) => false,
+            Err(e) => panic!("cannot get {name} of {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Assert that the entry at `path` is a whiteout, a character device with 0/0 device number.
+#[track_caller]
+fn assert_whiteout(path: &Path) {
+    let stat = lstat(path).unwrap();
+    assert_eq!(stat.st_mode & SFlag::S_IFMT.bits(), SFlag::S_IFCHR.bits());
+    assert_eq!(stat.st_rdev, 0);
+}
+
+/// Extended attribute which is copied up along with the entry.
+const XATTR: &str = "trusted.pjdfstest";
+
+/// Metadata which is preserved by copy-up.
+fn copied_metadata(stat: &FileStat) -> (u32, u32, u32, i64, i64) {
+    (
+        stat.st_mode,
+        stat.st_uid,
+        stat.st_gid,
+        stat.st_atime,
+        stat.st_mtime,
+    )
+}
+
+crate::test_case! {
+    /// Copy-up preserves the metadata and extended attributes of the entry
+    /// which are not changed by chmod, chown or utimensat
+    copy_up_metadata, root; overlay_enabled => [Regular, Dir, Fifo]
+}
+fn copy_up_metadata(ctx: &mut TestContext, ft: FileType) {
+    let (user, group) = ctx.get_new_entry();
+    let (other_user, other_group) = ctx.get_new_entry();
+    let past = TimeSpec::seconds(1_000_000_000);
+    let layers = Layers::new(ctx);
+
+    for name in ["chmod", "chown", "utimensat"] {
+        let path = layers
+            .lower_file(ctx, ft.clone(), name)
+            .mode(0o640)
+            .create()
+            .unwrap();
+        if ft == FileType::Regular {
+            write(&path, "data").unwrap();
+        }
+        chown(&path, Some(user.uid), Some(group.gid)).unwrap();
+        setxattr(&path, XATTR, name.as_bytes()).unwrap();
+        utimensat(&path, &past, &past, UtimensatFlags::NoFollowSymlink).unwrap();
+    }
+
+    let mount = layers.mount(ctx);
+    let time = TimeSpec::seconds(1_500_000_000);
+    let file_type = lstat(&mount.merged("chmod")).unwrap().st_mode & SFlag::S_IFMT.bits();
+    let (uid, gid) = (user.uid.as_raw(), group.gid.as_raw());
+
+    chmod(&mount.merged("chmod"), Mode::from_bits_truncate(0o604)).unwrap();
+    chown(
+        &mount.merged("chown"),
+        Some(other_user.uid),
+        Some(other_group.gid),
+    )
+    .unwrap();
+    utimensat(
+        &mount.merged("utimensat"),
+        &time,
+        &time,
+        UtimensatFlags::NoFollowSymlink,
+    )
+    .unwrap();
+
+    for (name, expected) in [
+        ("chmod", (file_type | 0o604, uid, gid, past, past)),
+        (
+            "chown",
+            (
+                file_type | 0o640,
+                other_user.uid.as_raw(),
+                other_group.gid.as_raw(),
+                past,
+                past,
+            ),
+        ),
+        ("utimensat", (file_type | 0o640, uid, gid, time, time)),
+    ] {
+        let (mode, uid, gid, atime, mtime) = expected;
+        let expected = (mode, uid, gid, atime.tv_sec(), mtime.tv_sec());
+
+        let merged = lstat(&mount.merged(name)).unwrap();
+        assert_eq!(
+            copied_metadata(&merged),
+            expected,
+            "{name} through the overlay"
+        );
+        let upper = lstat(&mount.upper(name)).unwrap();
+        assert_eq!(
+            copied_metadata(&upper),
+            expected,
+            "{name} in the upper layer"
+        );
+        assert_eq!(
+            lgetxattr(&mount.upper(name), XATTR).unwrap(),
+            name.as_bytes(),
+            "{name} in the upper layer"
+        );
+
+        if ft == FileType::Regular {
+            assert_eq!(read(mount.merged(name)).unwrap(), b"data", "{name}");
+        }
+    }
+}
+
+crate::test_case! {
+    /// Hard links of a lower file stay consistent once one of them is copied up
+    hard_links, root; overlay_index
+}
+fn hard_links(ctx: &mut TestContext) {
+    let layers = Layers::new(ctx);
+    let path = layers
+        .lower_file(ctx, FileType::Regular, "a")
+        .create()
+        .unwrap();
+    write(&path, "data").unwrap();
+    link(&path, &layers.lower.join("b")).unwrap();
+
+    let mount = layers.mount(ctx);
+    #[track_caller]
+    fn assert_links(mount: &OverlayMount, names: &[&str], mode: u32, content: &[u8]) {
+        let ino = lstat(&mount.merged(names[0])).unwrap().st_ino;
+        for name in names {
+            let path = mount.merged(name);
+            let stat = lstat(&path).unwrap();
+            assert_eq!(stat.st_ino, ino, "{name}");
+            assert_eq!(stat.st_nlink as usize, names.len(), "{name}");
+            assert_eq!(stat.st_mode & 0o7777, mode, "{name}");
+            assert_eq!(read(&path).unwrap(), content, "{name}");
+        }
+    }
+
+    // Copy-up of the first link
+    chmod(&mount.merged("a"), Mode::from_bits_truncate(0o600)).unwrap();
+    assert_links(&mount, &["a", "b"], 0o600, b"data");
+
+    write(mount.merged("b"), "new data").unwrap();
+    assert_links(&mount, &["a", "b"], 0o600, b"new data");
+
+    link(&mount.merged("b"), &mount.merged("c")).unwrap();
+    assert_links(&mount, &["a", "b", "c"], 0o600, b"new data");
+
+    unlink(&mount.merged("a")).unwrap();
+    assert_links(&mount, &["b", "c"], 0o600, b"new data");
+    assert_whiteout(&mount.upper("a"));
+}
+
+crate::test_case! {
+    /// rename of a lower directory records the redirection in the upper layer with redirect_dir,
+    /// and returns EXDEV otherwise
+    rename_lower_dir, root; overlay_enabled
+}
+fn rename_lower_dir(ctx: &mut TestContext) {
+    let layers = Layers::new(ctx);
+    layers
+        .lower_file(ctx, FileType::Dir, "dir")
+        .create()
+        .unwrap();
+    layers
+        .lower_file(ctx, FileType::Regular, "dir/file")
+        .create()
+        .unwrap();
+
+    let options = ctx.overlay_config().unwrap().options.as_deref();
+    // metacopy enables redirect_dir, unless it is set explicitly
+    let redirect_dir = match options.and_then(|options| overlay_option(options, "redirect_dir")) {
+        Some(value) => value == "on",
+        None => overlay_feature(None, "redirect_dir") || overlay_feature(options, "metacopy"),
+    };
+    let mount = layers.mount(ctx);
+    let res = rename(&mount.merged("dir"), &mount.merged("renamed"));
+
+    let (old_exists, new_exists) = if redirect_dir {
+        assert_eq!(res, Ok(()));
+        assert!(mount.has_xattr(&mount.upper("renamed"), "redirect"));
+        assert_whiteout(&mount.upper("dir"));
+        (false, true)
+    } else {
+        assert_eq!(res, Err(Errno::EXDEV));
+        (true, false)
+    };
+
+    assert_eq!(mount.merged("dir/file").exists(), old_exists);
+    assert_eq!(mount.merged("renamed/file").exists(), new_exists);
+}
+
+crate::test_case! {
+    /// unlink of a lower entry creates a whiteout in the upper layer
+    unlink_whiteout, root; overlay_enabled => [Regular, Fifo, Symlink(None)]
+}
+fn unlink_whiteout(ctx: &mut TestContext, ft: FileType) {
+    let layers = Layers::new(ctx);
+    layers.lower_file(ctx, ft, "entry").create().unwrap();
+
+    let mount = layers.mount(ctx);
+    unlink(&mount.merged("entry")).unwrap();
+
+    assert_eq!(lstat(&mount.merged("entry")).unwrap_err(), Errno::ENOENT);
+    assert_whiteout(&mount.upper("entry"));
+    assert!(mount.layers.lower.join("entry").symlink_metadata().is_ok());
+}
+
+crate::test_case! {
+    /// rmdir of a lower directory creates a whiteout in the upper layer,
+    /// and a directory created again at its place is opaque
+    rmdir_whiteout, root; overlay_enabled
+}
+fn rmdir_whiteout(ctx: &mut TestContext) {
+    let layers = Layers::new(ctx);
+    layers
+        .lower_file(ctx, FileType::Dir, "dir")
+        .create()
+        .unwrap();
+
+    let mount = layers.mount(ctx);
+    rmdir(&mount.merged("dir")).unwrap();
+
+    assert_eq!(lstat(&mount.merged("dir")).unwrap_err(), Errno::ENOENT);
+    assert_whiteout(&mount.upper("dir"));
+
+    ctx.new_file(FileType::Dir)
+        .name(mount.merged("dir"))
+        .create()
+        .unwrap();
+    let upper = mount.upper("dir");
+    assert!(upper.metadata().unwrap().is_dir());
+    assert!(mount.has_xattr(&upper, "opaque"));
+}
@@ -0,0 +1,276 @@
+//! Tests for directory enumeration with `readdir`, `telldir` and `seekdir`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CStr, CString, OsStr, OsString},
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::{Path, PathBuf},
+    ptr::NonNull,
+};
+
+use nix::{
+    errno::Errno,
+    libc::{self, c_long},
+    sys::stat::{lstat, SFlag},
+};
+
+use crate::context::{FileBuilder, FileType, TestContext};
+use crate::utils::unlink;
+
+/// Directory stream, which is a thin wrapper around `opendir`/`readdir`.
+/// Unlike [`nix::dir::Dir`], it supports `telldir` and `seekdir`.
+struct DirStream(NonNull<libc::DIR>);
+
+impl DirStream {
+    fn open(path: &Path) -> Self {
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let dir = unsafe { libc::opendir(path.as_ptr()) };
+
+        Self(NonNull::new(dir).unwrap_or_else(|| panic!("opendir failed: {}", Errno::last())))
+    }
+
+    fn tell(&self) -> c_long {
+        unsafe { libc::telldir(self.0.as_ptr()) }
+    }
+
+    fn seek(&mut self, loc: c_long) {
+        unsafe { libc::seekdir(self.0.as_ptr(), loc) }
+    }
+}
+
+impl Iterator for DirStream {
+    /// Name and `d_type` of the entry.
+    type Item = (OsString, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Errno::clear();
+        let entry = unsafe { libc::readdir(self.0.as_ptr()) };
+        if entry.is_null() {
+            assert_eq!(Errno::last_raw(), 0, "readdir failed: {}", Errno::last());
+            return None;
+        }
+
+        // SAFETY: readdir returned a valid entry, which lives until the next call
+        let entry = unsafe { &*entry };
+        let name = unsafe { CStr::from_ptr(entry.d_name.as_ptr()) };
+
+        Some((OsString::from_vec(name.to_bytes().to_vec()), entry.d_type))
+    }
+}
+
+impl Drop for DirStream {
+    fn drop(&mut self) {
+        unsafe { libc::closedir(self.0.as_ptr()) };
+    }
+}
+
+/// Count how many times each name appears.
+fn count_names<I: IntoIterator<Item = OsString>>(names: I) -> HashMap<OsString, usize> {
+    let mut counts = HashMap::new();
+    for name in names {
+        *counts.entry(name).or_default() += 1;
+    }
+
+    counts
+}
+
+/// List the names of the entries of `dir`, asserting that each name appears only once.
+fn list(dir: &Path) -> HashSet<OsString> {
+    let counts = count_names(DirStream::open(dir).map(|(name, _)| name));
+    assert_no_duplicates(&counts);
+
+    counts.into_keys().collect()
+}
+
+fn assert_no_duplicates(counts: &HashMap<OsString, usize>) {
+    let duplicates: Vec<_> = counts.iter().filter(|(_, &count)| count > 1).collect();
+    assert!(duplicates.is_empty(), "duplicate entries: {duplicates:?}");
+}
+
+/// Create `count` regular files in `dir`, and return their names.
+fn populate(dir: &Path, count: usize) -> Vec<OsString> {
+    populate_with_prefix(dir, count, "file")
+}
+
+fn populate_with_prefix(dir: &Path, count: usize, prefix: &str) -> Vec<OsString> {
+    (0..count)
+        .map(|i| {
+            let name = OsString::from(format!("{prefix}{i}"));
+            FileBuilder::new(FileType::Regular, &dir)
+                .name(&name)
+                .create()
+                .unwrap();
+            name
+        })
+        .collect()
+}
+
+/// Return the names of `.`, `..` and `names`.
+fn with_dots(names: &[OsString]) -> HashSet<OsString> {
+    names
+        .iter()
+        .cloned()
+        .chain([".".into(), "..".into()])
+        .collect()
+}
+
+/// Return the `d_type` matching a `st_mode`.
+fn d_type_of(mode: nix::libc::mode_t) -> u8 {
+    match SFlag::from_bits_truncate(mode & SFlag::S_IFMT.bits()) {
+        SFlag::S_IFREG => libc::DT_REG,
+        SFlag::S_IFDIR => libc::DT_DIR,
+        SFlag::S_IFIFO => libc::DT_FIFO,
+        SFlag::S_IFBLK => libc::DT_BLK,
+        SFlag::S_IFCHR => libc::DT_CHR,
+        SFlag::S_IFSOCK => libc::DT_SOCK,
+        SFlag::S_IFLNK => libc::DT_LNK,
+        _ => unreachable!("unknown file type {mode:o}"),
+    }
+}
+
+crate::test_case! {
+    /// readdir lists `.` and `..` exactly once in an empty directory
+    dot_entries
+}
+fn dot_entries(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+
+    assert_eq!(list(&dir), with_dots(&[]));
+}
+
+crate::test_case! {
+    /// readdir lists every entry of a directory exactly once
+    entries_appear_once
+}
+fn entries_appear_once(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let names = populate(&dir, 200);
+
+    assert_eq!(list(&dir), with_dots(&names));
+}
+
+crate::test_case! {
+    /// readdir reports a d_type which matches the file type, or DT_UNKNOWN
+    d_type_matches_lstat => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn d_type_matches_lstat(ctx: &mut TestContext, ft: FileType) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let path = FileBuilder::new(ft, &dir).create().unwrap();
+    let name = path.file_name().unwrap();
+
+    let d_type = DirStream::open(&dir)
+        .find_map(|(entry, d_type)| (entry == name).then_some(d_type))
+        .expect("entry not found");
+    let expected = d_type_of(lstat(&path).unwrap().st_mode);
+    assert!(
+        d_type == expected || d_type == libc::DT_UNKNOWN,
+        "d_type is {d_type}, expected {expected} or DT_UNKNOWN"
+    );
+
+    // `.` and `..` are directories
+    for (entry, d_type) in DirStream::open(&dir) {
+        if entry == OsStr::new(".") || entry == OsStr::new("..") {
+            assert!(d_type == libc::DT_DIR || d_type == libc::DT_UNKNOWN);
+        }
+    }
+}
+
+crate::test_case! {
+    /// readdir reports a d_type which matches the file type, or DT_UNKNOWN, for device files
+    d_type_matches_lstat_device, root => [Block, Char]
+}
+fn d_type_matches_lstat_device(ctx: &mut TestContext, ft: FileType) {
+    d_type_matches_lstat(ctx, ft)
+}
+
+crate::test_case! {
+    /// seekdir to a location returned by telldir resumes the listing at the same entry,
+    /// even after unrelated entries have been removed
+    seekdir_after_unlink
+}
+fn seekdir_after_unlink(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    populate(&dir, 200);
+
+    let mut stream = DirStream::open(&dir);
+    let seen: Vec<_> = stream.by_ref().take(100).map(|(name, _)| name).collect();
+    let loc = stream.tell();
+    let rest = count_names(stream.by_ref().map(|(name, _)| name));
+    assert_no_duplicates(&rest);
+
+    // Remove entries which were already listed
+    for name in seen
+        .iter()
+        .filter(|&name| name != OsStr::new(".") && name != OsStr::new(".."))
+        .take(50)
+    {
+        unlink(&dir.join(name)).unwrap();
+    }
+
+    stream.seek(loc);
+    let rest_after_seek = count_names(stream.map(|(name, _)| name));
+    assert_eq!(rest_after_seek, rest);
+}
+
+crate::test_case! {
+    /// Entries created or removed while listing a directory may or may not appear,
+    /// but the other entries appear exactly once
+    modify_during_iteration
+}
+fn modify_during_iteration(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let names = populate(&dir, 200);
+
+    let mut stream = DirStream::open(&dir);
+    let mut listed: Vec<_> = stream.by_ref().take(100).map(|(name, _)| name).collect();
+    let seen: HashSet<_> = listed.iter().cloned().collect();
+
+    let created: HashSet<_> = populate_with_prefix(&dir, 100, "new").into_iter().collect();
+    // Remove entries which were already listed as well as entries which were not
+    let removed: HashSet<_> = names
+        .iter()
+        .filter(|&name| !seen.contains(name))
+        .take(25)
+        .chain(names.iter().filter(|&name| seen.contains(name)).take(25))
+        .cloned()
+        .collect();
+    for name in &removed {
+        unlink(&dir.join(name)).unwrap();
+    }
+
+    listed.extend(stream.map(|(name, _)| name));
+    let counts = count_names(listed);
+    assert_no_duplicates(&counts);
+
+    let untouched: HashSet<_> = with_dots(&names).difference(&removed).cloned().collect();
+    for name in &untouched {
+        assert!(counts.contains_key(name), "{name:?} is missing");
+    }
+    for name in counts.keys() {
+        assert!(
+            untouched.contains(name) || created.contains(name) || removed.contains(name),
+            "unexpected entry {name:?}"
+        );
+    }
+}
+
+crate::test_case! {
+    /// readdir lists every entry of a directory with tens of thousands of entries exactly once
+    many_entries
+}
+fn many_entries(ctx: &mut TestContext) {
+    const COUNT: usize = 20000;
+    let dir = ctx.create(FileType::Dir).unwrap();
+    let mut names = populate(&dir, COUNT);
+
+    assert_eq!(list(&dir), with_dots(&names));
+
+    // Remove every other entry to leave gaps in the directory
+    let removed: Vec<PathBuf> = names.iter().step_by(2).map(|n| dir.join(n)).collect();
+    for path in &removed {
+        unlink(path).unwrap();
+    }
+    names = names.into_iter().skip(1).step_by(2).collect();
+
+    assert_eq!(list(&dir), with_dots(&names));
+}
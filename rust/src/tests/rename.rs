@@ -1,4 +1,4 @@
-use std::fs::symlink_metadata;
+use std::{fs::symlink_metadata, path::Path};
 
 use nix::{
     errno::Errno,
@@ -25,6 +25,12 @@ use super::{
     },
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{
+    eperm_append_test_case, eperm_immutable_test_case, eperm_parent_append_test_case,
+    eperm_parent_immutable_test_case,
+};
+
 crate::test_case! {
     /// rename preserve file metadata
     // rename/00.t
@@ -321,6 +327,36 @@ erofs_named_test_case!(rename, |ctx: &mut TestContext, file| {
     rename(file, &path)
 });
 
+// rename/06.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(rename, |ctx: &mut TestContext, file: &Path| {
+    rename(file, &ctx.gen_path())
+} => [Regular, Dir]);
+
+// rename/06.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(rename, |ctx: &mut TestContext, file: &Path| {
+    rename(file, &ctx.gen_path())
+} => [Regular, Dir]);
+
+// rename/07.t, rename/08.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(
+    rename,
+    |ctx: &mut TestContext, existing: &Path, _: &Path| rename(existing, &ctx.gen_path()),
+    |ctx: &mut TestContext, _: &Path, new: &Path| {
+        let from = ctx.create(FileType::Regular).unwrap();
+        rename(from.as_path(), new)
+    }
+    => [Regular, Dir]
+);
+
+// rename/07.t
+#[cfg(inode_flags)]
+eperm_parent_append_test_case!(rename, |ctx: &mut TestContext, existing: &Path, _: &Path| {
+    rename(existing, &ctx.gen_path())
+} => [Regular, Dir]);
+
 // rename/17.t
 efault_either_test_case!(rename, nix::libc::rename);
 
@@ -18,6 +18,12 @@ use super::{
     errors::{enametoolong::enametoolong_path_test_case, enotdir::enotdir_comp_test_case},
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{
+    eperm_append_test_case, eperm_immutable_test_case, eperm_parent_append_test_case,
+    eperm_parent_immutable_test_case,
+};
+
 crate::test_case! {
     /// rmdir remove directory
     // rmdir/00.t
@@ -198,5 +204,25 @@ fn ebusy(ctx: &mut TestContext) {
 // rmdir/14.t
 erofs_named_test_case!(rmdir);
 
+// rmdir/09.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(rmdir => [Dir]);
+
+// rmdir/09.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(rmdir => [Dir]);
+
+// rmdir/10.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(rmdir, |_: &mut TestContext, existing: &Path, _: &Path| {
+    rmdir(existing)
+} => [Dir]);
+
+// rmdir/10.t
+#[cfg(inode_flags)]
+eperm_parent_append_test_case!(rmdir, |_: &mut TestContext, existing: &Path, _: &Path| {
+    rmdir(existing)
+} => [Dir]);
+
 // rmdir/15.t
 efault_path_test_case!(rmdir, nix::libc::rmdir);
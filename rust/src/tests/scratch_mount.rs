@@ -0,0 +1,36 @@
+//! Mounts created by the tests in their directory.
+
+use std::path::{Path, PathBuf};
+
+use nix::mount::{umount2, MntFlags};
+
+use crate::context::TestContext;
+
+/// A file system mounted by a test, which is unmounted when dropped.
+pub(super) struct ScratchMount {
+    path: PathBuf,
+}
+
+impl ScratchMount {
+    /// Take over the mount at `path`, which must have just been mounted.
+    pub(super) fn new(path: PathBuf) -> Self {
+        ScratchMount { path }
+    }
+
+    /// Return the mount point.
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return a new path in the mount, since a configured device keeps the entries of previous mounts.
+    pub(super) fn gen_path(&self, ctx: &TestContext) -> PathBuf {
+        self.path.join(ctx.gen_path().file_name().unwrap())
+    }
+}
+
+impl Drop for ScratchMount {
+    fn drop(&mut self) {
+        // The test might have failed while a file was still in use
+        let _ = umount2(&self.path, MntFlags::MNT_DETACH);
+    }
+}
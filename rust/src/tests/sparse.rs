@@ -0,0 +1,336 @@
+//! Tests for sparse files and the `SEEK_DATA`/`SEEK_HOLE` whences of `lseek`.
+//!
+//! File systems are allowed to be conservative: a hole might be reported as data,
+//! and the only hole which is guaranteed to be reported is the virtual one at the end of the file.
+
+use std::os::fd::{AsRawFd, OwnedFd};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::off_t,
+    sys::{stat::fstat, uio::pwrite},
+    unistd::{fsync, lseek, Whence},
+};
+
+use crate::utils::ftruncate;
+use crate::{context::TestContext, test::FileSystemFeature};
+
+use super::data::{pattern, range_unit, read_all, st_blocks, to_blocks};
+
+/// Layout of a sparse file.
+struct Layout {
+    /// Ranges (offset, length) which contain data.
+    data: Vec<(off_t, off_t)>,
+    /// Size of the file.
+    size: off_t,
+}
+
+impl Layout {
+    /// Write the layout to `fd`, which should be an empty file,
+    /// and return the expected content of the file.
+    fn write(&self, fd: &OwnedFd) -> Vec<u8> {
+        let mut expected = vec![0; self.size as usize];
+        for &(offset, len) in &self.data {
+            let data = pattern(len, None);
+            assert_eq!(pwrite(fd, &data, offset).unwrap(), data.len());
+            expected[offset as usize..(offset + len) as usize].copy_from_slice(&data);
+        }
+        ftruncate(fd, self.size).unwrap();
+        // Some file systems only report holes once the data reached the disk
+        fsync(fd.as_raw_fd()).unwrap();
+
+        expected
+    }
+}
+
+/// Walk the file with `SEEK_DATA` and `SEEK_HOLE`,
+/// and return the ranges (start, end) reported as data.
+fn reported_data(fd: &OwnedFd) -> Vec<(off_t, off_t)> {
+    let size = fstat(fd.as_raw_fd()).unwrap().st_size;
+    let mut ranges = vec![];
+    let mut offset = 0;
+
+    while offset < size {
+        let start = match lseek(fd.as_raw_fd(), offset, Whence::SeekData) {
+            Ok(start) => start,
+            // Only holes remain until EOF
+            Err(Errno::ENXIO) => break,
+            Err(e) => panic!("lseek(SEEK_DATA, {offset}) failed: {e}"),
+        };
+        assert!(
+            (offset..size).contains(&start),
+            "SEEK_DATA from {offset} returned {start}, outside of [{offset}, {size})"
+        );
+
+        let end = lseek(fd.as_raw_fd(), start, Whence::SeekHole).unwrap();
+        assert!(
+            end > start && end <= size,
+            "SEEK_HOLE from {start} returned {end}, outside of ({start}, {size}]"
+        );
+
+        ranges.push((start, end));
+        offset = end;
+    }
+
+    ranges
+}
+
+/// Assert that what `SEEK_DATA` and `SEEK_HOLE` report is consistent with `layout`,
+/// i.e. that every written range is reported as data, and that holes read back as zeros.
+fn assert_consistent(fd: &OwnedFd, layout: &Layout, expected: &[u8]) {
+    assert_eq!(read_all(fd), expected, "holes do not read back as zeros");
+
+    let reported = reported_data(fd);
+    for &(offset, len) in &layout.data {
+        for pos in [offset, offset + len - 1] {
+            assert!(
+                reported
+                    .iter()
+                    .any(|&(start, end)| (start..end).contains(&pos)),
+                "data at offset {pos} is reported as a hole (reported data: {reported:?})"
+            );
+        }
+    }
+    for window in reported.windows(2) {
+        let [(_, hole_start), (data_start, _)] = window else {
+            unreachable!()
+        };
+        assert!(
+            expected[*hole_start as usize..*data_start as usize]
+                .iter()
+                .all(|&b| b == 0),
+            "range [{hole_start}, {data_start}) is reported as a hole but contains data"
+        );
+    }
+}
+
+crate::test_case! {
+    /// lseek reports the virtual hole at the end of a file without holes
+    virtual_hole_at_eof
+}
+fn virtual_hole_at_eof(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(0, 2 * unit)],
+        size: 2 * unit,
+    };
+    let expected = layout.write(&fd);
+
+    assert_eq!(lseek(fd.as_raw_fd(), 0, Whence::SeekData), Ok(0));
+    assert_eq!(lseek(fd.as_raw_fd(), unit, Whence::SeekData), Ok(unit));
+    assert_eq!(lseek(fd.as_raw_fd(), 0, Whence::SeekHole), Ok(2 * unit));
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 2 * unit - 1, Whence::SeekHole),
+        Ok(2 * unit)
+    );
+    assert_consistent(&fd, &layout, &expected);
+}
+
+crate::test_case! {
+    /// lseek returns ENXIO for SEEK_DATA and SEEK_HOLE at or past EOF
+    enxio_past_eof
+}
+fn enxio_past_eof(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    // Empty file
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 0, Whence::SeekData),
+        Err(Errno::ENXIO)
+    );
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 0, Whence::SeekHole),
+        Err(Errno::ENXIO)
+    );
+
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(0, unit)],
+        size: 2 * unit,
+    };
+    layout.write(&fd);
+
+    for offset in [2 * unit, 2 * unit + 1, 4 * unit] {
+        assert_eq!(
+            lseek(fd.as_raw_fd(), offset, Whence::SeekData),
+            Err(Errno::ENXIO)
+        );
+        assert_eq!(
+            lseek(fd.as_raw_fd(), offset, Whence::SeekHole),
+            Err(Errno::ENXIO)
+        );
+    }
+
+    // Only a trailing hole remains, there is no more data
+    assert!(matches!(
+        lseek(fd.as_raw_fd(), unit, Whence::SeekData),
+        // The file system might have reported the hole as data
+        Ok(_) | Err(Errno::ENXIO)
+    ));
+}
+
+crate::test_case! {
+    /// SEEK_DATA and SEEK_HOLE are consistent with the layout of sparse files,
+    /// and holes read back as zeros
+    layouts_consistent
+}
+fn layouts_consistent(ctx: &mut TestContext) {
+    let unit = {
+        let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+        range_unit(&fd)
+    };
+
+    let layouts = [
+        // Hole in the middle
+        Layout {
+            data: vec![(0, unit), (3 * unit, unit)],
+            size: 4 * unit,
+        },
+        // Leading hole
+        Layout {
+            data: vec![(2 * unit, unit)],
+            size: 3 * unit,
+        },
+        // Trailing hole
+        Layout {
+            data: vec![(0, unit)],
+            size: 4 * unit,
+        },
+        // Only a hole
+        Layout {
+            data: vec![],
+            size: 4 * unit,
+        },
+        // Data which is not aligned with the block size
+        Layout {
+            data: vec![(unit / 2, 100), (3 * unit + 1, unit / 2)],
+            size: 5 * unit,
+        },
+    ];
+
+    for layout in &layouts {
+        let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+        let expected = layout.write(&fd);
+        assert_consistent(&fd, layout, &expected);
+    }
+}
+
+crate::test_case! {
+    /// Extending a file after shrinking it exposes zeros rather than the previous data
+    extend_after_shrink_reads_zeros
+}
+fn extend_after_shrink_reads_zeros(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(0, 2 * unit)],
+        size: 2 * unit,
+    };
+    let mut expected = layout.write(&fd);
+
+    // Shrink in the middle of a block, then extend again
+    ftruncate(&fd, unit + 1).unwrap();
+    ftruncate(&fd, 3 * unit).unwrap();
+    expected.truncate(unit as usize + 1);
+    expected.resize(3 * unit as usize, 0);
+
+    let layout = Layout {
+        data: vec![(0, unit + 1)],
+        size: 3 * unit,
+    };
+    assert_consistent(&fd, &layout, &expected);
+}
+
+crate::test_case! {
+    /// Extending a file does not allocate blocks for the unwritten range
+    unwritten_range_not_allocated, FileSystemFeature::SparseFiles
+}
+fn unwritten_range_not_allocated(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(0, unit)],
+        size: unit,
+    };
+    layout.write(&fd);
+    let blocks = st_blocks(&fd);
+
+    ftruncate(&fd, 64 * unit).unwrap();
+    fsync(fd.as_raw_fd()).unwrap();
+    // Allow some metadata blocks, but nothing close to the size of the extension
+    assert!(st_blocks(&fd) < blocks + to_blocks(unit));
+
+    // Writing past the hole only allocates blocks for the written data
+    let data = pattern(unit, None);
+    assert_eq!(pwrite(&fd, &data, 128 * unit).unwrap(), data.len());
+    fsync(fd.as_raw_fd()).unwrap();
+    assert!(st_blocks(&fd) < blocks + to_blocks(3 * unit));
+}
+
+crate::test_case! {
+    /// SEEK_DATA and SEEK_HOLE report holes of unwritten block-aligned ranges
+    holes_reported, FileSystemFeature::SparseFiles
+}
+fn holes_reported(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(unit, unit), (3 * unit, unit)],
+        size: 5 * unit,
+    };
+    let expected = layout.write(&fd);
+
+    assert_eq!(lseek(fd.as_raw_fd(), 0, Whence::SeekHole), Ok(0));
+    assert_eq!(lseek(fd.as_raw_fd(), 0, Whence::SeekData), Ok(unit));
+    assert_eq!(lseek(fd.as_raw_fd(), unit, Whence::SeekHole), Ok(2 * unit));
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 2 * unit, Whence::SeekData),
+        Ok(3 * unit)
+    );
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 3 * unit, Whence::SeekHole),
+        Ok(4 * unit)
+    );
+    assert_eq!(
+        lseek(fd.as_raw_fd(), 4 * unit, Whence::SeekData),
+        Err(Errno::ENXIO)
+    );
+    assert_eq!(reported_data(&fd), [(unit, 2 * unit), (3 * unit, 4 * unit)]);
+    assert_consistent(&fd, &layout, &expected);
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// SEEK_DATA and SEEK_HOLE are consistent with holes punched with fallocate
+    punched_hole_consistent, FileSystemFeature::FallocatePunchHole
+}
+#[cfg(target_os = "linux")]
+fn punched_hole_consistent(ctx: &mut TestContext) {
+    use nix::fcntl::{fallocate, FallocateFlags};
+
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let unit = range_unit(&fd);
+    let layout = Layout {
+        data: vec![(0, 4 * unit)],
+        size: 4 * unit,
+    };
+    let mut expected = layout.write(&fd);
+
+    fallocate(
+        fd.as_raw_fd(),
+        FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+        unit,
+        2 * unit,
+    )
+    .unwrap();
+    fsync(fd.as_raw_fd()).unwrap();
+    expected[unit as usize..3 * unit as usize].fill(0);
+
+    let layout = Layout {
+        data: vec![(0, unit), (3 * unit, unit)],
+        size: 4 * unit,
+    };
+    assert_consistent(&fd, &layout, &expected);
+}
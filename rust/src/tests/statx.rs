@@ -0,0 +1,252 @@
+//! Tests for the Linux-specific `statx` syscall.
+
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nix::{
+    libc::{
+        statx_timestamp, AT_STATX_DONT_SYNC, AT_STATX_FORCE_SYNC, AT_STATX_SYNC_AS_STAT,
+        AT_SYMLINK_NOFOLLOW, STATX_ATTR_APPEND, STATX_ATTR_IMMUTABLE, STATX_ATTR_NODUMP,
+        STATX_BASIC_STATS, STATX_BTIME, STATX_CTIME, STATX_INO, STATX_MTIME, STATX_TYPE, S_IFMT,
+    },
+    sys::{
+        stat::{lstat, Mode, UtimensatFlags},
+        time::{TimeSpec, TimeValLike},
+    },
+};
+
+use crate::utils::utimensat;
+use crate::{
+    context::{FileType, TestContext},
+    test::FileSystemFeature,
+    tests::{AsTimeInvariant, MetadataExt},
+    utils::{
+        chmod, get_inode_flags,
+        iflags::{FS_APPEND_FL, FS_IMMUTABLE_FL, FS_NODUMP_FL},
+        rename, set_inode_flags, statx,
+    },
+};
+
+/// Convert a `statx` timestamp to a `TimeSpec`.
+fn statx_ts(ts: &statx_timestamp) -> TimeSpec {
+    TimeSpec::new(ts.tv_sec, ts.tv_nsec.into())
+}
+
+/// Return the creation time of the file, without following symlinks.
+fn btime_ts(path: &Path) -> TimeSpec {
+    let stx = statx(path, AT_SYMLINK_NOFOLLOW, STATX_BTIME).unwrap();
+    assert_ne!(
+        stx.stx_mask & STATX_BTIME,
+        0,
+        "statx did not fill stx_btime"
+    );
+
+    statx_ts(&stx.stx_btime)
+}
+
+/// Assert that a certain operation does not change the creation time of a file.
+fn assert_btime_unchanged<F>(ctx: &TestContext, path: &Path, f: F)
+where
+    F: FnOnce(),
+{
+    let before = btime_ts(path);
+    ctx.nap();
+    f();
+    assert_eq!(btime_ts(path), before, "stx_btime changed");
+}
+
+crate::test_case! {
+    /// statx reports the basic stats as filled, and they match the values returned by lstat
+    basic_stats_match_stat => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn basic_stats_match_stat(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+
+    let stx = statx(&path, AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS | STATX_BTIME).unwrap();
+    let st = lstat(&path).unwrap();
+
+    assert_eq!(stx.stx_mask & STATX_BASIC_STATS, STATX_BASIC_STATS);
+    assert_eq!(stx.as_time_invariant(), st.as_time_invariant());
+
+    let meta = path.symlink_metadata().unwrap();
+    assert_eq!(statx_ts(&stx.stx_atime), meta.atime_ts());
+    assert_eq!(statx_ts(&stx.stx_ctime), meta.ctime_ts());
+    assert_eq!(statx_ts(&stx.stx_mtime), meta.mtime_ts());
+}
+
+crate::test_case! {
+    /// statx reports the basic stats of device files as filled, and they match the values returned by lstat
+    basic_stats_match_stat_device, root => [Block, Char]
+}
+fn basic_stats_match_stat_device(ctx: &mut TestContext, ft: FileType) {
+    basic_stats_match_stat(ctx, ft)
+}
+
+crate::test_case! {
+    /// statx only reports stx_btime as filled when it holds a plausible creation time
+    btime_mask_honest => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn btime_mask_honest(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+
+    let stx = statx(
+        &path,
+        AT_SYMLINK_NOFOLLOW,
+        STATX_BTIME | STATX_CTIME | STATX_MTIME,
+    )
+    .unwrap();
+
+    if stx.stx_mask & STATX_BTIME != 0 {
+        let btime = statx_ts(&stx.stx_btime);
+        assert_ne!(
+            btime,
+            TimeSpec::seconds(0),
+            "stx_btime is reported but empty"
+        );
+        assert!(btime <= statx_ts(&stx.stx_ctime));
+        assert!(btime <= statx_ts(&stx.stx_mtime));
+    }
+}
+
+crate::test_case! {
+    /// statx sets stx_btime to the time of creation
+    btime_set_at_creation, FileSystemFeature::StatxBtime => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn btime_set_at_creation(ctx: &mut TestContext, ft: FileType) {
+    // Timestamps might come from a coarser clock than the system one
+    let margin = TimeSpec::seconds(1);
+    let now = || TimeSpec::from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap());
+
+    let before = now();
+    let path = ctx.create(ft).unwrap();
+    let after = now();
+
+    let btime = btime_ts(&path);
+    assert!(
+        btime >= before - margin,
+        "stx_btime is earlier than the creation"
+    );
+    assert!(
+        btime <= after + margin,
+        "stx_btime is later than the creation"
+    );
+}
+
+crate::test_case! {
+    /// chmod does not change stx_btime
+    btime_unchanged_chmod, FileSystemFeature::StatxBtime => [Regular, Dir, Fifo, Socket]
+}
+fn btime_unchanged_chmod(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+
+    assert_btime_unchanged(ctx, &path, || {
+        chmod(&path, Mode::from_bits_truncate(0o600)).unwrap();
+    });
+}
+
+crate::test_case! {
+    /// utimensat does not change stx_btime
+    btime_unchanged_utimensat, FileSystemFeature::StatxBtime, FileSystemFeature::Utimensat
+        => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn btime_unchanged_utimensat(ctx: &mut TestContext, ft: FileType) {
+    let date1 = TimeSpec::seconds(1900000000); // Sun Mar 17 11:46:40 MDT 2030
+    let date2 = TimeSpec::seconds(100000000); // Sat Mar  3 02:46:40 MST 1973
+    let path = ctx.create(ft).unwrap();
+
+    assert_btime_unchanged(ctx, &path, || {
+        utimensat(&path, &date1, &date1, UtimensatFlags::NoFollowSymlink).unwrap();
+        utimensat(&path, &date2, &date2, UtimensatFlags::NoFollowSymlink).unwrap();
+    });
+}
+
+crate::test_case! {
+    /// rename does not change stx_btime
+    btime_unchanged_rename, FileSystemFeature::StatxBtime
+        => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn btime_unchanged_rename(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+    let new_path = ctx.gen_path();
+
+    let btime = btime_ts(&path);
+    ctx.nap();
+    rename(&path, &new_path).unwrap();
+    assert_eq!(btime_ts(&new_path), btime);
+}
+
+crate::test_case! {
+    /// statx does not report attributes which are not in stx_attributes_mask
+    attributes_in_mask => [Regular, Dir]
+}
+fn attributes_in_mask(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+
+    let stx = statx(&path, AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS).unwrap();
+    assert_eq!(stx.stx_attributes & !stx.stx_attributes_mask, 0);
+}
+
+crate::test_case! {
+    /// stx_attributes reflects the immutable, append-only and nodump inode flags
+    /// when they are advertised in stx_attributes_mask
+    attributes_match_inode_flags, root => [Regular, Dir]
+}
+fn attributes_match_inode_flags(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+
+    let stx = statx(&path, AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS).unwrap();
+    let supported = stx.stx_attributes_mask;
+
+    for (attr, flag) in [
+        (STATX_ATTR_IMMUTABLE, FS_IMMUTABLE_FL),
+        (STATX_ATTR_APPEND, FS_APPEND_FL),
+        (STATX_ATTR_NODUMP, FS_NODUMP_FL),
+    ] {
+        let attr = attr as u64;
+        if supported & attr == 0 {
+            continue;
+        }
+
+        let original_flags = get_inode_flags(&path).unwrap();
+        set_inode_flags(&path, original_flags | flag).unwrap();
+        let stx_set = statx(&path, AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS);
+        set_inode_flags(&path, original_flags & !flag).unwrap();
+        let stx_cleared = statx(&path, AT_SYMLINK_NOFOLLOW, STATX_BASIC_STATS).unwrap();
+
+        let stx_set = stx_set.unwrap();
+        assert_ne!(stx_set.stx_attributes & attr, 0);
+        assert_eq!(stx_set.stx_attributes_mask & attr, attr);
+        assert_eq!(stx_cleared.stx_attributes & attr, 0);
+    }
+}
+
+crate::test_case! {
+    /// statx accepts AT_STATX_FORCE_SYNC and AT_STATX_DONT_SYNC
+    sync_flags => [Regular, Dir, Fifo, Socket, Symlink(None)]
+}
+fn sync_flags(ctx: &mut TestContext, ft: FileType) {
+    let path = ctx.create(ft).unwrap();
+    let mask = STATX_BASIC_STATS | STATX_BTIME;
+
+    let stx_as_stat = statx(&path, AT_SYMLINK_NOFOLLOW | AT_STATX_SYNC_AS_STAT, mask).unwrap();
+
+    let stx_force_sync = statx(&path, AT_SYMLINK_NOFOLLOW | AT_STATX_FORCE_SYNC, mask).unwrap();
+    assert_eq!(
+        stx_force_sync.as_time_invariant(),
+        stx_as_stat.as_time_invariant()
+    );
+
+    let stx_dont_sync = statx(&path, AT_SYMLINK_NOFOLLOW | AT_STATX_DONT_SYNC, mask).unwrap();
+    // Cached attributes might be stale, but the identity of the file cannot change
+    if stx_dont_sync.stx_mask & STATX_INO != 0 {
+        assert_eq!(stx_dont_sync.stx_ino, stx_as_stat.stx_ino);
+    }
+    if stx_dont_sync.stx_mask & STATX_TYPE != 0 {
+        assert_eq!(
+            u32::from(stx_dont_sync.stx_mode) & S_IFMT,
+            u32::from(stx_as_stat.stx_mode) & S_IFMT
+        );
+    }
+}
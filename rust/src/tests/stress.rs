@@ -0,0 +1,422 @@
+//! Concurrent stress tests for namespace operations.
+//!
+//! Several threads concurrently create, link, rename and remove entries from a shared set of names,
+//! and the invariants of the tree are checked while the threads are paused and at the end.
+//! These tests only run when the `[stress]` section is present in the configuration.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::read_dir,
+    os::fd::AsRawFd,
+    os::unix::{ffi::OsStrExt, fs::DirEntryExt},
+    path::{Path, PathBuf},
+    sync::RwLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    sys::{
+        stat::{lstat, Mode, SFlag},
+        statvfs::statvfs,
+    },
+    unistd::{mkdir, read, sync, write},
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    config::Config,
+    context::TestContext,
+    utils::{link, open, rename, rmdir, unlink},
+};
+
+/// Number of times the invariants are checked while the workers are running.
+const CHECKPOINTS: u32 = 5;
+
+/// Guard which checks if the stress tests have been enabled.
+pub(crate) fn stress_enabled(config: &Config, _: &Path) -> anyhow::Result<()> {
+    if config.stress.is_none() {
+        anyhow::bail!("Stress tests are not enabled in the configuration file")
+    }
+
+    Ok(())
+}
+
+/// Settings of a stress test run.
+struct Settings {
+    seed: u64,
+    duration: Duration,
+    threads: usize,
+}
+
+impl Settings {
+    fn new(ctx: &TestContext) -> Self {
+        let config = ctx.stress_config().unwrap();
+
+        Settings {
+            seed: config.seed.unwrap_or_else(rand::random),
+            duration: Duration::from_secs_f64(config.duration),
+            threads: config.threads.max(2),
+        }
+    }
+
+    /// Return the random number generator of the `i`-th worker.
+    fn rng(&self, i: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed.wrapping_add(i as u64))
+    }
+}
+
+/// Errors which are expected when operating on names which are concurrently modified.
+const EXPECTED_ERRORS: [Errno; 8] = [
+    Errno::ENOENT,
+    Errno::EEXIST,
+    Errno::ENOTEMPTY,
+    Errno::EISDIR,
+    Errno::ENOTDIR,
+    // link on a directory
+    Errno::EPERM,
+    // rename of a directory to a subdirectory of itself
+    Errno::EINVAL,
+    Errno::EMLINK,
+];
+
+/// Return the paths shared by the workers, relative to the base path.
+fn shared_paths() -> Vec<PathBuf> {
+    let top: Vec<PathBuf> = (0..8).map(|i| PathBuf::from(format!("a{i}"))).collect();
+    let nested = top
+        .iter()
+        .flat_map(|dir| (0..4).map(move |i| dir.join(format!("b{i}"))));
+
+    top.iter().cloned().chain(nested).collect()
+}
+
+/// Whether the name could have been created by a worker.
+fn is_shared_name(name: &[u8]) -> bool {
+    matches!(name, [b'a' | b'b', b'0'..=b'9'])
+}
+
+/// Perform a random namespace operation on the shared paths.
+fn random_operation(rng: &mut StdRng, base: &Path, paths: &[PathBuf]) -> Result<(), String> {
+    let from = base.join(paths.choose(rng).unwrap());
+    let to = base.join(paths.choose(rng).unwrap());
+
+    let (name, res) = match rng.gen_range(0..6) {
+        0 => (
+            "create",
+            open(&from, OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU).map(drop),
+        ),
+        1 => ("link", link(&from, &to)),
+        2 => ("rename", rename(&from, &to)),
+        3 => ("unlink", unlink(&from)),
+        4 => ("mkdir", mkdir(&from, Mode::S_IRWXU)),
+        _ => ("rmdir", rmdir(&from)),
+    };
+
+    match res {
+        Err(e) if !EXPECTED_ERRORS.contains(&e) => Err(format!(
+            "{name} failed with an unexpected error for {} (and {}): {e}",
+            from.display(),
+            to.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// State collected while walking the tree.
+#[derive(Default)]
+struct Walk {
+    /// Paths found for each inode.
+    paths: HashMap<u64, Vec<PathBuf>>,
+    /// Link count reported by `lstat` for each inode.
+    nlink: HashMap<u64, u64>,
+}
+
+impl Walk {
+    /// Walk `dir`, whose parent is the inode `parent`, and record the entries found.
+    /// Return the number of subdirectories of `dir`.
+    fn walk(&mut self, dir: &Path, parent: u64, errors: &mut Vec<String>) -> u64 {
+        let dir_ino = lstat(dir).unwrap().st_ino;
+        let dotdot = lstat(&dir.join("..")).unwrap().st_ino;
+        if dotdot != parent {
+            errors.push(format!(
+                "{}/.. has inode {dotdot}, but the parent has inode {parent}",
+                dir.display()
+            ));
+        }
+
+        let mut names = HashSet::new();
+        let mut subdirs = 0;
+        for entry in read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let path = entry.path();
+            if !names.insert(entry.file_name()) {
+                errors.push(format!("{} is listed twice", path.display()));
+            }
+            if !is_shared_name(entry.file_name().as_bytes()) {
+                errors.push(format!("unexpected entry {}", path.display()));
+            }
+
+            let st = lstat(&path).unwrap();
+            if entry.ino() != st.st_ino as u64 {
+                errors.push(format!(
+                    "{} is listed with inode {}, but lstat reports inode {}",
+                    path.display(),
+                    entry.ino(),
+                    st.st_ino
+                ));
+            }
+            self.paths
+                .entry(st.st_ino as u64)
+                .or_default()
+                .push(path.clone());
+            self.nlink.insert(st.st_ino as u64, st.st_nlink as u64);
+
+            if st.st_mode & SFlag::S_IFMT.bits() == SFlag::S_IFDIR.bits() {
+                subdirs += 1;
+                let children = self.walk(&path, dir_ino, errors);
+                // Some file systems do not track the link count of directories and report 1
+                if st.st_nlink as u64 != 2 + children && st.st_nlink != 1 {
+                    errors.push(format!(
+                        "{} has a link count of {}, but has {children} subdirectories",
+                        path.display(),
+                        st.st_nlink
+                    ));
+                }
+            }
+        }
+
+        subdirs
+    }
+}
+
+/// Return the number of free inodes in the file system of `path`, if it reports one.
+fn free_inodes(path: &Path) -> Option<u64> {
+    // Some file systems only release the inodes of removed files in the background
+    sync();
+    let st = statvfs(path).unwrap();
+    (st.files() != 0).then_some(st.files_free() as u64)
+}
+
+/// Inode usage of the file system, to check that every allocated inode is reachable from the tree.
+#[derive(Clone, Copy)]
+struct InodeUsage {
+    /// Number of free inodes before the tree was populated.
+    free_before: u64,
+    /// Whether each additional hard link is charged as an inode, as on tmpfs.
+    links_charged: bool,
+}
+
+impl InodeUsage {
+    /// Measure the inode usage of the file system of `base`, if it reports inode counts.
+    fn new(base: &Path) -> Option<Self> {
+        let file = base.join("inode_usage");
+        let link_path = base.join("inode_usage_link");
+        open(&file, OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU).unwrap();
+        let free = free_inodes(base)?;
+        link(&file, &link_path).unwrap();
+        let links_charged = free_inodes(base)? < free;
+        unlink(&link_path).unwrap();
+        unlink(&file).unwrap();
+
+        Some(InodeUsage {
+            free_before: free_inodes(base)?,
+            links_charged,
+        })
+    }
+}
+
+/// Check the invariants of the tree rooted at `base`, which must not be modified concurrently.
+/// `usage` is used to check that no inode was leaked, when the file system reports inode counts.
+fn check_invariants(base: &Path, usage: Option<InodeUsage>) -> Vec<String> {
+    let mut errors = vec![];
+    let mut walk = Walk::default();
+    let parent = lstat(&base.join("..")).unwrap().st_ino;
+    walk.walk(base, parent, &mut errors);
+
+    for (ino, paths) in &walk.paths {
+        let st = lstat(&paths[0]).unwrap();
+        let is_dir = st.st_mode & SFlag::S_IFMT.bits() == SFlag::S_IFDIR.bits();
+        if is_dir && paths.len() > 1 {
+            errors.push(format!(
+                "directory inode {ino} has several paths: {paths:?}"
+            ));
+        }
+        if !is_dir && walk.nlink[ino] != paths.len() as u64 {
+            errors.push(format!(
+                "inode {ino} has a link count of {}, but {} hard links were found: {paths:?}",
+                walk.nlink[ino],
+                paths.len()
+            ));
+        }
+    }
+
+    if let (Some(usage), Some(free)) = (usage, free_inodes(base)) {
+        let allocated = usage.free_before.saturating_sub(free);
+        let inodes = walk.paths.len() as u64;
+        let entries = walk.paths.values().map(|paths| paths.len() as u64).sum();
+        let expected = if usage.links_charged { entries } else { inodes };
+        if allocated != expected {
+            errors.push(format!(
+                "{allocated} inodes were allocated, but the tree has {inodes} inodes and {entries} entries"
+            ));
+        }
+    }
+
+    errors
+}
+
+fn assert_no_errors(errors: &[String], seed: u64) {
+    assert!(
+        errors.is_empty(),
+        "invariants violated (seed {seed}):\n{}",
+        errors.join("\n")
+    );
+}
+
+/// Remove the tree rooted at `base`, but not `base` itself.
+fn remove_tree(base: &Path) {
+    for entry in read_dir(base).unwrap() {
+        let path = entry.unwrap().path();
+        if lstat(&path).unwrap().st_mode & SFlag::S_IFMT.bits() == SFlag::S_IFDIR.bits() {
+            remove_tree(&path);
+            rmdir(&path).unwrap();
+        } else {
+            unlink(&path).unwrap();
+        }
+    }
+}
+
+crate::test_case! {
+    /// Concurrent create, link, rename, unlink, mkdir and rmdir on a shared set of names
+    /// keep the directory tree consistent
+    namespace_invariants; stress_enabled
+}
+fn namespace_invariants(ctx: &mut TestContext) {
+    let settings = Settings::new(ctx);
+    let base = ctx.base_path();
+    let paths = shared_paths();
+    let deadline = Instant::now() + settings.duration;
+    // Workers hold a read lock for each operation, so that a write lock pauses them
+    let pause = RwLock::new(());
+    let usage = InodeUsage::new(base);
+
+    let errors = thread::scope(|s| {
+        let workers: Vec<_> = (0..settings.threads)
+            .map(|i| {
+                let (pause, paths) = (&pause, &paths);
+                let mut rng = settings.rng(i);
+                s.spawn(move || {
+                    while Instant::now() < deadline {
+                        let _guard = pause.read().unwrap();
+                        random_operation(&mut rng, base, paths)?;
+                    }
+                    Ok::<_, String>(())
+                })
+            })
+            .collect();
+
+        let mut errors = vec![];
+        for _ in 0..CHECKPOINTS {
+            thread::sleep(settings.duration / (CHECKPOINTS + 1));
+            let _guard = pause.write().unwrap();
+            errors.extend(check_invariants(base, usage));
+        }
+        for worker in workers {
+            if let Err(e) = worker.join().unwrap() {
+                errors.push(e);
+            }
+        }
+
+        errors
+    });
+    assert_no_errors(&errors, settings.seed);
+    assert_no_errors(&check_invariants(base, usage), settings.seed);
+
+    // Removing every entry releases all the inodes
+    remove_tree(base);
+    assert_eq!(free_inodes(base), usage.map(|usage| usage.free_before));
+    let nlink = lstat(base).unwrap().st_nlink;
+    assert!(nlink == 2 || nlink == 1, "link count of {nlink}");
+}
+
+crate::test_case! {
+    /// A concurrent observer never sees the destination of rename missing,
+    /// nor a partially written file
+    rename_atomicity; stress_enabled
+}
+fn rename_atomicity(ctx: &mut TestContext) {
+    const CONTENT_LEN: usize = 8;
+    let settings = Settings::new(ctx);
+    let base = ctx.base_path();
+    let file = base.join("file");
+    let dir = base.join("dir");
+    let deadline = Instant::now() + settings.duration;
+
+    write(
+        open(&file, OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU).unwrap(),
+        &[0; CONTENT_LEN],
+    )
+    .unwrap();
+    mkdir(&dir, Mode::S_IRWXU).unwrap();
+
+    let renamers = settings.threads.div_ceil(2);
+    let errors: Vec<String> = thread::scope(|s| {
+        let renamers: Vec<_> = (0..renamers)
+            .map(|i| {
+                let (file, dir) = (&file, &dir);
+                let mut rng = settings.rng(i);
+                s.spawn(move || -> Result<(), String> {
+                    let tmp_file = base.join(format!("tmp_file{i}"));
+                    let tmp_dir = base.join(format!("tmp_dir{i}"));
+                    while Instant::now() < deadline {
+                        if rng.gen() {
+                            let fd =
+                                open(&tmp_file, OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU)
+                                    .map_err(|e| {
+                                        format!("cannot create {}: {e}", tmp_file.display())
+                                    })?;
+                            assert_eq!(write(fd, &[i as u8 + 1; CONTENT_LEN]), Ok(CONTENT_LEN));
+                            rename(&tmp_file, file).map_err(|e| format!("rename failed: {e}"))?;
+                        } else {
+                            mkdir(&tmp_dir, Mode::S_IRWXU)
+                                .map_err(|e| format!("cannot create {}: {e}", tmp_dir.display()))?;
+                            rename(&tmp_dir, dir).map_err(|e| format!("rename failed: {e}"))?;
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        let observers: Vec<_> = (renamers.len()..settings.threads)
+            .map(|_| {
+                let (file, dir) = (&file, &dir);
+                s.spawn(move || -> Result<(), String> {
+                    while Instant::now() < deadline {
+                        lstat(dir)
+                            .map_err(|e| format!("lstat on {} failed: {e}", dir.display()))?;
+                        let fd = open(file, OFlag::O_RDONLY, Mode::empty())
+                            .map_err(|e| format!("open on {} failed: {e}", file.display()))?;
+                        let mut buf = [0; CONTENT_LEN + 1];
+                        match read(fd.as_raw_fd(), &mut buf) {
+                            Ok(CONTENT_LEN) => (),
+                            res => {
+                                return Err(format!("read {res:?}, expected {CONTENT_LEN} bytes"))
+                            }
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        renamers
+            .into_iter()
+            .chain(observers)
+            .filter_map(|t| t.join().unwrap().err())
+            .collect()
+    });
+    assert_no_errors(&errors, settings.seed);
+}
@@ -0,0 +1,289 @@
+//! Behaviour of the S_ISUID and S_ISGID bits with `chmod`, `chown`, `write` and `truncate`,
+//! checked for each kind of caller.
+
+use std::{os::fd::AsRawFd, path::Path};
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::mode_t,
+    sys::stat::{fstat, stat, Mode},
+    unistd::{write, Gid, User},
+};
+
+use crate::{
+    context::{FileType, SerializedTestContext},
+    utils::{chmod, chown, open, truncate},
+};
+
+const S_ISUID: mode_t = Mode::S_ISUID.bits();
+const S_ISGID: mode_t = Mode::S_ISGID.bits();
+
+/// Relation of the calling process to the tested file.
+#[derive(Debug, Clone, Copy)]
+enum Caller {
+    /// Owner of the file, which is also a member of its group.
+    Owner,
+    /// Member of the group of the file, which is not its owner.
+    GroupMember,
+    /// Neither the owner nor a member of the group of the file.
+    Other,
+    /// Super-user.
+    Root,
+}
+
+const CALLERS: [Caller; 4] = [
+    Caller::Owner,
+    Caller::GroupMember,
+    Caller::Other,
+    Caller::Root,
+];
+
+/// Identities of the callers.
+/// The file is owned by `owner`, and its group is the one of `member`.
+struct Callers<'a> {
+    owner: &'a User,
+    member: &'a User,
+}
+
+impl<'a> Callers<'a> {
+    fn new(ctx: &'a SerializedTestContext) -> Self {
+        Self {
+            owner: ctx.get_new_user(),
+            member: ctx.get_new_user(),
+        }
+    }
+
+    /// Create a file owned by the owner, with the group of the member and the provided mode.
+    fn create(
+        &self,
+        ctx: &SerializedTestContext,
+        ft: FileType,
+        mode: mode_t,
+    ) -> std::path::PathBuf {
+        let path = ctx.create(ft).unwrap();
+        chown(&path, Some(self.owner.uid), Some(self.member.gid)).unwrap();
+        chmod(&path, Mode::from_bits_truncate(mode)).unwrap();
+
+        path
+    }
+
+    /// Execute the function as the caller.
+    fn run<T, F>(&self, ctx: &SerializedTestContext, caller: Caller, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        let mut res = None;
+        let call = || res = Some(f());
+        match caller {
+            Caller::Owner => {
+                ctx.as_user(self.owner, Some(&[self.owner.gid, self.member.gid]), call)
+            }
+            Caller::GroupMember => ctx.as_user(self.member, None, call),
+            // Not a member of the group of the file
+            Caller::Other => ctx.as_user(self.member, Some(&[self.owner.gid]), call),
+            Caller::Root => call(),
+        }
+
+        res.unwrap()
+    }
+}
+
+fn mode(path: &Path) -> mode_t {
+    stat(path).unwrap().st_mode & 0o7777
+}
+
+crate::test_case! {
+    /// chmod sets S_ISUID and S_ISGID only if the caller is the owner of the file or the super-user
+    // chmod/12.t
+    chmod_set, serialized, root => [Regular, Dir, Fifo]
+}
+fn chmod_set(ctx: &mut SerializedTestContext, ft: FileType) {
+    let callers = Callers::new(ctx);
+
+    for caller in CALLERS {
+        let path = callers.create(ctx, ft.clone(), 0o755);
+
+        let res = callers.run(ctx, caller, || {
+            chmod(&path, Mode::from_bits_truncate(0o6755))
+        });
+        match caller {
+            Caller::Owner | Caller::Root => {
+                assert_eq!(res, Ok(()), "{caller:?}");
+                assert_eq!(mode(&path), 0o6755, "{caller:?}");
+            }
+            Caller::GroupMember | Caller::Other => {
+                assert_eq!(res, Err(Errno::EPERM), "{caller:?}");
+                assert_eq!(mode(&path), 0o755, "{caller:?}");
+            }
+        }
+    }
+}
+
+crate::test_case! {
+    /// chmod does not set S_ISGID if the caller is the owner of the file but not a member of its group,
+    /// either by silently dropping it or by returning EPERM
+    // chmod/00.t
+    chmod_sgid_not_member, serialized, root => [Regular, Dir, Fifo]
+}
+fn chmod_sgid_not_member(ctx: &mut SerializedTestContext, ft: FileType) {
+    let callers = Callers::new(ctx);
+    let path = callers.create(ctx, ft, 0o755);
+
+    ctx.as_user(callers.owner, None, || {
+        match chmod(&path, Mode::from_bits_truncate(0o6755)) {
+            Ok(()) => assert_eq!(mode(&path), 0o4755),
+            Err(Errno::EPERM) => assert_eq!(mode(&path), 0o755),
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    });
+}
+
+/// Expected mode after a successful write or truncate by the caller,
+/// or `None` if both the original mode and the mode without the bits are allowed.
+fn expected_mode_after_write(caller: Caller, original_mode: mode_t) -> Option<mode_t> {
+    match caller {
+        // POSIX allows the bits to be cleared for the owner too
+        Caller::Owner => None,
+        Caller::GroupMember | Caller::Other => Some(original_mode & !(S_ISUID | S_ISGID)),
+        Caller::Root => Some(original_mode),
+    }
+}
+
+fn assert_mode_after_write(
+    path: &Path,
+    caller: Caller,
+    original_mode: mode_t,
+    actual_mode: mode_t,
+) {
+    match expected_mode_after_write(caller, original_mode) {
+        Some(expected_mode) => assert_eq!(
+            actual_mode,
+            expected_mode,
+            "{caller:?} {original_mode:#o} {}",
+            path.display()
+        ),
+        None => assert!(
+            actual_mode == original_mode || actual_mode == original_mode & !(S_ISUID | S_ISGID),
+            "{caller:?} {original_mode:#o} {actual_mode:#o}"
+        ),
+    }
+}
+
+crate::test_case! {
+    /// Writing to a regular file clears its S_ISUID and S_ISGID bits
+    /// if the caller is not the owner of the file or the super-user
+    // chmod/12.t
+    write_clear, serialized, root
+}
+fn write_clear(ctx: &mut SerializedTestContext) {
+    let callers = Callers::new(ctx);
+
+    for caller in CALLERS {
+        for original_mode in [0o4777, 0o2777, 0o6777] {
+            let path = callers.create(ctx, FileType::Regular, original_mode);
+
+            let fd_mode = callers.run(ctx, caller, || {
+                let fd = open(&path, OFlag::O_RDWR, Mode::empty()).unwrap();
+                assert_eq!(write(&fd, b"x").unwrap(), 1);
+                fstat(fd.as_raw_fd()).unwrap().st_mode & 0o7777
+            });
+            assert_mode_after_write(&path, caller, original_mode, fd_mode);
+            assert_mode_after_write(&path, caller, original_mode, mode(&path));
+        }
+    }
+}
+
+crate::test_case! {
+    /// Truncating a regular file clears its S_ISUID and S_ISGID bits
+    /// if the caller is not the owner of the file or the super-user
+    truncate_clear, serialized, root
+}
+fn truncate_clear(ctx: &mut SerializedTestContext) {
+    let callers = Callers::new(ctx);
+
+    for caller in CALLERS {
+        for original_mode in [0o4777, 0o2777, 0o6777] {
+            let path = callers.create(ctx, FileType::Regular, original_mode);
+
+            callers.run(ctx, caller, || truncate(&path, 123).unwrap());
+            assert_mode_after_write(&path, caller, original_mode, mode(&path));
+        }
+    }
+}
+
+crate::test_case! {
+    /// chown of an executable regular file clears its S_ISUID and S_ISGID bits
+    /// when called by the owner, may clear them when called by the super-user,
+    /// and returns EPERM for the other callers
+    // chown/00.t, granular/06.t
+    chown_clear, serialized, root
+}
+fn chown_clear(ctx: &mut SerializedTestContext) {
+    let callers = Callers::new(ctx);
+
+    for caller in CALLERS {
+        let path = callers.create(ctx, FileType::Regular, 0o6555);
+
+        let res = callers.run(ctx, caller, || {
+            chown(&path, Some(callers.owner.uid), Some(callers.owner.gid))
+        });
+        match caller {
+            Caller::Owner => {
+                assert_eq!(res, Ok(()), "{caller:?}");
+                assert_eq!(mode(&path), 0o555, "{caller:?}");
+            }
+            Caller::Root => {
+                assert_eq!(res, Ok(()), "{caller:?}");
+                assert!(matches!(mode(&path), 0o6555 | 0o555), "{caller:?}");
+            }
+            Caller::GroupMember | Caller::Other => {
+                assert_eq!(res, Err(Errno::EPERM), "{caller:?}");
+                assert_eq!(mode(&path), 0o6555, "{caller:?}");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn has_fsetid_cap(_: &crate::config::Config, _: &Path) -> anyhow::Result<()> {
+    use caps::{has_cap, CapSet, Capability};
+
+    if !has_cap(None, CapSet::Permitted, Capability::CAP_FSETID)? {
+        anyhow::bail!("process doesn't have the CAP_FSETID cap")
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+crate::test_case! {
+    /// A caller with CAP_FSETID can set S_ISGID without being a member of the group of the file,
+    /// and its writes do not clear S_ISUID and S_ISGID
+    cap_fsetid, serialized, root; has_fsetid_cap
+}
+#[cfg(target_os = "linux")]
+fn cap_fsetid(ctx: &mut SerializedTestContext) {
+    use caps::{raise, CapSet, Capability};
+
+    let callers = Callers::new(ctx);
+    let not_member: &[Gid] = &[callers.owner.gid];
+
+    let path = callers.create(ctx, FileType::Regular, 0o755);
+    ctx.as_user(callers.owner, Some(not_member), || {
+        // The effective set is cleared when the effective user ID changes,
+        // but the permitted one is kept
+        raise(None, CapSet::Effective, Capability::CAP_FSETID).unwrap();
+        chmod(&path, Mode::from_bits_truncate(0o6755)).unwrap();
+    });
+    assert_eq!(mode(&path), 0o6755);
+
+    let path = callers.create(ctx, FileType::Regular, 0o6777);
+    ctx.as_user(callers.member, Some(not_member), || {
+        raise(None, CapSet::Effective, Capability::CAP_FSETID).unwrap();
+        let fd = open(&path, OFlag::O_WRONLY, Mode::empty()).unwrap();
+        assert_eq!(write(&fd, b"x").unwrap(), 1);
+        truncate(&path, 123).unwrap();
+    });
+    assert_eq!(mode(&path), 0o6777);
+}
@@ -1,12 +1,20 @@
-use std::{fs::File, io::Write};
+use std::{fs::File, io::Write, path::Path};
 
-use nix::{errno::Errno, sys::stat::lstat, unistd::truncate};
+#[cfg(not(target_os = "linux"))]
+use nix::unistd::{pathconf, PathconfVar};
+use nix::{
+    errno::Errno,
+    libc::off_t,
+    sys::stat::{lstat, Mode},
+};
 use rand::random;
 
 use crate::{
+    config::Config,
     context::{FileType, SerializedTestContext},
     test::TestContext,
     tests::{assert_ctime_changed, assert_ctime_unchanged},
+    utils::{chmod, chown, truncate},
 };
 
 use super::errors::{
@@ -19,6 +27,9 @@ use super::errors::{
     etxtbsy::etxtbsy_test_case,
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{eperm_append_test_case, eperm_immutable_test_case};
+
 crate::test_case! {
     /// truncate should extend a file, and shrink a sparse file
     // truncate/00.t
@@ -105,6 +116,66 @@ enametoolong_path_test_case!(truncate(~path, 0));
 enoent_named_file_test_case!(truncate(~path, 0));
 enoent_comp_test_case!(truncate(~path, 0));
 
+crate::test_case! {
+    /// truncate returns EACCES when search permission is denied for a component of the path prefix
+    // (f)truncate/05.t
+    eacces_search, serialized, root
+}
+fn eacces_search(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    let dir = ctx.create(FileType::Dir).unwrap();
+    chown(&dir, Some(user.uid), Some(user.gid)).unwrap();
+    let path = ctx
+        .new_file(FileType::Regular)
+        .name(dir.join("file"))
+        .create()
+        .unwrap();
+    chown(&path, Some(user.uid), Some(user.gid)).unwrap();
+
+    ctx.as_user(user, None, || {
+        truncate(&path, 123).unwrap();
+    });
+    assert_eq!(lstat(&path).unwrap().st_size, 123);
+
+    chmod(&dir, Mode::from_bits_truncate(0o644)).unwrap();
+    ctx.as_user(user, None, || {
+        assert_eq!(truncate(&path, 1234), Err(Errno::EACCES));
+    });
+
+    chmod(&dir, Mode::from_bits_truncate(0o755)).unwrap();
+    assert_eq!(lstat(&path).unwrap().st_size, 123);
+    ctx.as_user(user, None, || {
+        truncate(&path, 1234).unwrap();
+    });
+    assert_eq!(lstat(&path).unwrap().st_size, 1234);
+}
+
+crate::test_case! {
+    /// truncate returns EACCES if the named file is not writable by the user
+    // (f)truncate/06.t
+    eacces_not_writable, serialized, root
+}
+fn eacces_not_writable(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    let path = ctx
+        .new_file(FileType::Regular)
+        .mode(0o644)
+        .create()
+        .unwrap();
+
+    ctx.as_user(user, None, || {
+        assert_eq!(truncate(&path, 123), Err(Errno::EACCES));
+    });
+
+    chown(&path, Some(user.uid), Some(user.gid)).unwrap();
+    chmod(&path, Mode::from_bits_truncate(0o444)).unwrap();
+    ctx.as_user(user, None, || {
+        assert_eq!(truncate(&path, 123), Err(Errno::EACCES));
+    });
+
+    assert_eq!(lstat(&path).unwrap().st_size, 0);
+}
+
 // truncate/07.t
 eloop_comp_test_case!(truncate(~path, 0));
 
@@ -121,9 +192,71 @@ fn eisdir(ctx: &mut TestContext) {
 // (f)truncate/10.t
 erofs_named_test_case!(truncate(~path, 123));
 
+// truncate/08.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(truncate(~path, 123) => [Regular]);
+
+// truncate/08.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(truncate(~path, 123) => [Regular]);
+
 // (f)truncate/11.t
 etxtbsy_test_case!(truncate(~path, 123));
 
+/// Return a length greater than the maximum size of a file in the file system of `path`,
+/// or an error if it is not reliably known.
+#[cfg(target_os = "linux")]
+fn length_above_max_file_size(path: &Path) -> anyhow::Result<off_t> {
+    use nix::sys::statfs::{statfs, EXT4_SUPER_MAGIC, MSDOS_SUPER_MAGIC};
+
+    // glibc only guesses FILESIZEBITS from the file system type,
+    // so the limit is derived from the on-disk format of known file systems
+    let fs = statfs(path)?;
+    match fs.filesystem_type() {
+        // ext2, ext3 and ext4 address files with 32-bit logical block numbers
+        EXT4_SUPER_MAGIC => Ok((1 << 32) * fs.block_size() as off_t),
+        // The size of a file is stored in 32 bits on FAT
+        MSDOS_SUPER_MAGIC => Ok(1 << 32),
+        fs_type => anyhow::bail!(
+            "The maximum file size of the file system type {:#x} is unknown",
+            fs_type.0
+        ),
+    }
+}
+
+/// Return a length greater than the maximum size of a file in the file system of `path`,
+/// or an error if it is not reliably known.
+#[cfg(not(target_os = "linux"))]
+fn length_above_max_file_size(path: &Path) -> anyhow::Result<off_t> {
+    // Minimum number of bits needed to represent the maximum file size as a signed integer
+    match pathconf(path, PathconfVar::FILESIZEBITS)? {
+        Some(bits) if bits < off_t::BITS.into() => Ok(1 << (bits - 1)),
+        _ => anyhow::bail!("The maximum file size cannot be represented by off_t"),
+    }
+}
+
+/// Guard to allow execution of this test only if the maximum file size is reliably known.
+fn max_file_size_known(_: &Config, base_path: &Path) -> anyhow::Result<()> {
+    length_above_max_file_size(base_path).map(drop)
+}
+
+crate::test_case! {
+    /// truncate returns EFBIG or EINVAL if the length argument was greater than the maximum file size
+    // (f)truncate/12.t
+    efbig; max_file_size_known
+}
+fn efbig(ctx: &mut TestContext) {
+    let path = ctx.create(FileType::Regular).unwrap();
+    let length = length_above_max_file_size(&path).unwrap();
+
+    let res = truncate(&path, length);
+    assert!(
+        matches!(res, Err(Errno::EFBIG | Errno::EINVAL)),
+        "truncate to {length} bytes returned {res:?}"
+    );
+    assert_eq!(lstat(&path).unwrap().st_size, 0);
+}
+
 crate::test_case! {
     /// truncate returns EINVAL if the length argument was less than 0
     // truncate/13.t
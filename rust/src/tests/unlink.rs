@@ -1,11 +1,12 @@
 use std::os::fd::AsRawFd;
 
-use nix::{sys::stat::fstat, unistd::unlink};
+use nix::{errno::Errno, sys::stat::fstat};
 
 use crate::{
     context::{FileType, SerializedTestContext, TestContext},
+    outcome::{assert_outcome, Outcome},
     tests::{assert_ctime_changed, assert_ctime_unchanged},
-    utils::link,
+    utils::{chown, link, rmdir, unlink},
 };
 
 use super::{
@@ -20,6 +21,12 @@ use super::{
     },
 };
 
+#[cfg(inode_flags)]
+use super::errors::eperm::{
+    eperm_append_test_case, eperm_immutable_test_case, eperm_parent_append_test_case,
+    eperm_parent_immutable_test_case,
+};
+
 crate::test_case! {
     /// unlink removes regular, block and char files, symbolic links, fifos and sockets
     // unlink/00.t
@@ -157,8 +164,77 @@ enoent_named_file_test_case!(unlink);
 // unlink/07.t
 eloop_comp_test_case!(unlink);
 
+crate::test_case! {
+    /// unlink may return EPERM if the named file is a directory
+    // unlink/08.t
+    eperm_dir, root
+}
+fn eperm_dir(ctx: &mut TestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+
+    // POSIX specifies EPERM, but Linux returns EISDIR,
+    // and some systems allow the super-user to unlink directories
+    let res = assert_outcome(
+        "unlink of a directory by the super-user",
+        unlink(&dir),
+        &[
+            Outcome::Error(Errno::EPERM),
+            Outcome::Error(Errno::EISDIR),
+            Outcome::Success,
+        ],
+    );
+    match res {
+        Ok(()) => assert!(dir.symlink_metadata().is_err()),
+        Err(_) => rmdir(&dir).unwrap(),
+    }
+}
+
+crate::test_case! {
+    /// unlink returns EPERM (EISDIR on Linux) if the named file is a directory
+    /// and the calling process does not have appropriate privileges
+    // unlink/08.t
+    eperm_dir_unprivileged, serialized, root
+}
+fn eperm_dir_unprivileged(ctx: &mut SerializedTestContext) {
+    let user = ctx.get_new_user();
+    chown(ctx.base_path(), Some(user.uid), Some(user.gid)).unwrap();
+    let dir = ctx.create(FileType::Dir).unwrap();
+    chown(&dir, Some(user.uid), Some(user.gid)).unwrap();
+
+    ctx.as_user(user, None, || {
+        // Linux returns EISDIR
+        assert_outcome(
+            "unlink of a directory by an unprivileged user",
+            unlink(&dir),
+            &[Outcome::Error(Errno::EPERM), Outcome::Error(Errno::EISDIR)],
+        )
+        .unwrap_err();
+    });
+    assert!(dir.is_dir());
+}
+
 // unlink/12.t
 erofs_named_test_case!(unlink);
 
+// unlink/09.t
+#[cfg(inode_flags)]
+eperm_immutable_test_case!(unlink => [Regular]);
+
+// unlink/09.t
+#[cfg(inode_flags)]
+eperm_append_test_case!(unlink => [Regular]);
+
+// unlink/10.t
+#[cfg(inode_flags)]
+eperm_parent_immutable_test_case!(unlink, |_: &mut TestContext, existing: &std::path::Path, _: &std::path::Path| {
+    unlink(existing)
+} => [Regular, Fifo, Socket, Symlink(None)]);
+
+// unlink/10.t
+#[cfg(inode_flags)]
+eperm_parent_append_test_case!(unlink, |_: &mut TestContext, existing: &std::path::Path, _: &std::path::Path| {
+    unlink(existing)
+} => [Regular, Fifo, Socket, Symlink(None)]);
+
 // unlink/13.t
 efault_path_test_case!(unlink, nix::libc::unlink);
@@ -0,0 +1,179 @@
+//! Tests for files and directories which are removed while still in use.
+//!
+//! POSIX requires the data of a removed file to stay accessible until the last descriptor is closed.
+
+use std::os::fd::AsRawFd;
+
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::off_t,
+    sys::{
+        mman::{MapFlags, ProtFlags},
+        stat::{fchmod, fstat, lstat, mknod, stat, Mode, SFlag},
+        uio::pwrite,
+    },
+    unistd::{fsync, mkdir},
+};
+
+use crate::{
+    context::{FileType, SerializedTestContext, TestContext},
+    utils::{ftruncate, open, rename, rmdir, symlink, unlink},
+};
+
+use super::{
+    data::{pattern, pread_exact},
+    mmap::{page_size, Mapping},
+};
+
+crate::test_case! {
+    /// An unlinked file which is still open can be read, written, truncated and its mode changed,
+    /// and fstat reports st_nlink as 0
+    unlinked_file_io
+}
+fn unlinked_file_io(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let fd2 = open(&path, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    let data = pattern(10000, None);
+    assert_eq!(pwrite(&fd, &data, 0).unwrap(), data.len());
+
+    unlink(&path).unwrap();
+    assert_eq!(lstat(&path).err(), Some(Errno::ENOENT));
+
+    let st = fstat(fd.as_raw_fd()).unwrap();
+    assert_eq!(st.st_nlink, 0);
+    assert_eq!(st.st_size, data.len() as off_t);
+    assert_eq!(pread_exact(&fd, data.len(), 0), data);
+
+    // Write past the previous end
+    let more = pattern(5000, None);
+    assert_eq!(pwrite(&fd, &more, 20000).unwrap(), more.len());
+    fsync(fd.as_raw_fd()).unwrap();
+    assert_eq!(fstat(fd.as_raw_fd()).unwrap().st_size, 25000);
+    assert_eq!(pread_exact(&fd, more.len(), 20000), more);
+
+    fchmod(fd.as_raw_fd(), Mode::from_bits_truncate(0o600)).unwrap();
+    assert_eq!(fstat(fd.as_raw_fd()).unwrap().st_mode & 0o777, 0o600);
+
+    ftruncate(&fd, 100).unwrap();
+    assert_eq!(fstat(fd.as_raw_fd()).unwrap().st_size, 100);
+
+    // Closing one descriptor does not release the data for the other ones
+    drop(fd);
+    let st = fstat(fd2.as_raw_fd()).unwrap();
+    assert_eq!(st.st_nlink, 0);
+    assert_eq!(st.st_size, 100);
+    assert_eq!(pread_exact(&fd2, 100, 0), &data[..100]);
+}
+
+crate::test_case! {
+    /// A new file created at the path of an unlinked open file is unrelated to it
+    unlinked_path_reused
+}
+fn unlinked_path_reused(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let data = pattern(100, None);
+    assert_eq!(pwrite(&fd, &data, 0).unwrap(), data.len());
+    let ino = fstat(fd.as_raw_fd()).unwrap().st_ino;
+
+    unlink(&path).unwrap();
+    let (_, new_fd) = ctx
+        .new_file(FileType::Regular)
+        .name(&path)
+        .open(OFlag::O_RDWR)
+        .unwrap();
+
+    let st = fstat(new_fd.as_raw_fd()).unwrap();
+    assert_eq!(st.st_size, 0);
+    assert_eq!(st.st_nlink, 1);
+    assert_ne!(st.st_ino, ino);
+    assert_eq!(pread_exact(&fd, data.len(), 0), data);
+}
+
+crate::test_case! {
+    /// Entries cannot be created in a directory which was removed while being the current working directory
+    removed_cwd, serialized
+}
+fn removed_cwd(ctx: &mut SerializedTestContext) {
+    let dir = ctx.create(FileType::Dir).unwrap();
+
+    ctx.with_cwd(&dir, || {
+        rmdir(&dir).unwrap();
+
+        let st = stat(".").unwrap();
+        assert_eq!(st.st_mode & SFlag::S_IFMT.bits(), SFlag::S_IFDIR.bits());
+
+        assert_eq!(
+            open("file", OFlag::O_CREAT | OFlag::O_WRONLY, Mode::S_IRWXU).err(),
+            Some(Errno::ENOENT)
+        );
+        assert_eq!(mkdir("dir", Mode::S_IRWXU), Err(Errno::ENOENT));
+        assert_eq!(
+            mknod("fifo", SFlag::S_IFIFO, Mode::S_IRWXU, 0),
+            Err(Errno::ENOENT)
+        );
+        assert_eq!(symlink("test", "symlink"), Err(Errno::ENOENT));
+    });
+}
+
+crate::test_case! {
+    /// The data of a file unlinked while it is mapped stays accessible through the mapping and descriptors
+    unlinked_while_mapped
+}
+fn unlinked_while_mapped(ctx: &mut TestContext) {
+    let len = 2 * page_size();
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut expected = pattern(len as off_t, None);
+    assert_eq!(pwrite(&fd, &expected, 0).unwrap(), len);
+
+    let mut mapping = Mapping::new(
+        &fd,
+        len,
+        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+        MapFlags::MAP_SHARED,
+    )
+    .unwrap();
+    unlink(&path).unwrap();
+
+    assert_eq!(mapping.as_slice(), expected);
+    mapping.as_mut_slice()[..10].fill(0);
+    expected[..10].fill(0);
+    mapping.sync();
+    assert_eq!(pread_exact(&fd, len, 0), expected);
+
+    // The mapping outlives the descriptor
+    drop(fd);
+    assert_eq!(mapping.as_slice(), expected);
+}
+
+crate::test_case! {
+    /// A file replaced by rename stays accessible through its open descriptors
+    rename_replaces_open_target
+}
+fn rename_replaces_open_target(ctx: &mut TestContext) {
+    let (target, target_fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let old_data = pattern(1000, None);
+    assert_eq!(pwrite(&target_fd, &old_data, 0).unwrap(), old_data.len());
+
+    let (source, source_fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let new_data = vec![1; 500];
+    assert_eq!(pwrite(&source_fd, &new_data, 0).unwrap(), new_data.len());
+
+    rename(&source, &target).unwrap();
+
+    let st = fstat(target_fd.as_raw_fd()).unwrap();
+    assert_eq!(st.st_nlink, 0);
+    assert_eq!(pread_exact(&target_fd, old_data.len(), 0), old_data);
+
+    let fd = open(&target, OFlag::O_RDONLY, Mode::empty()).unwrap();
+    assert_eq!(
+        fstat(fd.as_raw_fd()).unwrap().st_ino,
+        fstat(source_fd.as_raw_fd()).unwrap().st_ino
+    );
+    assert_eq!(pread_exact(&fd, new_data.len(), 0), new_data);
+
+    // Writing to the replaced file does not affect the new one
+    assert_eq!(pwrite(&target_fd, &[0; 10], 0).unwrap(), 10);
+    assert_eq!(pread_exact(&fd, new_data.len(), 0), new_data);
+    assert_eq!(lstat(&target).unwrap().st_size, new_data.len() as off_t);
+}
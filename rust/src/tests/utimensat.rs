@@ -6,14 +6,14 @@ use std::{
 #[cfg(birthtime)]
 use crate::tests::birthtime_ts;
 use crate::tests::MetadataExt;
-use crate::utils::chmod;
+use crate::utils::{chmod, utimensat};
 use crate::{context::FileType, test::TestContext};
 use crate::{context::SerializedTestContext, test::FileSystemFeature};
 
 use nix::{
     errno::Errno,
     sys::{
-        stat::{utimensat, Mode, UtimensatFlags::*},
+        stat::{Mode, UtimensatFlags::*},
         time::{TimeSpec, TimeValLike},
     },
 };
@@ -31,7 +31,7 @@ fn changes_timestamps(ctx: &mut TestContext, f_type: FileType) {
     let date2 = TimeSpec::seconds(1950000000); // Fri Oct 17 04:40:00 MDT 2031
     let path = ctx.create(f_type).unwrap();
 
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
 
     let md = metadata(&path).unwrap();
     assert_eq!(md.atime_ts(), date1);
@@ -53,7 +53,7 @@ fn utime_now(ctx: &mut TestContext) {
     let orig_mtime = md.mtime_ts();
     ctx.nap();
 
-    assert!(utimensat(None, &path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
 
     let md = metadata(&path).unwrap();
     let delta_atime = md.atime_ts() - orig_atime;
@@ -82,12 +82,12 @@ fn utime_omit(ctx: &mut TestContext) {
     let md = metadata(&path).unwrap();
     let orig_mtime = md.mtime_ts();
 
-    assert!(utimensat(None, &path, &date1, &UTIME_OMIT, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &UTIME_OMIT, FollowSymlink).is_ok());
     let md = metadata(&path).unwrap();
     assert_eq!(md.atime_ts(), date1);
     assert_eq!(md.mtime_ts(), orig_mtime);
 
-    assert!(utimensat(None, &path, &UTIME_OMIT, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &UTIME_OMIT, &date2, FollowSymlink).is_ok());
     let md = metadata(&path).unwrap();
     assert_eq!(md.atime_ts(), date1);
     assert_eq!(md.mtime_ts(), date2);
@@ -105,13 +105,13 @@ fn birthtime(ctx: &mut TestContext) {
     let date2 = TimeSpec::seconds(200000000); // Mon May  3 13:33:20 MDT 1976
     let path = ctx.create(FileType::Regular).unwrap();
 
-    assert!(utimensat(None, &path, &date1, &date1, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date1, FollowSymlink).is_ok());
     let md = metadata(&path).unwrap();
     assert_eq!(date1, md.atime_ts());
     assert_eq!(date1, md.mtime_ts());
     assert_eq!(date1, birthtime_ts(&path));
 
-    assert!(utimensat(None, &path, &date2, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date2, &date2, FollowSymlink).is_ok());
     let md = metadata(&path).unwrap();
     assert_eq!(date2, md.atime_ts());
     assert_eq!(date2, md.mtime_ts());
@@ -127,8 +127,8 @@ fn order(ctx: &mut TestContext) {
     let date1 = TimeSpec::seconds(1900000000); // Sun Mar 17 11:46:40 MDT 2030
     let date2 = TimeSpec::seconds(1950000000); // Fri Oct 17 04:40:00 MDT 2031
     let path = ctx.create(FileType::Regular).unwrap();
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
-    assert!(utimensat(None, &path, &date2, &date1, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date2, &date1, FollowSymlink).is_ok());
 }
 
 crate::test_case! {
@@ -148,8 +148,8 @@ fn follow_symlink(ctx: &mut TestContext) {
     let lpath = path.with_extension("link");
     symlink(&path, &lpath).unwrap();
 
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
-    assert!(utimensat(None, &lpath, &date3, &date4, NoFollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&lpath, &date3, &date4, NoFollowSymlink).is_ok());
 
     let md = metadata(&path).unwrap();
     let lmd = symlink_metadata(&lpath).unwrap();
@@ -158,7 +158,7 @@ fn follow_symlink(ctx: &mut TestContext) {
     assert_eq!(date3, lmd.atime_ts());
     assert_eq!(date4, lmd.mtime_ts());
 
-    assert!(utimensat(None, &lpath, &date5, &date6, FollowSymlink).is_ok());
+    assert!(utimensat(&lpath, &date5, &date6, FollowSymlink).is_ok());
     let md = metadata(&path).unwrap();
     let lmd = symlink_metadata(&lpath).unwrap();
     assert_eq!(date5, md.atime_ts());
@@ -184,7 +184,7 @@ fn utime_now_nobody(ctx: &mut SerializedTestContext) {
     ctx.as_user(user, None, || {
         assert_eq!(
             Err(Errno::EACCES),
-            utimensat(None, &path, &UTIME_NOW, &UTIME_NOW, FollowSymlink)
+            utimensat(&path, &UTIME_NOW, &UTIME_NOW, FollowSymlink)
         );
     });
 }
@@ -198,7 +198,7 @@ fn utime_now_owner(ctx: &mut TestContext) {
     let path = ctx.create(FileType::Regular).unwrap();
     let mode = Mode::from_bits_truncate(0o444);
     chmod(&path, mode).unwrap();
-    assert!(utimensat(None, &path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
 }
 
 crate::test_case! {
@@ -210,7 +210,7 @@ fn utime_now_root(ctx: &mut TestContext) {
     let path = ctx.create(FileType::Regular).unwrap();
     let mode = Mode::from_bits_truncate(0o444);
     chmod(&path, mode).unwrap();
-    assert!(utimensat(None, &path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &UTIME_NOW, &UTIME_NOW, FollowSymlink).is_ok());
 }
 
 crate::test_case! {
@@ -224,7 +224,7 @@ fn utime_now_write_perm(ctx: &mut SerializedTestContext) {
     chmod(&path, mode).unwrap();
     let user = ctx.get_new_user();
     ctx.as_user(user, None, || {
-        assert!(utimensat(None, &path, &UTIME_OMIT, &UTIME_OMIT, FollowSymlink).is_ok());
+        assert!(utimensat(&path, &UTIME_OMIT, &UTIME_OMIT, FollowSymlink).is_ok());
     });
 }
 
@@ -243,15 +243,15 @@ fn nobody(ctx: &mut SerializedTestContext) {
     ctx.as_user(user, None, || {
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &UTIME_OMIT, &date2, FollowSymlink)
+            utimensat(&path, &UTIME_OMIT, &date2, FollowSymlink)
         );
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &date1, &UTIME_OMIT, FollowSymlink)
+            utimensat(&path, &date1, &UTIME_OMIT, FollowSymlink)
         );
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &date1, &date2, FollowSymlink)
+            utimensat(&path, &date1, &date2, FollowSymlink)
         );
     })
 }
@@ -271,15 +271,15 @@ fn write_perm(ctx: &mut SerializedTestContext) {
     ctx.as_user(user, None, || {
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &UTIME_OMIT, &date2, FollowSymlink)
+            utimensat(&path, &UTIME_OMIT, &date2, FollowSymlink)
         );
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &date1, &UTIME_OMIT, FollowSymlink)
+            utimensat(&path, &date1, &UTIME_OMIT, FollowSymlink)
         );
         assert_eq!(
             Err(Errno::EPERM),
-            utimensat(None, &path, &date1, &date2, FollowSymlink)
+            utimensat(&path, &date1, &date2, FollowSymlink)
         );
     })
 }
@@ -295,7 +295,7 @@ fn owner(ctx: &mut TestContext) {
     let path = ctx.create(FileType::Regular).unwrap();
     let mode = Mode::from_bits_truncate(0o444);
     chmod(&path, mode).unwrap();
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
 }
 crate::test_case! {
     /// Root can always update the timestamps, even if the file is read-only
@@ -308,7 +308,7 @@ fn root(ctx: &mut TestContext) {
     let path = ctx.create(FileType::Regular).unwrap();
     let mode = Mode::from_bits_truncate(0o444);
     chmod(&path, mode).unwrap();
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
 }
 
 crate::test_case! {
@@ -327,7 +327,7 @@ fn subsecond(ctx: &mut TestContext) {
 
     let path = ctx.create(FileType::Regular).unwrap();
 
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
 
     let md = metadata(&path).unwrap();
     assert_eq!(date1, md.atime_ts());
@@ -347,7 +347,7 @@ fn y2038(ctx: &mut TestContext) {
 
     let path = ctx.create(FileType::Regular).unwrap();
 
-    assert!(utimensat(None, &path, &date1, &date2, FollowSymlink).is_ok());
+    assert!(utimensat(&path, &date1, &date2, FollowSymlink).is_ok());
 
     let md = metadata(&path).unwrap();
     assert_eq!(date1, md.atime_ts());
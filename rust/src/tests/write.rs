@@ -0,0 +1,258 @@
+//! Tests for the data path of regular files: `read`, `write`, `pread` and `pwrite`.
+
+use std::os::fd::{AsRawFd, OwnedFd};
+
+use nix::{
+    fcntl::OFlag,
+    libc::off_t,
+    sys::{
+        stat::{fstat, Mode},
+        uio::{pread, pwrite},
+    },
+    unistd::{lseek, read, write, Whence},
+};
+
+use crate::{
+    context::TestContext,
+    tests::{
+        assert_times_changed, assert_times_unchanged,
+        data::{pattern, read_all, st_size},
+        CTIME, MTIME,
+    },
+    utils::{ftruncate, open},
+};
+
+/// Write `data` at `offset` and update the in-memory `model` of the file accordingly.
+fn pwrite_model(fd: &OwnedFd, model: &mut Vec<u8>, data: &[u8], offset: usize) {
+    assert_eq!(pwrite(fd, data, offset as off_t).unwrap(), data.len());
+
+    if model.len() < offset + data.len() {
+        model.resize(offset + data.len(), 0);
+    }
+    model[offset..offset + data.len()].copy_from_slice(data);
+}
+
+fn file_offset(fd: &OwnedFd) -> off_t {
+    lseek(fd.as_raw_fd(), 0, Whence::SeekCur).unwrap()
+}
+
+crate::test_case! {
+    /// Data written at unaligned offsets and across block boundaries reads back unchanged
+    unaligned_read_back
+}
+fn unaligned_read_back(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let blksize = (fstat(fd.as_raw_fd()).unwrap().st_blksize as usize).max(512);
+    let mut model = vec![];
+
+    let offsets = [0, 1, blksize - 1, blksize, blksize + 7, 3 * blksize - 3];
+    let lengths = [1, 511, blksize, blksize + 1, 3 * blksize + 5];
+    for (seed, (&offset, &len)) in offsets
+        .iter()
+        .flat_map(|o| lengths.iter().map(move |l| (o, l)))
+        .enumerate()
+    {
+        pwrite_model(
+            &fd,
+            &mut model,
+            &pattern(len as off_t, Some(seed as off_t)),
+            offset,
+        );
+    }
+
+    assert_eq!(st_size(&fd), model.len() as off_t);
+    assert_eq!(read_all(&fd), model);
+
+    // Read back ranges which start and end in the middle of blocks
+    for (offset, len) in [
+        (1, blksize),
+        (blksize - 1, 2),
+        (blksize + 3, 2 * blksize - 5),
+    ] {
+        let mut buf = vec![0; len];
+        assert_eq!(pread(&fd, &mut buf, offset as off_t).unwrap(), len);
+        assert_eq!(buf, &model[offset..offset + len]);
+    }
+}
+
+crate::test_case! {
+    /// write on a file opened with O_APPEND always writes at the end of the file,
+    /// even after lseek or when another descriptor extended the file
+    append_writes_at_eof
+}
+fn append_writes_at_eof(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut model = vec![];
+    pwrite_model(&fd, &mut model, &pattern(1000, Some(0)), 0);
+
+    let append_fd = open(&path, OFlag::O_WRONLY | OFlag::O_APPEND, Mode::empty()).unwrap();
+
+    let data = pattern(100, Some(1));
+    assert_eq!(write(&append_fd, &data).unwrap(), data.len());
+    model.extend_from_slice(&data);
+    assert_eq!(file_offset(&append_fd), model.len() as off_t);
+
+    lseek(append_fd.as_raw_fd(), 0, Whence::SeekSet).unwrap();
+    let data = pattern(10, Some(2));
+    assert_eq!(write(&append_fd, &data).unwrap(), data.len());
+    model.extend_from_slice(&data);
+    assert_eq!(file_offset(&append_fd), model.len() as off_t);
+
+    // Extend the file through the other descriptor
+    let offset = model.len();
+    pwrite_model(&fd, &mut model, &pattern(500, Some(3)), offset);
+
+    let data = pattern(20, Some(4));
+    assert_eq!(write(&append_fd, &data).unwrap(), data.len());
+    model.extend_from_slice(&data);
+
+    assert_eq!(read_all(&fd), model);
+}
+
+crate::test_case! {
+    /// pread and pwrite neither use nor change the file offset
+    positional_io_ignores_offset
+}
+fn positional_io_ignores_offset(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut model = vec![];
+    pwrite_model(&fd, &mut model, &pattern(1000, Some(0)), 0);
+
+    lseek(fd.as_raw_fd(), 10, Whence::SeekSet).unwrap();
+    pwrite_model(&fd, &mut model, &pattern(100, Some(1)), 500);
+    assert_eq!(file_offset(&fd), 10);
+
+    let mut buf = [0; 100];
+    assert_eq!(pread(&fd, &mut buf, 700).unwrap(), buf.len());
+    assert_eq!(buf, &model[700..800]);
+    assert_eq!(file_offset(&fd), 10);
+
+    // write and read use the file offset which was left untouched
+    let data = pattern(20, Some(2));
+    assert_eq!(write(&fd, &data).unwrap(), data.len());
+    model[10..30].copy_from_slice(&data);
+    assert_eq!(file_offset(&fd), 30);
+
+    assert_eq!(read(fd.as_raw_fd(), &mut buf).unwrap(), buf.len());
+    assert_eq!(buf, &model[30..130]);
+    assert_eq!(file_offset(&fd), 130);
+
+    assert_eq!(read_all(&fd), model);
+}
+
+crate::test_case! {
+    /// read and pread return fewer bytes than requested at the end of the file,
+    /// and 0 at or past the end of the file
+    short_read_at_eof
+}
+fn short_read_at_eof(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut model = vec![];
+    pwrite_model(&fd, &mut model, &pattern(1000, Some(0)), 0);
+
+    let mut buf = [0; 4096];
+    assert_eq!(pread(&fd, &mut buf, 900).unwrap(), 100);
+    assert_eq!(&buf[..100], &model[900..]);
+    assert_eq!(pread(&fd, &mut buf, 1000).unwrap(), 0);
+    assert_eq!(pread(&fd, &mut buf, 5000).unwrap(), 0);
+
+    lseek(fd.as_raw_fd(), 0, Whence::SeekSet).unwrap();
+    assert_eq!(read(fd.as_raw_fd(), &mut buf).unwrap(), 1000);
+    assert_eq!(&buf[..1000], model);
+    assert_eq!(read(fd.as_raw_fd(), &mut buf).unwrap(), 0);
+
+    lseek(fd.as_raw_fd(), 5000, Whence::SeekSet).unwrap();
+    assert_eq!(read(fd.as_raw_fd(), &mut buf).unwrap(), 0);
+}
+
+crate::test_case! {
+    /// Writing past the end of the file extends it, and the gap reads back as zeros
+    write_past_eof_zero_gap
+}
+fn write_past_eof_zero_gap(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut model = vec![];
+    pwrite_model(&fd, &mut model, &pattern(100, Some(0)), 0);
+
+    pwrite_model(&fd, &mut model, &pattern(100, Some(1)), 10000);
+    assert_eq!(st_size(&fd), 10100);
+    assert!(model[100..10000].iter().all(|&b| b == 0));
+    assert_eq!(read_all(&fd), model);
+
+    // The same goes through the file offset
+    lseek(fd.as_raw_fd(), 20000, Whence::SeekSet).unwrap();
+    let data = pattern(10, Some(2));
+    assert_eq!(write(&fd, &data).unwrap(), data.len());
+    model.resize(20000, 0);
+    model.extend_from_slice(&data);
+    assert_eq!(read_all(&fd), model);
+}
+
+crate::test_case! {
+    /// Data removed by truncate does not reappear when writing past the new end of the file
+    truncate_then_write
+}
+fn truncate_then_write(ctx: &mut TestContext) {
+    let (_path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    let mut model = vec![];
+    pwrite_model(&fd, &mut model, &pattern(10000, Some(0)), 0);
+
+    ftruncate(&fd, 3000).unwrap();
+    model.truncate(3000);
+    pwrite_model(&fd, &mut model, &pattern(100, Some(1)), 5000);
+    assert_eq!(read_all(&fd), model);
+
+    // The file offset is not changed by truncate
+    lseek(fd.as_raw_fd(), 8000, Whence::SeekSet).unwrap();
+    ftruncate(&fd, 1000).unwrap();
+    model.truncate(1000);
+    assert_eq!(file_offset(&fd), 8000);
+    let data = pattern(100, Some(2));
+    assert_eq!(write(&fd, &data).unwrap(), data.len());
+    model.resize(8000, 0);
+    model.extend_from_slice(&data);
+    assert_eq!(read_all(&fd), model);
+
+    // Extend with truncate, then write inside the extension
+    ftruncate(&fd, 12000).unwrap();
+    model.resize(12000, 0);
+    pwrite_model(&fd, &mut model, &pattern(100, Some(3)), 10000);
+    assert_eq!(read_all(&fd), model);
+}
+
+crate::test_case! {
+    /// Successful writes update ctime and mtime
+    write_changes_times
+}
+fn write_changes_times(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+
+    assert_times_changed()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            assert_eq!(write(&fd, b"data").unwrap(), 4);
+        });
+
+    assert_times_changed()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            assert_eq!(pwrite(&fd, b"data", 100).unwrap(), 4);
+        });
+}
+
+crate::test_case! {
+    /// Zero-length writes do not update ctime and mtime
+    zero_length_write_unchanged_times
+}
+fn zero_length_write_unchanged_times(ctx: &mut TestContext) {
+    let (path, fd) = ctx.create_file(OFlag::O_RDWR, None).unwrap();
+    assert_eq!(write(&fd, b"data").unwrap(), 4);
+
+    assert_times_unchanged()
+        .path(&path, CTIME | MTIME)
+        .execute(ctx, false, || {
+            assert_eq!(write(&fd, &[]).unwrap(), 0);
+            assert_eq!(pwrite(&fd, &[], 100).unwrap(), 0);
+        });
+    assert_eq!(st_size(&fd), 4);
+}
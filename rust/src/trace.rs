@@ -0,0 +1,937 @@
+//! Recording and replay of the file system syscalls performed by the tests.
+//!
+//! The runner records the last syscalls of every test, which are printed when the test fails,
+//! and the whole trace with `--trace`, which is written to a file.
+//! While a trace is recorded, the [`utils`](crate::utils) wrappers and
+//! [`FileBuilder::create`](crate::context::FileBuilder::create) record each syscall they perform,
+//! along with the credentials and umask in effect and its result.
+//! Syscalls performed directly with `nix` are not recorded.
+//!
+//! Traces use a portable text format, with one syscall per line:
+//!
+//! ```text
+//! 0:0:0 0022 mkdir $BASE/dir 0755 = 0
+//! 65534:65534:65534 0000 rename $BASE/dir $BASE/other = EACCES
+//! ```
+//!
+//! The first field contains the effective user, effective group and supplementary groups,
+//! and the second one the umask. Paths inside the base directory of the test are written relatively
+//! to `$BASE`, flags and errors are written by name, so that traces can be replayed on other systems.
+//! `open` records the file descriptor it returned, which identifies the file
+//! in the following syscalls on file descriptors:
+//!
+//! ```text
+//! 0:0:0 0022 open $BASE/file O_WRONLY|O_CREAT 0644 = 3
+//! 0:0:0 0022 ftruncate 3 1024 = 0
+//! ```
+
+use std::{
+    collections::{HashMap, VecDeque},
+    ffi::OsStr,
+    fmt::Write as _,
+    fs,
+    os::{
+        fd::{AsRawFd, OwnedFd, RawFd},
+        unix::{ffi::OsStrExt, fs::MetadataExt},
+    },
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, bail, Context};
+use clap::Args;
+use colored::Colorize;
+use nix::{
+    errno::Errno,
+    fcntl::OFlag,
+    libc::{c_int, dev_t, off_t},
+    sys::{
+        socket::{bind, socket, AddressFamily, SockFlag, SockType, UnixAddr},
+        stat::{mknod, umask, Mode, SFlag, UtimensatFlags},
+        time::TimeSpec,
+    },
+    unistd::{getgroups, mkdir, mkfifo, setegid, seteuid, setgroups, Gid, Uid},
+    NixPath,
+};
+use tempfile::tempdir_in;
+
+use crate::utils::{
+    chmod, chown, ftruncate, lchmod, lchown, link, open, rename, rmdir, symlink, truncate, unlink,
+    utimensat,
+};
+#[cfg(target_os = "linux")]
+use crate::utils::{set_inode_flags, setxattr};
+
+/// Marker which replaces the base directory of the test in paths.
+const BASE_MARKER: &str = "$BASE";
+/// Encoding of the empty path.
+const EMPTY_PATH: &str = "\"\"";
+/// Number of syscalls shown in the log of a failed test.
+const LOG_LENGTH: usize = 100;
+
+static RECORDER: Mutex<Option<Trace>> = Mutex::new(None);
+
+/// Syscalls performed in the base directory of a test.
+pub struct Trace {
+    base: PathBuf,
+    entries: VecDeque<Entry>,
+    /// Whether all the entries are kept, or only the last [`LOG_LENGTH`] ones.
+    full: bool,
+    /// Number of entries which were not kept.
+    dropped: usize,
+    /// Supplementary groups and umask in effect, which are tracked by [`set_groups`]
+    /// and [`set_umask`] rather than retrieved for each syscall.
+    groups: Vec<Gid>,
+    umask: Mode,
+    /// State of the directory tree when the test failed, see [`close`].
+    tree: Option<String>,
+    /// Whether the test is being cleaned up, in which case syscalls are not recorded anymore.
+    closed: bool,
+}
+
+/// A recorded syscall.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Call {
+    Open {
+        path: PathBuf,
+        flags: OFlag,
+        mode: Mode,
+    },
+    Mkdir {
+        path: PathBuf,
+        mode: Mode,
+    },
+    Mkfifo {
+        path: PathBuf,
+        mode: Mode,
+    },
+    Mknod {
+        path: PathBuf,
+        kind: SFlag,
+        mode: Mode,
+        dev: dev_t,
+    },
+    /// `bind` of a UNIX domain socket.
+    Bind {
+        path: PathBuf,
+    },
+    Symlink {
+        target: PathBuf,
+        path: PathBuf,
+    },
+    /// `chmod` if `follow` is set, `lchmod` otherwise.
+    Chmod {
+        path: PathBuf,
+        mode: Mode,
+        follow: bool,
+    },
+    /// `chown` if `follow` is set, `lchown` otherwise.
+    Chown {
+        path: PathBuf,
+        uid: Option<Uid>,
+        gid: Option<Gid>,
+        follow: bool,
+    },
+    Unlink {
+        path: PathBuf,
+    },
+    Rmdir {
+        path: PathBuf,
+    },
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Link {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Truncate {
+        path: PathBuf,
+        len: off_t,
+    },
+    /// `ftruncate` of a file descriptor returned by a recorded `open`.
+    Ftruncate {
+        fd: RawFd,
+        len: off_t,
+    },
+    Utimensat {
+        path: PathBuf,
+        atime: TimeSpec,
+        mtime: TimeSpec,
+        follow: bool,
+    },
+    Setxattr {
+        path: PathBuf,
+        name: String,
+        value: Vec<u8>,
+    },
+    /// `FS_IOC_SETFLAGS` ioctl on a file descriptor opened on `path`.
+    SetInodeFlags {
+        path: PathBuf,
+        flags: c_int,
+    },
+}
+
+/// Return the path of a [`NixPath`].
+pub fn to_path<P: ?Sized + NixPath>(path: &P) -> PathBuf {
+    path.with_nix_path(|cstr| PathBuf::from(OsStr::from_bytes(cstr.to_bytes())))
+        .unwrap_or_default()
+}
+
+/// Start recording the syscalls performed in `base`,
+/// keeping all of them if `full` is set or only the last [`LOG_LENGTH`] ones otherwise.
+pub fn start(base: &Path, full: bool) {
+    // The umask can only be retrieved by changing it
+    let mask = umask(Mode::empty());
+    umask(mask);
+
+    *RECORDER.lock().unwrap() = Some(Trace {
+        base: base.to_path_buf(),
+        entries: VecDeque::new(),
+        full,
+        dropped: 0,
+        groups: getgroups().unwrap_or_default(),
+        umask: mask,
+        tree: None,
+        closed: false,
+    });
+}
+
+/// Update the supplementary groups recorded with the following syscalls.
+pub fn set_groups(groups: &[Gid]) {
+    if let Some(trace) = RECORDER.lock().unwrap().as_mut() {
+        trace.groups = groups.to_vec();
+    }
+}
+
+/// Update the umask recorded with the following syscalls.
+pub fn set_umask(mask: Mode) {
+    if let Some(trace) = RECORDER.lock().unwrap().as_mut() {
+        trace.umask = mask;
+    }
+}
+
+/// Stop recording and return the recorded trace.
+pub fn stop() -> Option<Trace> {
+    RECORDER.lock().unwrap().take()
+}
+
+/// Stop recording the syscalls of a test before it is cleaned up,
+/// and save the state of its directory tree if `snapshot` is set.
+pub fn close(snapshot: bool) {
+    if let Some(trace) = RECORDER.lock().unwrap().as_mut() {
+        if snapshot && !trace.closed {
+            trace.tree = Some(dump_tree(&trace.base));
+        }
+        trace.closed = true;
+    }
+}
+
+/// Record a syscall and its result, if a trace is being recorded.
+pub fn record<T, F: FnOnce() -> Call>(call: F, result: &nix::Result<T>) {
+    push(call, result.as_ref().map(|_| 0).map_err(|e| *e));
+}
+
+/// Record a syscall returning a file descriptor, along with the descriptor.
+pub fn record_fd<F: FnOnce() -> Call>(call: F, result: &nix::Result<OwnedFd>) {
+    push(
+        call,
+        result.as_ref().map(AsRawFd::as_raw_fd).map_err(|e| *e),
+    );
+}
+
+fn push<F: FnOnce() -> Call>(call: F, result: Result<i32, Errno>) {
+    let mut recorder = RECORDER.lock().unwrap();
+    let Some(trace) = recorder.as_mut().filter(|trace| !trace.closed) else {
+        return;
+    };
+
+    if !trace.full && trace.entries.len() == LOG_LENGTH {
+        trace.entries.pop_front();
+        trace.dropped += 1;
+    }
+    trace.entries.push_back(Entry {
+        uid: Uid::effective(),
+        gid: Gid::effective(),
+        groups: trace.groups.clone(),
+        umask: trace.umask,
+        call: call(),
+        result,
+    });
+}
+
+impl Trace {
+    /// Encode the trace in the portable format.
+    pub fn encode(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| format!("{}\n", entry.encode(&self.base)))
+            .collect()
+    }
+
+    /// Return the name of each recorded syscall, its arguments in the portable format and its result.
+    pub fn calls(&self) -> impl Iterator<Item = (&'static str, String, Result<i32, Errno>)> + '_ {
+        self.entries.iter().map(|entry| {
+            (
+                entry.call.name(),
+                entry.call.encode(&self.base),
+                entry.result,
+            )
+        })
+    }
+
+    /// Return a human-readable log of the last syscalls,
+    /// with paths relative to the base directory, and the state of the directory tree.
+    pub fn log(&self) -> String {
+        let mut log = String::from("Operations:\n");
+        let skipped = self.entries.len().saturating_sub(LOG_LENGTH);
+        if self.dropped + skipped > 0 {
+            let _ = writeln!(log, "\t... {} earlier operations", self.dropped + skipped);
+        }
+        for entry in self.entries.iter().skip(skipped) {
+            let path = |p: &Path| match p.strip_prefix(&self.base) {
+                Ok(rel) if rel.as_os_str().is_empty() => String::from("."),
+                Ok(rel) => rel.display().to_string(),
+                Err(_) => p.display().to_string(),
+            };
+            let _ = writeln!(
+                log,
+                "\t[{}:{}] {}({}) = {}",
+                entry.uid,
+                entry.gid,
+                entry.call.name(),
+                entry.call.args(path).join(", "),
+                encode_result(&entry.result)
+            );
+        }
+        let tree = self.tree.clone().unwrap_or_else(|| dump_tree(&self.base));
+        let _ = write!(log, "Directory tree:\n{tree}");
+
+        log
+    }
+}
+
+/// Return the type, mode, owner, link count and size of each file under `base`.
+fn dump_tree(base: &Path) -> String {
+    let mut dump = String::new();
+    for entry in walkdir::WalkDir::new(base).sort_by_file_name() {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path().strip_prefix(base).unwrap_or(entry.path());
+        let _ = write!(
+            dump,
+            "\t{:07o} {}:{} nlink={} size={} {}",
+            metadata.mode(),
+            metadata.uid(),
+            metadata.gid(),
+            metadata.nlink(),
+            metadata.size(),
+            if path.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                path
+            }
+            .display()
+        );
+        if let Ok(target) = fs::read_link(entry.path()) {
+            let target = target.strip_prefix(base).unwrap_or(&target);
+            let _ = write!(dump, " -> {}", target.display());
+        }
+        dump.push('\n');
+    }
+
+    dump
+}
+
+fn encode_result(result: &Result<i32, Errno>) -> String {
+    match result {
+        Ok(value) => value.to_string(),
+        Err(e) => format!("{e:?}"),
+    }
+}
+
+fn decode_result(s: &str) -> anyhow::Result<Result<i32, Errno>> {
+    if let Ok(value) = s.parse() {
+        return Ok(Ok(value));
+    }
+
+    (1..=255)
+        .map(Errno::from_raw)
+        .find(|e| format!("{e:?}") == s)
+        .map(Err)
+        .ok_or_else(|| anyhow!("unknown error {s}"))
+}
+
+/// Encode a path, relatively to `base` if it is inside it.
+/// Bytes which are not printable ASCII are percent-encoded.
+fn encode_path(path: &Path, base: &Path) -> String {
+    let (bytes, base) = (path.as_os_str().as_bytes(), base.as_os_str().as_bytes());
+    // Trailing slashes are meaningful, so the path is not normalized
+    let (prefix, rest) = match bytes.strip_prefix(base) {
+        Some(rest) if rest.is_empty() || rest[0] == b'/' => (BASE_MARKER, rest),
+        _ => ("", bytes),
+    };
+
+    encode_bytes(prefix, rest)
+}
+
+fn decode_path(s: &str, base: &Path) -> anyhow::Result<PathBuf> {
+    let (prefix, s) = match s.strip_prefix(BASE_MARKER) {
+        Some(rest) => (base.as_os_str().as_bytes(), rest),
+        None => (&[][..], s),
+    };
+    let bytes = decode_bytes(prefix, s)?;
+
+    Ok(PathBuf::from(OsStr::from_bytes(&bytes)))
+}
+
+/// Append `bytes` to `prefix`, percent-encoding the bytes which are not printable ASCII.
+fn encode_bytes(prefix: &str, bytes: &[u8]) -> String {
+    let mut encoded = String::from(prefix);
+    for &b in bytes {
+        if b.is_ascii_graphic() && b != b'%' && b != b'$' {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+    }
+    if encoded.is_empty() {
+        encoded.push_str(EMPTY_PATH);
+    }
+
+    encoded
+}
+
+fn decode_bytes(prefix: &[u8], s: &str) -> anyhow::Result<Vec<u8>> {
+    if s == EMPTY_PATH {
+        return Ok(prefix.to_vec());
+    }
+    let mut bytes = prefix.to_vec();
+    let mut chars = s.bytes();
+    while let Some(b) = chars.next() {
+        if b == b'%' {
+            let hex: Vec<u8> = chars.by_ref().take(2).collect();
+            if hex.is_empty() {
+                continue;
+            }
+            let hex = std::str::from_utf8(&hex)?;
+            bytes.push(u8::from_str_radix(hex, 16).with_context(|| format!("invalid bytes {s}"))?);
+        } else {
+            bytes.push(b);
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn encode_mode(mode: Mode) -> String {
+    format!("{:04o}", mode.bits())
+}
+
+fn decode_mode(s: &str) -> anyhow::Result<Mode> {
+    let bits = u32::from_str_radix(s, 8).with_context(|| format!("invalid mode {s}"))?;
+
+    Ok(Mode::from_bits_truncate(bits as _))
+}
+
+fn encode_oflag(flags: OFlag) -> String {
+    let names: Vec<_> = flags.iter_names().map(|(name, _)| name).collect();
+    if names.is_empty() {
+        String::from("O_RDONLY")
+    } else {
+        names.join("|")
+    }
+}
+
+fn decode_oflag(s: &str) -> anyhow::Result<OFlag> {
+    s.split('|').try_fold(OFlag::empty(), |flags, name| {
+        OFlag::from_name(name)
+            .map(|flag| flags | flag)
+            .ok_or_else(|| anyhow!("unknown open flag {name}"))
+    })
+}
+
+fn decode_iflags(s: &str) -> anyhow::Result<c_int> {
+    s.strip_prefix("0x")
+        .and_then(|hex| c_int::from_str_radix(hex, 16).ok())
+        .ok_or_else(|| anyhow!("invalid inode flags {s}"))
+}
+
+fn encode_id(id: Option<u32>) -> String {
+    id.map_or_else(|| String::from("-1"), |id| id.to_string())
+}
+
+fn decode_id(s: &str) -> anyhow::Result<Option<u32>> {
+    if s == "-1" {
+        Ok(None)
+    } else {
+        Ok(Some(s.parse()?))
+    }
+}
+
+fn encode_time(time: &TimeSpec) -> String {
+    if *time == TimeSpec::UTIME_NOW {
+        String::from("UTIME_NOW")
+    } else if *time == TimeSpec::UTIME_OMIT {
+        String::from("UTIME_OMIT")
+    } else {
+        format!("{}.{:09}", time.tv_sec(), time.tv_nsec())
+    }
+}
+
+fn decode_time(s: &str) -> anyhow::Result<TimeSpec> {
+    Ok(match s {
+        "UTIME_NOW" => TimeSpec::UTIME_NOW,
+        "UTIME_OMIT" => TimeSpec::UTIME_OMIT,
+        _ => {
+            let (sec, nsec) = s
+                .split_once('.')
+                .ok_or_else(|| anyhow!("invalid time {s}"))?;
+            TimeSpec::new(sec.parse()?, nsec.parse()?)
+        }
+    })
+}
+
+fn encode_kind(kind: SFlag) -> &'static str {
+    match kind {
+        SFlag::S_IFBLK => "S_IFBLK",
+        SFlag::S_IFCHR => "S_IFCHR",
+        SFlag::S_IFIFO => "S_IFIFO",
+        SFlag::S_IFSOCK => "S_IFSOCK",
+        SFlag::S_IFDIR => "S_IFDIR",
+        _ => "S_IFREG",
+    }
+}
+
+fn decode_kind(s: &str) -> anyhow::Result<SFlag> {
+    Ok(match s {
+        "S_IFBLK" => SFlag::S_IFBLK,
+        "S_IFCHR" => SFlag::S_IFCHR,
+        "S_IFIFO" => SFlag::S_IFIFO,
+        "S_IFSOCK" => SFlag::S_IFSOCK,
+        "S_IFDIR" => SFlag::S_IFDIR,
+        "S_IFREG" => SFlag::S_IFREG,
+        _ => bail!("unknown file type {s}"),
+    })
+}
+
+impl Call {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Call::Open { .. } => "open",
+            Call::Mkdir { .. } => "mkdir",
+            Call::Mkfifo { .. } => "mkfifo",
+            Call::Mknod { .. } => "mknod",
+            Call::Bind { .. } => "bind",
+            Call::Symlink { .. } => "symlink",
+            Call::Chmod { follow: true, .. } => "chmod",
+            Call::Chmod { follow: false, .. } => "lchmod",
+            Call::Chown { follow: true, .. } => "chown",
+            Call::Chown { follow: false, .. } => "lchown",
+            Call::Unlink { .. } => "unlink",
+            Call::Rmdir { .. } => "rmdir",
+            Call::Rename { .. } => "rename",
+            Call::Link { .. } => "link",
+            Call::Truncate { .. } => "truncate",
+            Call::Ftruncate { .. } => "ftruncate",
+            Call::Utimensat { .. } => "utimensat",
+            Call::Setxattr { .. } => "setxattr",
+            Call::SetInodeFlags { .. } => "setflags",
+        }
+    }
+
+    /// Encode the syscall and its arguments.
+    fn encode(&self, base: &Path) -> String {
+        std::iter::once(self.name().to_string())
+            .chain(self.args(|p| encode_path(p, base)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Return the encoded arguments of the syscall, with paths encoded by `path`.
+    fn args<F: Fn(&Path) -> String>(&self, path: F) -> Vec<String> {
+        match self {
+            Call::Open {
+                path: p,
+                flags,
+                mode,
+            } => {
+                vec![path(p), encode_oflag(*flags), encode_mode(*mode)]
+            }
+            Call::Mkdir { path: p, mode } | Call::Mkfifo { path: p, mode } => {
+                vec![path(p), encode_mode(*mode)]
+            }
+            Call::Mknod {
+                path: p,
+                kind,
+                mode,
+                dev,
+            } => vec![
+                path(p),
+                encode_kind(*kind).to_string(),
+                encode_mode(*mode),
+                dev.to_string(),
+            ],
+            Call::Bind { path: p } | Call::Unlink { path: p } | Call::Rmdir { path: p } => {
+                vec![path(p)]
+            }
+            Call::Symlink { target, path: p } => vec![path(target), path(p)],
+            Call::Chmod { path: p, mode, .. } => vec![path(p), encode_mode(*mode)],
+            Call::Chown {
+                path: p, uid, gid, ..
+            } => vec![
+                path(p),
+                encode_id(uid.map(Uid::as_raw)),
+                encode_id(gid.map(Gid::as_raw)),
+            ],
+            Call::Rename { from, to } | Call::Link { from, to } => vec![path(from), path(to)],
+            Call::Truncate { path: p, len } => vec![path(p), len.to_string()],
+            Call::Ftruncate { fd, len } => vec![fd.to_string(), len.to_string()],
+            Call::Utimensat {
+                path: p,
+                atime,
+                mtime,
+                follow,
+            } => vec![
+                path(p),
+                encode_time(atime),
+                encode_time(mtime),
+                String::from(if *follow { "0" } else { "AT_SYMLINK_NOFOLLOW" }),
+            ],
+            Call::Setxattr {
+                path: p,
+                name,
+                value,
+            } => vec![
+                path(p),
+                encode_bytes("", name.as_bytes()),
+                encode_bytes("", value),
+            ],
+            Call::SetInodeFlags { path: p, flags } => vec![path(p), format!("{flags:#x}")],
+        }
+    }
+
+    /// Decode a syscall from its name and arguments.
+    fn decode(name: &str, args: &[&str], base: &Path) -> anyhow::Result<Self> {
+        let arg = |i: usize| {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| anyhow!("missing argument {i} for {name}"))
+        };
+        let path = |i: usize| decode_path(arg(i)?, base);
+
+        Ok(match name {
+            "open" => Call::Open {
+                path: path(0)?,
+                flags: decode_oflag(arg(1)?)?,
+                mode: decode_mode(arg(2)?)?,
+            },
+            "mkdir" => Call::Mkdir {
+                path: path(0)?,
+                mode: decode_mode(arg(1)?)?,
+            },
+            "mkfifo" => Call::Mkfifo {
+                path: path(0)?,
+                mode: decode_mode(arg(1)?)?,
+            },
+            "mknod" => Call::Mknod {
+                path: path(0)?,
+                kind: decode_kind(arg(1)?)?,
+                mode: decode_mode(arg(2)?)?,
+                dev: arg(3)?.parse()?,
+            },
+            "bind" => Call::Bind { path: path(0)? },
+            "symlink" => Call::Symlink {
+                target: path(0)?,
+                path: path(1)?,
+            },
+            "chmod" | "lchmod" => Call::Chmod {
+                path: path(0)?,
+                mode: decode_mode(arg(1)?)?,
+                follow: name == "chmod",
+            },
+            "chown" | "lchown" => Call::Chown {
+                path: path(0)?,
+                uid: decode_id(arg(1)?)?.map(Uid::from_raw),
+                gid: decode_id(arg(2)?)?.map(Gid::from_raw),
+                follow: name == "chown",
+            },
+            "unlink" => Call::Unlink { path: path(0)? },
+            "rmdir" => Call::Rmdir { path: path(0)? },
+            "rename" => Call::Rename {
+                from: path(0)?,
+                to: path(1)?,
+            },
+            "link" => Call::Link {
+                from: path(0)?,
+                to: path(1)?,
+            },
+            "truncate" => Call::Truncate {
+                path: path(0)?,
+                len: arg(1)?.parse()?,
+            },
+            "ftruncate" => Call::Ftruncate {
+                fd: arg(0)?.parse()?,
+                len: arg(1)?.parse()?,
+            },
+            "utimensat" => Call::Utimensat {
+                path: path(0)?,
+                atime: decode_time(arg(1)?)?,
+                mtime: decode_time(arg(2)?)?,
+                follow: match arg(3)? {
+                    "0" => true,
+                    "AT_SYMLINK_NOFOLLOW" => false,
+                    flag => bail!("unknown utimensat flag {flag}"),
+                },
+            },
+            "setxattr" => Call::Setxattr {
+                path: path(0)?,
+                name: String::from_utf8(decode_bytes(&[], arg(1)?)?)?,
+                value: decode_bytes(&[], arg(2)?)?,
+            },
+            "setflags" => Call::SetInodeFlags {
+                path: path(0)?,
+                flags: decode_iflags(arg(1)?)?,
+            },
+            _ => bail!("unknown syscall {name}"),
+        })
+    }
+
+    /// Perform the syscall, looking up the file descriptors in `fds`,
+    /// and return the file descriptor opened by `open`.
+    fn execute(&self, fds: &HashMap<RawFd, OwnedFd>) -> nix::Result<Option<OwnedFd>> {
+        let res = match self {
+            Call::Open { path, flags, mode } => return open(path, *flags, *mode).map(Some),
+            Call::Mkdir { path, mode } => mkdir(path, *mode),
+            Call::Mkfifo { path, mode } => mkfifo(path, *mode),
+            Call::Mknod {
+                path,
+                kind,
+                mode,
+                dev,
+            } => mknod(path, *kind, *mode, *dev),
+            Call::Bind { path } => {
+                let fd = socket(
+                    AddressFamily::Unix,
+                    SockType::Stream,
+                    SockFlag::empty(),
+                    None,
+                )?;
+                bind(fd.as_raw_fd(), &UnixAddr::new(path)?)
+            }
+            Call::Symlink { target, path } => symlink(target, path),
+            Call::Chmod {
+                path,
+                mode,
+                follow: true,
+            } => chmod(path, *mode),
+            Call::Chmod {
+                path,
+                mode,
+                follow: false,
+            } => lchmod(path, *mode),
+            Call::Chown {
+                path,
+                uid,
+                gid,
+                follow: true,
+            } => chown(path, *uid, *gid),
+            Call::Chown {
+                path,
+                uid,
+                gid,
+                follow: false,
+            } => lchown(path, *uid, *gid),
+            Call::Unlink { path } => unlink(path),
+            Call::Rmdir { path } => rmdir(path),
+            Call::Rename { from, to } => rename(from, to),
+            Call::Link { from, to } => link(from, to),
+            Call::Truncate { path, len } => truncate(path, *len),
+            Call::Ftruncate { fd, len } => ftruncate(fds.get(fd).ok_or(Errno::EBADF)?, *len),
+            Call::Utimensat {
+                path,
+                atime,
+                mtime,
+                follow,
+            } => utimensat(
+                path,
+                atime,
+                mtime,
+                if *follow {
+                    UtimensatFlags::FollowSymlink
+                } else {
+                    UtimensatFlags::NoFollowSymlink
+                },
+            ),
+            #[cfg(target_os = "linux")]
+            Call::Setxattr { path, name, value } => setxattr(path, name, value),
+            #[cfg(not(target_os = "linux"))]
+            Call::Setxattr { .. } => Err(Errno::ENOTSUP),
+            #[cfg(target_os = "linux")]
+            Call::SetInodeFlags { path, flags } => set_inode_flags(path, *flags),
+            #[cfg(not(target_os = "linux"))]
+            Call::SetInodeFlags { .. } => Err(Errno::ENOTSUP),
+        };
+
+        res.map(|()| None)
+    }
+}
+
+/// A line of a trace.
+struct Entry {
+    uid: Uid,
+    gid: Gid,
+    groups: Vec<Gid>,
+    umask: Mode,
+    call: Call,
+    /// Result of the syscall, which is the file descriptor for `open` and 0 otherwise.
+    result: Result<i32, Errno>,
+}
+
+impl Entry {
+    fn encode(&self, base: &Path) -> String {
+        let groups: Vec<_> = self.groups.iter().map(Gid::to_string).collect();
+
+        format!(
+            "{}:{}:{} {} {} = {}",
+            self.uid,
+            self.gid,
+            groups.join(","),
+            encode_mode(self.umask),
+            self.call.encode(base),
+            encode_result(&self.result)
+        )
+    }
+
+    fn decode(line: &str, base: &Path) -> anyhow::Result<Self> {
+        let (line, result) = line
+            .rsplit_once(" = ")
+            .ok_or_else(|| anyhow!("missing result"))?;
+        let mut fields = line.split(' ');
+        let mut field = || fields.next().ok_or_else(|| anyhow!("truncated line"));
+
+        let creds = field()?;
+        let mut ids = creds.splitn(3, ':');
+        let mut id = || -> anyhow::Result<u32> {
+            Ok(ids
+                .next()
+                .ok_or_else(|| anyhow!("invalid credentials {creds}"))?
+                .parse()?)
+        };
+        let (uid, gid) = (Uid::from_raw(id()?), Gid::from_raw(id()?));
+        let groups = creds
+            .splitn(3, ':')
+            .nth(2)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|g| !g.is_empty())
+            .map(|g| g.parse().map(Gid::from_raw))
+            .collect::<Result<_, _>>()?;
+        let umask = decode_mode(field()?)?;
+        let name = field()?;
+        let args: Vec<_> = fields.collect();
+
+        Ok(Entry {
+            uid,
+            gid,
+            groups,
+            umask,
+            call: Call::decode(name, &args, base)?,
+            result: decode_result(result)?,
+        })
+    }
+
+    /// Execute the call with the recorded credentials and umask,
+    /// and keep the file descriptor it opened in `fds` under the recorded number.
+    fn execute(&self, fds: &mut HashMap<RawFd, OwnedFd>) -> nix::Result<()> {
+        let original_euid = Uid::effective();
+        let original_egid = Gid::effective();
+        let original_groups = getgroups()?;
+        let switch = self.uid != original_euid || self.gid != original_egid;
+        // The supplementary groups may differ even with the same user and group
+        setgroups(&self.groups)?;
+        if switch {
+            setegid(self.gid)?;
+            seteuid(self.uid)?;
+        }
+        let previous_mask = umask(self.umask);
+
+        let res = self.call.execute(fds);
+
+        umask(previous_mask);
+        if switch {
+            seteuid(original_euid)?;
+            setegid(original_egid)?;
+        }
+        setgroups(&original_groups)?;
+
+        match (res, self.result) {
+            (Ok(Some(fd)), Ok(recorded)) => {
+                fds.insert(recorded, fd);
+                Ok(())
+            }
+            (res, _) => res.map(drop),
+        }
+    }
+}
+
+/// Options of the `replay` command.
+#[derive(Debug, Args)]
+pub struct ReplayOptions {
+    #[arg(help = "Trace recorded with --trace")]
+    trace: PathBuf,
+}
+
+/// Replay a trace in a temporary directory created in `path`, and report the diverging results.
+pub fn replay(options: &ReplayOptions, path: &Path) -> anyhow::Result<()> {
+    let trace = fs::read_to_string(&options.trace)
+        .with_context(|| format!("cannot read {}", options.trace.display()))?;
+    let base_dir = tempdir_in(path)?;
+    // Same mode as the base directory of the tests
+    chmod(base_dir.path(), Mode::from_bits_truncate(0o755))?;
+
+    let mut divergences = 0;
+    let mut fds = HashMap::new();
+    for (i, line) in trace.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let entry = Entry::decode(line, base_dir.path())
+            .with_context(|| format!("invalid trace at line {}", i + 1))?;
+        let result = entry.execute(&mut fds);
+
+        // The replayed file descriptors may differ from the recorded ones
+        if result == entry.result.map(drop) {
+            println!("{line}");
+        } else {
+            divergences += 1;
+            println!(
+                "{} {}",
+                line.red(),
+                format!("(got {})", encode_result(&result.map(|()| 0)))
+                    .red()
+                    .bold()
+            );
+        }
+    }
+
+    println!(
+        "\n{}: {divergences} diverging results",
+        if divergences == 0 {
+            "Summary".green().bold()
+        } else {
+            "Summary".red().bold()
+        }
+    );
+
+    if divergences == 0 {
+        Ok(())
+    } else {
+        Err(anyhow!("The replayed trace diverged"))
+    }
+}
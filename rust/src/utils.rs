@@ -3,58 +3,255 @@
 //! This module provides utility functions for filesystem operations which are not available in the standard library.
 
 use std::{
-    os::fd::{FromRawFd, OwnedFd},
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd},
     path::Path,
 };
 
+use crate::trace::{self, to_path, Call};
+
 use nix::{
     fcntl::{renameat, AtFlags, OFlag},
-    sys::stat::{fchmodat, lstat, FchmodatFlags, Mode},
-    unistd::{fchownat, linkat, symlinkat, Gid, Uid},
+    libc::off_t,
+    sys::{
+        stat::{fchmodat, lstat, FchmodatFlags, Mode, UtimensatFlags},
+        time::TimeSpec,
+    },
+    unistd::{fchownat, linkat, symlinkat, unlinkat, Gid, Uid, UnlinkatFlags},
 };
 
 pub mod dev;
 
 /// Wrapper for `fchmodat(None, path, mode, FchmodatFlags::FollowSymlink)`.
 pub fn chmod<P: ?Sized + nix::NixPath>(path: &P, mode: nix::sys::stat::Mode) -> nix::Result<()> {
-    fchmodat(None, path, mode, FchmodatFlags::FollowSymlink)
+    let res = fchmodat(None, path, mode, FchmodatFlags::FollowSymlink);
+    let call = || Call::Chmod {
+        path: to_path(path),
+        mode,
+        follow: true,
+    };
+    trace::record(call, &res);
+
+    res
 }
 
 /// Wrapper for `fchmodat(None, path, mode, FchmodatFlags::NoFollowSymlink)`.
 pub fn lchmod<P: ?Sized + nix::NixPath>(path: &P, mode: nix::sys::stat::Mode) -> nix::Result<()> {
-    fchmodat(None, path, mode, FchmodatFlags::NoFollowSymlink)
+    let res = fchmodat(None, path, mode, FchmodatFlags::NoFollowSymlink);
+    let call = || Call::Chmod {
+        path: to_path(path),
+        mode,
+        follow: false,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Wrapper for `fchownat(None, path, owner, group, AtFlags::empty())`.
+pub fn chown<P: ?Sized + nix::NixPath>(
+    path: &P,
+    owner: Option<Uid>,
+    group: Option<Gid>,
+) -> nix::Result<()> {
+    let res = fchownat(None, path, owner, group, AtFlags::empty());
+    let call = || Call::Chown {
+        path: to_path(path),
+        uid: owner,
+        gid: group,
+        follow: true,
+    };
+    trace::record(call, &res);
+
+    res
 }
 
-/// Wrapper for `fchownat(None, path, mode, FchownatFlags::NoFollowSymlink)`.
+/// Wrapper for `fchownat(None, path, owner, group, AtFlags::AT_SYMLINK_NOFOLLOW)`.
 pub fn lchown<P: ?Sized + nix::NixPath>(
     path: &P,
     owner: Option<Uid>,
     group: Option<Gid>,
 ) -> nix::Result<()> {
-    fchownat(None, path, owner, group, AtFlags::AT_SYMLINK_NOFOLLOW)
+    let res = fchownat(None, path, owner, group, AtFlags::AT_SYMLINK_NOFOLLOW);
+    let call = || Call::Chown {
+        path: to_path(path),
+        uid: owner,
+        gid: group,
+        follow: false,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Wrapper for `unlinkat(None, path, UnlinkatFlags::NoRemoveDir)`.
+pub fn unlink<P: ?Sized + nix::NixPath>(path: &P) -> nix::Result<()> {
+    let res = unlinkat(None, path, UnlinkatFlags::NoRemoveDir);
+    trace::record(
+        || Call::Unlink {
+            path: to_path(path),
+        },
+        &res,
+    );
+
+    res
 }
 
 /// Wrapper for `rmdir`.
 pub fn rmdir<P: ?Sized + nix::NixPath>(path: &P) -> nix::Result<()> {
-    let res = path.with_nix_path(|cstr| unsafe { nix::libc::rmdir(cstr.as_ptr()) })?;
-    nix::errno::Errno::result(res).map(std::mem::drop)
+    let res = path
+        .with_nix_path(|cstr| unsafe { nix::libc::rmdir(cstr.as_ptr()) })
+        .and_then(|res| nix::errno::Errno::result(res).map(std::mem::drop));
+    trace::record(
+        || Call::Rmdir {
+            path: to_path(path),
+        },
+        &res,
+    );
+
+    res
 }
 
 pub const ALLPERMS: nix::sys::stat::mode_t = 0o7777;
 
 /// Wrapper for `renameat(None, old_path, None, new_path)`.
 pub fn rename<P: ?Sized + nix::NixPath>(old_path: &P, new_path: &P) -> nix::Result<()> {
-    renameat(None, old_path, None, new_path)
+    let res = renameat(None, old_path, None, new_path);
+    let call = || Call::Rename {
+        from: to_path(old_path),
+        to: to_path(new_path),
+    };
+    trace::record(call, &res);
+
+    res
 }
 
 /// Wrapper for `linkat(None, old_path, None, new_path)`.
 pub fn link<P: ?Sized + nix::NixPath>(old_path: &P, new_path: &P) -> nix::Result<()> {
-    linkat(None, old_path, None, new_path, AtFlags::empty())
+    let res = linkat(None, old_path, None, new_path, AtFlags::empty());
+    let call = || Call::Link {
+        from: to_path(old_path),
+        to: to_path(new_path),
+    };
+    trace::record(call, &res);
+
+    res
 }
 
 /// Wrapper for `symlinkat(path1, None, path2)`.
 pub fn symlink<P: ?Sized + nix::NixPath>(path1: &P, path2: &P) -> nix::Result<()> {
-    symlinkat(path1, None, path2)
+    let res = symlinkat(path1, None, path2);
+    let call = || Call::Symlink {
+        target: to_path(path1),
+        path: to_path(path2),
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Wrapper for `truncate`.
+pub fn truncate<P: ?Sized + nix::NixPath>(path: &P, len: off_t) -> nix::Result<()> {
+    let res = nix::unistd::truncate(path, len);
+    let call = || Call::Truncate {
+        path: to_path(path),
+        len,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Wrapper for `ftruncate`.
+pub fn ftruncate<Fd: AsFd>(fd: Fd, len: off_t) -> nix::Result<()> {
+    let fd = fd.as_fd();
+    let res = nix::unistd::ftruncate(fd, len);
+    let call = || Call::Ftruncate {
+        fd: fd.as_raw_fd(),
+        len,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Wrapper for `utimensat(None, path, atime, mtime, flag)`.
+pub fn utimensat<P: ?Sized + nix::NixPath>(
+    path: &P,
+    atime: &TimeSpec,
+    mtime: &TimeSpec,
+    flag: UtimensatFlags,
+) -> nix::Result<()> {
+    let follow = matches!(flag, UtimensatFlags::FollowSymlink);
+    let res = nix::sys::stat::utimensat(None, path, atime, mtime, flag);
+    let call = || Call::Utimensat {
+        path: to_path(path),
+        atime: *atime,
+        mtime: *mtime,
+        follow,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Safe wrapper for `setxattr(path, name, value, size, 0)`.
+#[cfg(target_os = "linux")]
+pub fn setxattr<P: ?Sized + nix::NixPath>(path: &P, name: &str, value: &[u8]) -> nix::Result<()> {
+    use nix::errno::Errno;
+    use std::ffi::CString;
+
+    let c_name = CString::new(name).map_err(|_| Errno::EINVAL)?;
+    let res = path
+        .with_nix_path(|cstr| unsafe {
+            nix::libc::setxattr(
+                cstr.as_ptr(),
+                c_name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+                0,
+            )
+        })
+        .and_then(|res| Errno::result(res).map(drop));
+    let call = || Call::Setxattr {
+        path: to_path(path),
+        name: name.to_string(),
+        value: value.to_vec(),
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+/// Return the value of the extended attribute `name` of `path`, without following symlinks.
+#[cfg(target_os = "linux")]
+pub fn lgetxattr<P: ?Sized + nix::NixPath>(path: &P, name: &str) -> nix::Result<Vec<u8>> {
+    use nix::errno::Errno;
+    use std::ffi::CString;
+
+    let c_name = CString::new(name).map_err(|_| Errno::EINVAL)?;
+    let lgetxattr = |value: &mut [u8]| {
+        let res = path.with_nix_path(|cstr| {
+            // SAFETY: The path and name are NUL-terminated strings, and at most `value.len()`
+            // bytes are written to `value`, all valid for the duration of the call.
+            unsafe {
+                nix::libc::lgetxattr(
+                    cstr.as_ptr(),
+                    c_name.as_ptr(),
+                    value.as_mut_ptr().cast(),
+                    value.len(),
+                )
+            }
+        })?;
+
+        Errno::result(res).map(|len| len as usize)
+    };
+
+    // An empty buffer returns the size of the value
+    let mut value = vec![0u8; lgetxattr(&mut [])?];
+    let len = lgetxattr(&mut value)?;
+    value.truncate(len);
+
+    Ok(value)
 }
 
 /// Get mountpoint.
@@ -93,9 +290,140 @@ pub fn lchflags<P: ?Sized + nix::NixPath>(
     Errno::result(res).map(drop)
 }
 
+/// Safe wrapper for `statx(AT_FDCWD, path, flags, mask)`.
+#[cfg(statx)]
+pub fn statx<P: ?Sized + nix::NixPath>(
+    path: &P,
+    flags: nix::libc::c_int,
+    mask: nix::libc::c_uint,
+) -> nix::Result<nix::libc::statx> {
+    use nix::errno::Errno;
+    use std::mem::MaybeUninit;
+
+    let mut buf = MaybeUninit::<nix::libc::statx>::uninit();
+    let res = path.with_nix_path(|cstr| unsafe {
+        nix::libc::statx(
+            nix::libc::AT_FDCWD,
+            cstr.as_ptr(),
+            flags,
+            mask,
+            buf.as_mut_ptr(),
+        )
+    })?;
+
+    // SAFETY: statx fills the buffer when it succeeds.
+    Errno::result(res).map(|_| unsafe { buf.assume_init() })
+}
+
+/// Linux inode flags (see `ioctl_iflags(2)`).
+#[cfg(target_os = "linux")]
+pub mod iflags {
+    pub const FS_IMMUTABLE_FL: nix::libc::c_int = 0x00000010;
+    pub const FS_APPEND_FL: nix::libc::c_int = 0x00000020;
+    pub const FS_NODUMP_FL: nix::libc::c_int = 0x00000040;
+}
+
+/// Open `path` to perform an inode flags ioctl on it.
+/// It is not recorded in the trace, where the ioctl itself is.
+#[cfg(target_os = "linux")]
+fn open_for_iflags<P: ?Sized + nix::NixPath>(path: &P) -> nix::Result<OwnedFd> {
+    // SAFETY: The file descriptor was initialized only by open and isn't used anywhere else,
+    // leaving the ownership to the caller.
+    nix::fcntl::open(path, OFlag::O_RDONLY | OFlag::O_NONBLOCK, Mode::empty())
+        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Get the inode flags of a file with the `FS_IOC_GETFLAGS` ioctl.
+#[cfg(target_os = "linux")]
+pub fn get_inode_flags<P: ?Sized + nix::NixPath>(path: &P) -> nix::Result<nix::libc::c_int> {
+    use nix::errno::Errno;
+
+    let fd = open_for_iflags(path)?;
+    let mut flags: nix::libc::c_int = 0;
+    // SAFETY: `fd` is open and FS_IOC_GETFLAGS writes an int to `flags`.
+    let res = unsafe { nix::libc::ioctl(fd.as_raw_fd(), nix::libc::FS_IOC_GETFLAGS, &mut flags) };
+
+    Errno::result(res).map(|_| flags)
+}
+
+/// Set the inode flags of a file with the `FS_IOC_SETFLAGS` ioctl.
+#[cfg(target_os = "linux")]
+pub fn set_inode_flags<P: ?Sized + nix::NixPath>(
+    path: &P,
+    flags: nix::libc::c_int,
+) -> nix::Result<()> {
+    use nix::errno::Errno;
+
+    let res = open_for_iflags(path).and_then(|fd| {
+        // SAFETY: `fd` is open and FS_IOC_SETFLAGS reads an int from `flags`.
+        let res = unsafe { nix::libc::ioctl(fd.as_raw_fd(), nix::libc::FS_IOC_SETFLAGS, &flags) };
+        Errno::result(res).map(drop)
+    });
+    let call = || Call::SetInodeFlags {
+        path: to_path(path),
+        flags,
+    };
+    trace::record(call, &res);
+
+    res
+}
+
+#[cfg(target_os = "linux")]
+mod clone_ioctl {
+    nix::ioctl_write_int!(ficlone, 0x94, 9);
+    nix::ioctl_write_ptr!(ficlonerange, 0x94, 13, nix::libc::file_clone_range);
+}
+
+/// Share the whole content of `src_fd` with `dest_fd` with the `FICLONE` ioctl (see `ioctl_ficlone(2)`).
+#[cfg(target_os = "linux")]
+pub fn ficlone<Fd1: std::os::fd::AsFd, Fd2: std::os::fd::AsFd>(
+    dest_fd: Fd1,
+    src_fd: Fd2,
+) -> nix::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    unsafe {
+        clone_ioctl::ficlone(
+            dest_fd.as_fd().as_raw_fd(),
+            src_fd.as_fd().as_raw_fd() as nix::sys::ioctl::ioctl_param_type,
+        )
+    }
+    .map(drop)
+}
+
+/// Share `src_length` bytes at `src_offset` of `src_fd` with `dest_fd` at `dest_offset`
+/// with the `FICLONERANGE` ioctl (see `ioctl_ficlonerange(2)`).
+#[cfg(target_os = "linux")]
+pub fn ficlonerange<Fd1: std::os::fd::AsFd, Fd2: std::os::fd::AsFd>(
+    dest_fd: Fd1,
+    dest_offset: u64,
+    src_fd: Fd2,
+    src_offset: u64,
+    src_length: u64,
+) -> nix::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let range = nix::libc::file_clone_range {
+        src_fd: src_fd.as_fd().as_raw_fd().into(),
+        src_offset,
+        src_length,
+        dest_offset,
+    };
+
+    unsafe { clone_ioctl::ficlonerange(dest_fd.as_fd().as_raw_fd(), &range) }.map(drop)
+}
+
 /// Wrapper for open which returns `Ownedfd` instead of `RawFd`.
 pub fn open<P: ?Sized + nix::NixPath>(path: &P, oflag: OFlag, mode: Mode) -> nix::Result<OwnedFd> {
     // SAFETY: The file descriptor was initialized only by open and isn't used anywhere else,
     // leaving the ownership to the caller.
-    nix::fcntl::open(path, oflag, mode).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) })
+    let res = nix::fcntl::open(path, oflag, mode).map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+    let call = || Call::Open {
+        path: to_path(path),
+        flags: oflag,
+        mode,
+    };
+    trace::record_fd(call, &res);
+
+    res
 }